@@ -0,0 +1,74 @@
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+
+/// Keeps a running total of the GPU memory allocated through the helpers of
+/// the crate: [crate::mesh::GpuMesh], [crate::texture::Texture] and
+/// [crate::upload_buffer::UploadBuffer]. wgpu doesn't report how much memory
+/// is in use, so the sizes are worked out from the descriptors, which is
+/// close enough to spot memory growing.
+#[derive(Clone, Default)]
+pub struct AllocationTracker {
+    bytes: Arc<AtomicU64>,
+}
+
+impl AllocationTracker {
+    /// Add `bytes` to the total, until the returned [Allocation] is dropped.
+    pub fn track(&self, bytes: u64) -> Allocation {
+        self.bytes.fetch_add(bytes, Ordering::Relaxed);
+        Allocation {
+            bytes,
+            total: Arc::clone(&self.bytes),
+        }
+    }
+
+    pub fn track_buffer(&self, buffer: &wgpu::Buffer) -> Allocation {
+        self.track(buffer.size())
+    }
+
+    pub fn track_texture(&self, texture: &wgpu::Texture) -> Allocation {
+        self.track(texture_size(texture))
+    }
+
+    /// The bytes held by the allocations that are still alive.
+    pub fn allocated_bytes(&self) -> u64 {
+        self.bytes.load(Ordering::Relaxed)
+    }
+}
+
+/// Counts towards the total of an [AllocationTracker] until it is dropped.
+/// Kept next to the buffer or texture it stands for.
+pub struct Allocation {
+    bytes: u64,
+    total: Arc<AtomicU64>,
+}
+
+impl Drop for Allocation {
+    fn drop(&mut self) {
+        self.total.fetch_sub(self.bytes, Ordering::Relaxed);
+    }
+}
+
+/// The size of all the mip levels and layers of `texture`.
+fn texture_size(texture: &wgpu::Texture) -> u64 {
+    let format = texture.format();
+    let (block_width, block_height) = format.block_dimensions();
+    // Depth formats without a fixed layout don't have a copy size, so assume
+    // they take 4 bytes, which is what they are stored in in practice.
+    let block_size = format
+        .block_copy_size(Some(wgpu::TextureAspect::All))
+        .or_else(|| format.block_copy_size(Some(wgpu::TextureAspect::DepthOnly)))
+        .unwrap_or(4) as u64;
+
+    let mut total = 0;
+    for mip_level in 0..texture.mip_level_count() {
+        let size = texture
+            .size()
+            .mip_level_size(mip_level, texture.dimension());
+        let blocks_wide = size.width.div_ceil(block_width) as u64;
+        let blocks_high = size.height.div_ceil(block_height) as u64;
+        total += blocks_wide * blocks_high * size.depth_or_array_layers as u64 * block_size;
+    }
+    total * texture.sample_count() as u64
+}
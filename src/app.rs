@@ -1,267 +1,926 @@
-use std::{borrow::Cow, sync::Arc};
+use std::{cell::RefCell, rc::Rc, sync::Arc};
 
-use cgmath::Angle;
+use cgmath::{Angle, EuclideanSpace, InnerSpace, MetricSpace, SquareMatrix};
 use winit::keyboard::KeyCode;
 
 use crate::{
+    blit::Blit,
     camera::Camera,
+    debug_dump,
+    decals::Decals,
+    deferred_renderer::{DebugView, DeferredRenderer},
+    dof::DepthOfField,
+    frame_stats::FrameStats,
+    gbuffer::{self, GBuffer, GBufferClearValues},
     gizmos::Gizmos,
-    lights::{Lights, PointLight},
+    lights::{DirectionalLight, Lights, PointLight, ShadowFilter},
+    matcap::Matcap,
     material::GpuMaterial,
     mesh::{GpuMesh, Mesh, Vertex},
-    mesh_render_pipeline::MeshRenderPipeline,
-    texture::{create_depth_texture, create_fullscreen_texture, Texture},
-    ui, Renderer,
+    mesh_render_pipeline::{MeshRenderPipeline, Shading},
+    motion_blur::MotionBlur,
+    particles::Particles,
+    pass_builder::PassBuilder,
+    passes::{Pass, PassList},
+    planar_reflection::PlanarReflection,
+    post_effects::{PostEffectId, PostEffects},
+    scenes::{Scene, SceneDescription, SceneMaterial, SceneObject},
+    shadows::ShadowAtlas,
+    texture::{create_depth_sampler, create_depth_texture, create_fullscreen_texture, Texture},
+    transparent_render_pipeline::TransparentRenderPipeline,
+    ui::{self, Widget},
+    wireframe::{WireframeMesh, WireframeOverlay},
+    Renderer,
 };
 
-enum RenderSource {
+/// What the app shows: the lit scene, or one of the G-buffer targets.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RenderSource {
     Final,
     Albedo,
     Position,
     Normal,
+    Depth,
+    /// The lit scene with the edges of the triangles drawn over it.
+    ShadedWireframe,
+    /// Where geometry was drawn, as a black and white mask.
+    Coverage,
+    /// The G-buffer normals shaded with a matcap, without any lights.
+    Matcap,
+}
+
+/// A debug material drawn in place of the real materials in the G-buffer
+/// pass, to tell apart shading issues caused by textures from those caused by
+/// geometry.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MaterialOverride {
+    /// White albedo and no normal map.
+    FlatWhite,
+    /// A checkerboard albedo and no normal map, to show the texture
+    /// coordinates.
+    Checkerboard,
+    /// White albedo with the real normal map.
+    NormalOnly,
+}
+
+impl MaterialOverride {
+    /// The override after this one, or `None` after the last one.
+    fn next(self) -> Option<Self> {
+        match self {
+            MaterialOverride::FlatWhite => Some(MaterialOverride::Checkerboard),
+            MaterialOverride::Checkerboard => Some(MaterialOverride::NormalOnly),
+            MaterialOverride::NormalOnly => None,
+        }
+    }
+}
+
+/// A view of the scene from the orbit camera, to go back to with
+/// [App::go_to_camera].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CameraPreset {
+    pub yaw: cgmath::Deg<f32>,
+    pub pitch: cgmath::Deg<f32>,
+    pub distance: f32,
+}
+
+impl CameraPreset {
+    /// The view `t` (0..1) of the way from `self` to `other`.
+    fn lerp(self, other: Self, t: f32) -> Self {
+        // Turn the short way around.
+        let yaw_delta = (other.yaw.0 - self.yaw.0 + 180.0).rem_euclid(360.0) - 180.0;
+        Self {
+            yaw: cgmath::Deg(self.yaw.0 + yaw_delta * t),
+            pitch: cgmath::Deg(self.pitch.0 + (other.pitch.0 - self.pitch.0) * t),
+            distance: self.distance + (other.distance - self.distance) * t,
+        }
+    }
+}
+
+/// The camera moving from one view to another, see [App::go_to_camera].
+struct CameraTransition {
+    from: CameraPreset,
+    to: CameraPreset,
+    elapsed: std::time::Duration,
+    duration: std::time::Duration,
+}
+
+/// What the mouse controls from a mouse down until the mouse up, wherever the
+/// mouse moves in between.
+#[derive(Clone, Copy, Debug)]
+enum DragTarget {
+    Slider(ui::SliderId),
+    /// Orbit the camera. Holds the mouse position of the last move.
+    Rotate((f32, f32)),
+}
+
+/// The meshes and material of an object in a [LoadedScene].
+struct ObjectAssets {
+    /// The mesh at each level of detail, from most to least detailed.
+    lods: Vec<GpuMesh>,
+    /// The triangles of each of the `lods`, for [RenderSource::ShadedWireframe].
+    wireframes: Vec<WireframeMesh>,
+    material: GpuMaterial,
+    /// Used to sort transparent objects back to front.
+    center: cgmath::Point3<f32>,
+    /// The world space bounding box, as its minimum and maximum corners.
+    bounds: (cgmath::Point3<f32>, cgmath::Point3<f32>),
+}
+
+/// An object as one window draws it.
+struct RenderObject {
+    assets: Rc<ObjectAssets>,
+    /// The level of detail drawn this frame, picked by [select_lod] for the
+    /// camera of the window.
+    lod: usize,
+    /// The material bind group for the override it was created for.
+    override_bind_group: Option<(MaterialOverride, wgpu::BindGroup)>,
+}
+
+impl RenderObject {
+    fn new(assets: &Rc<ObjectAssets>) -> Self {
+        Self {
+            assets: Rc::clone(assets),
+            lod: 0,
+            override_bind_group: None,
+        }
+    }
+
+    /// The mesh for the current level of detail.
+    fn mesh(&self) -> &GpuMesh {
+        &self.assets.lods[self.lod]
+    }
+
+    /// The wireframe of [RenderObject::mesh].
+    fn wireframe(&self) -> &WireframeMesh {
+        &self.assets.wireframes[self.lod]
+    }
+
+    /// The material bind group to draw with, taking `material_override` into
+    /// account.
+    fn material_bind_group(&self, material_override: Option<MaterialOverride>) -> &wgpu::BindGroup {
+        match (material_override, &self.override_bind_group) {
+            (Some(material_override), Some((created_for, bind_group)))
+                if material_override == *created_for =>
+            {
+                bind_group
+            }
+            _ => &self.assets.material.bind_group,
+        }
+    }
+}
+
+/// Textures swapped into materials by a [MaterialOverride].
+struct DebugTextures {
+    white: Texture,
+    checkerboard: Texture,
+    flat_normal: Texture,
+}
+
+impl DebugTextures {
+    fn new(renderer: &Renderer) -> Self {
+        Self {
+            white: Texture::from_color(
+                renderer,
+                [255, 255, 255, 255],
+                wgpu::TextureFormat::Rgba8UnormSrgb,
+            ),
+            checkerboard: checkerboard_texture(renderer),
+            flat_normal: Texture::from_color(
+                renderer,
+                [128, 128, 255, 255],
+                wgpu::TextureFormat::Rgba8Unorm,
+            ),
+        }
+    }
+
+    /// The albedo and normal textures to use for `material_override`. `None`
+    /// keeps the texture of the material.
+    fn textures(
+        &self,
+        material_override: MaterialOverride,
+    ) -> (Option<&Texture>, Option<&Texture>) {
+        match material_override {
+            MaterialOverride::FlatWhite => (Some(&self.white), Some(&self.flat_normal)),
+            MaterialOverride::Checkerboard => (Some(&self.checkerboard), Some(&self.flat_normal)),
+            MaterialOverride::NormalOnly => (Some(&self.white), None),
+        }
+    }
+}
+
+/// Choices made when the app is created, that can't change while it runs.
+#[derive(Clone, Copy, Debug)]
+pub struct AppOptions {
+    /// Keep world positions in a G-buffer target. Without it positions are
+    /// reconstructed from depth, which saves an `Rgba16Float` target.
+    pub store_position: bool,
+    /// Advance animations by this much every frame, instead of by the
+    /// wall-clock time between frames, so a given frame always renders the
+    /// same. The frame rate readout still uses the wall-clock.
+    pub fixed_time_step: Option<std::time::Duration>,
+    /// The shortest time a frame is taken to last. Two frames can start at
+    /// the same instant, like the first frame after startup, and dividing by
+    /// their zero length would put infinities into the animations.
+    pub min_frame_delta: std::time::Duration,
+    /// Wait for the GPU before starting a frame when this many frames (1 to 3)
+    /// are still queued, so the CPU can't get ahead of the GPU and add input
+    /// latency. `None` never waits.
+    pub max_frames_in_flight: Option<u32>,
+    /// The fill behind the column of sliders, e.g. [ui::Panel::SOLID] to stop
+    /// the scene from showing through. `None` draws no background. The
+    /// sliders are clipped to the column either way.
+    pub controls_background: Option<epaint::Color32>,
+}
+
+impl Default for AppOptions {
+    fn default() -> Self {
+        Self {
+            store_position: true,
+            fixed_time_step: None,
+            min_frame_delta: std::time::Duration::from_micros(1),
+            max_frames_in_flight: None,
+            controls_background: Some(ui::Panel::TRANSLUCENT),
+        }
+    }
 }
 
 pub struct App {
     depth_texture: Texture,
+    /// Reads [App::depth_texture] without comparing, see [create_depth_sampler].
+    depth_sampler: wgpu::Sampler,
 
     mesh_render_pipeline: MeshRenderPipeline,
-
-    mesh: GpuMesh,
-    material: crate::material::GpuMaterial,
+    transparent_render_pipeline: TransparentRenderPipeline,
+
+    /// The built-in scene of all the windows, cycled with C.
+    shared_scene: SharedScene,
+    /// The scene this window shows. It catches up with `shared_scene` when
+    /// another window loads a scene.
+    loaded_scene: Rc<LoadedScene>,
+    /// Loaded at the start of the next update.
+    next_scene: Option<Scene>,
+    objects: Vec<RenderObject>,
+    /// See [LoadedScene::ground].
+    ground: RenderObject,
+    reflection: PlanarReflection,
+    decals: Decals,
+    wireframe: WireframeOverlay,
+    matcap: Matcap,
 
     albedo_g_texture: Texture,
-    position_g_texture: Texture,
+    /// `None` when positions are reconstructed from depth.
+    position_g_texture: Option<Texture>,
     normal_g_texture: Texture,
+    specular_g_texture: Texture,
 
-    fullscreen_render_pipeline: wgpu::RenderPipeline,
-    debug_render_pipeline: wgpu::RenderPipeline,
-    fullscreen_bind_group_layout: wgpu::BindGroupLayout,
+    /// The lit scene at the render resolution, upscaled to the surface by
+    /// `blit`.
+    scene_texture: Texture,
+    gbuffer_clear_values: GBufferClearValues,
+    /// Scale of the render resolution relative to the surface size.
+    render_scale: f32,
+    blit: Blit,
+
+    deferred_renderer: DeferredRenderer,
 
     camera: Camera,
 
     lights: Lights,
+    shadow_atlas: ShadowAtlas,
 
-    rotating: Option<(f32, f32)>,
+    drag_target: Option<DragTarget>,
     last_mouse_position: (f32, f32),
+    /// Time and position of the last left click, to detect double-clicks.
+    last_click: Option<(std::time::Instant, (f32, f32))>,
     yaw: cgmath::Deg<f32>,
     pitch: cgmath::Deg<f32>,
     distance: f32,
+    /// How close the camera can zoom in to the target.
+    min_distance: f32,
+    /// How far the camera can zoom out from the target.
+    max_distance: f32,
+    /// Named views to move the camera to, in the order they were added.
+    camera_presets: Vec<(String, CameraPreset)>,
+    /// The preset the camera last went to with F5.
+    last_camera_preset: Option<usize>,
+    camera_transition: Option<CameraTransition>,
+    /// Orbit the camera around the target on its own, to show off a model.
+    /// Rotating with the mouse is ignored meanwhile. The speed is set with a
+    /// slider. Toggled with T.
+    turntable: bool,
+    /// The direction that is up on screen. The camera orbits around it.
+    up_vector: cgmath::Vector3<f32>,
+    /// Degrees the camera rotates per logical pixel the mouse moves.
+    rotation_sensitivity: f32,
+    /// Moving the mouse up tilts the camera down instead of up.
+    invert_y: bool,
+    /// Physical pixels per logical pixel of the window.
+    scale_factor: f32,
 
     render_source: RenderSource,
 
+    /// Draw opaque objects with a debug material instead of their own.
+    material_override: Option<MaterialOverride>,
+    /// How the normals of opaque objects are found, toggled with Shift+M.
+    shading: Shading,
+    /// The rays cast per vertex to bake ambient occlusion when a scene is
+    /// loaded, 0 to leave it out.
+    ao_samples: u32,
+    debug_textures: DebugTextures,
+
     light_angle: Option<cgmath::Deg<f32>>,
+    /// Keep an extra light at the camera, to light whatever it looks at.
+    headlight: bool,
+    /// Spread a grid of extra lights over the scene, to see how the renderer
+    /// copes with many lights. Its size is set with a slider.
+    show_light_grid: bool,
+    /// The size of the grid of lights that is in the scene, and the lights of
+    /// the scene without the grid.
+    light_grid: Option<(u32, Vec<PointLight>)>,
+
+    /// The hour of the day (0..24) when the day/night cycle is running.
+    time_of_day: Option<f32>,
+
+    /// Save the G-buffer targets to disk after the next frame is rendered.
+    dump_gbuffer: bool,
+    /// List the camera and light positions under the frame rate.
+    show_debug_overlay: bool,
+    /// Capture a screenshot at this supersampling scale after the next frame
+    /// is rendered.
+    screenshot_scale: Option<u32>,
+    /// Where the camera was for the last rendered frame.
+    camera_position: cgmath::Point3<f32>,
 
     gizmos: Gizmos,
 
+    particles: Particles,
+    /// Sparks are emitted at a fixed rate, so the fraction of a spark left
+    /// over from each frame is carried to the next.
+    spark_accumulator: f32,
+
+    depth_of_field: DepthOfField,
+    /// Focus the depth of field on whatever is under the cursor after the
+    /// next frame is rendered.
+    focus_at_cursor: bool,
+    motion_blur: MotionBlur,
+    post_effects: PostEffects,
+
+    /// The passes rendered each frame, in order.
+    passes: PassList,
+
     last_frame_time: std::time::Instant,
+    fixed_time_step: Option<std::time::Duration>,
+    min_frame_delta: std::time::Duration,
+    /// Stop the animations while still rendering, so the camera can move
+    /// around a frozen scene.
+    paused: bool,
+    /// Advance the animations by one frame while paused.
+    step_once: bool,
+    /// The number of frames rendered so far.
+    frame_count: u64,
+    max_frames_in_flight: Option<u32>,
+    /// Submissions of the frames the GPU might still be working on, oldest
+    /// first.
+    frames_in_flight: std::collections::VecDeque<wgpu::SubmissionIndex>,
+    frame_stats: FrameStats,
+    /// Set once a frame has been rendered into the current scene texture.
+    has_rendered: bool,
 
     ui: ui::UserInterface,
 
+    /// The background of the column of sliders.
+    controls_panel: ui::Panel,
     sliders: slotmap::SlotMap<ui::SliderId, ui::Slider>,
-    active_slider: Option<ui::SliderId>,
+    /// The slider that receives keyboard input, cycled with Tab.
+    focused_slider: Option<ui::SliderId>,
+    modifiers: winit::keyboard::ModifiersState,
     light_x_id: ui::SliderId,
     light_y_id: ui::SliderId,
     light_z_id: ui::SliderId,
     intensity_id: ui::SliderId,
     shininess_id: ui::SliderId,
-    ambient_id: ui::SliderId,
+    ambient_r_id: ui::SliderId,
+    ambient_g_id: ui::SliderId,
+    ambient_b_id: ui::SliderId,
+    render_scale_id: ui::SliderId,
+    day_speed_id: ui::SliderId,
+    turntable_speed_id: ui::SliderId,
+    shadow_softness_id: ui::SliderId,
+    shadow_bias_id: ui::SliderId,
+    shadow_slope_bias_id: ui::SliderId,
+    rotation_sensitivity_id: ui::SliderId,
+    /// Only there while the light grid is shown.
+    light_grid_id: Option<ui::SliderId>,
+}
+
+/// The sun light and sky color for an hour of the day (0..24). The sun rises in
+/// the east at 6 and sets in the west at 18.
+fn sun_at(time_of_day: f32) -> (DirectionalLight, [f32; 3]) {
+    fn lerp(a: [f32; 3], b: [f32; 3], t: f32) -> [f32; 3] {
+        std::array::from_fn(|i| a[i] + (b[i] - a[i]) * t)
+    }
+
+    let angle = cgmath::Deg((time_of_day - 6.0) / 24.0 * 360.0);
+    // A slightly tilted path across the sky, so the sun is never straight up.
+    let sun_position = cgmath::vec3(angle.cos(), angle.sin(), 0.3);
+    let elevation = angle.sin();
+
+    // Warm near the horizon, white at noon.
+    let daylight = elevation.clamp(0.0, 1.0);
+    let color = lerp([1.0, 0.5, 0.2], [1.0, 1.0, 0.95], daylight.sqrt());
+    let intensity = (elevation * 4.0).clamp(0.0, 1.0);
+
+    const NIGHT_SKY: [f32; 3] = [0.005, 0.005, 0.02];
+    const DAY_SKY: [f32; 3] = [0.25, 0.45, 0.8];
+    const SUNSET_SKY: [f32; 3] = [0.6, 0.25, 0.1];
+    let sky_color = if elevation < 0.0 {
+        lerp(SUNSET_SKY, NIGHT_SKY, (-elevation * 4.0).clamp(0.0, 1.0))
+    } else {
+        lerp(SUNSET_SKY, DAY_SKY, (elevation * 4.0).clamp(0.0, 1.0))
+    };
+
+    let sun = DirectionalLight {
+        // There is nothing to cast a shadow for once the sun is down.
+        casts_shadows: intensity > 0.0,
+        ..DirectionalLight::new((-sun_position).into(), intensity, color)
+    };
+
+    (sun, sky_color)
+}
+
+/// `size` by `size` point lights in different colors, spread out above the box
+/// from `min` to `max`.
+fn light_grid(
+    size: u32,
+    (min, max): (cgmath::Point3<f32>, cgmath::Point3<f32>),
+) -> Vec<PointLight> {
+    (0..size * size)
+        .map(|index| {
+            // The middle of a cell of the grid.
+            let x = min.x + (max.x - min.x) * ((index % size) as f32 + 0.5) / size as f32;
+            let z = min.z + (max.z - min.z) * ((index / size) as f32 + 0.5) / size as f32;
+
+            // Stepping the hue by the golden ratio keeps neighbouring lights
+            // apart in color without needing random numbers.
+            let hue = (index as f32 * 0.618_034).fract() * 6.0;
+            let color = [0.0, 4.0, 2.0]
+                .map(|offset: f32| (((hue + offset) % 6.0 - 3.0).abs() - 1.0).clamp(0.0, 1.0));
+
+            PointLight::default()
+                .with_position([x, max.y + 1.0, z])
+                .with_color(color)
+        })
+        .collect()
+}
+
+/// A white ring with a dot in the middle on a transparent background.
+fn ring_texture(renderer: &Renderer) -> Texture {
+    const SIZE: u32 = 64;
+
+    let pixels = (0..SIZE * SIZE)
+        .flat_map(|i| {
+            let x = (i % SIZE) as f32 / SIZE as f32 - 0.5;
+            let y = (i / SIZE) as f32 / SIZE as f32 - 0.5;
+            let distance = (x * x + y * y).sqrt();
+            let alpha = if distance < 0.1 || (0.3..0.45).contains(&distance) {
+                255
+            } else {
+                0
+            };
+            [255, 255, 255, alpha]
+        })
+        .collect::<Vec<u8>>();
+
+    Texture::from_rgba8(
+        renderer,
+        SIZE,
+        SIZE,
+        &pixels,
+        wgpu::TextureFormat::Rgba8UnormSrgb,
+    )
+}
+
+/// A black and white checkerboard with 8 squares along each side.
+fn checkerboard_texture(renderer: &Renderer) -> Texture {
+    const SIZE: u32 = 64;
+    const SQUARE: u32 = SIZE / 8;
+
+    let pixels = (0..SIZE * SIZE)
+        .flat_map(|i| {
+            let x = (i % SIZE) / SQUARE;
+            let y = (i / SIZE) / SQUARE;
+            if (x + y).is_multiple_of(2) {
+                [255, 255, 255, 255]
+            } else {
+                [32, 32, 32, 255]
+            }
+        })
+        .collect::<Vec<u8>>();
+
+    Texture::from_rgba8(
+        renderer,
+        SIZE,
+        SIZE,
+        &pixels,
+        wgpu::TextureFormat::Rgba8UnormSrgb,
+    )
+}
+
+/// How much of the screen height an object has to cover for each level of
+/// detail after the first. An object smaller than an entry drops to the next
+/// level.
+const LOD_SCREEN_SIZES: [f32; 2] = [0.3, 0.1];
+
+/// How many sparks fly off the first point light each second.
+const SPARKS_PER_SECOND: f32 = 60.0;
+
+/// The rays cast per vertex to bake the ambient occlusion of the objects of a
+/// scene, until changed with [App::set_ao_samples].
+const AO_SAMPLES: u32 = 16;
+
+/// How long the camera takes to move to a camera preset with F5.
+const CAMERA_TRANSITION_DURATION: std::time::Duration = std::time::Duration::from_millis(750);
+
+/// Upload `mesh` along with a simplified copy for each of `ratios`, to draw
+/// at each level of detail, and the wireframe of each of them.
+fn upload_lods(
+    renderer: &Renderer,
+    mesh: &Mesh<Vertex>,
+    ratios: &[f32],
+) -> (Vec<GpuMesh>, Vec<WireframeMesh>) {
+    let simplified = ratios.iter().map(|&ratio| mesh.simplify(ratio));
+    std::iter::once(mesh.clone())
+        .chain(simplified)
+        .map(|mesh| {
+            (
+                mesh.upload_to_gpu(renderer),
+                WireframeMesh::new(renderer, &mesh),
+            )
+        })
+        .unzip()
+}
+
+/// Upload the objects of a scene description, after baking their ambient
+/// occlusion with `ao_samples` rays per vertex.
+fn scene_objects(
+    renderer: &Renderer,
+    objects: Vec<SceneObject>,
+    ao_samples: u32,
+) -> Vec<Rc<ObjectAssets>> {
+    objects
+        .into_iter()
+        .map(
+            |SceneObject {
+                 mut mesh,
+                 material,
+                 lod_ratios,
+             }| {
+                mesh.bake_ao(ao_samples);
+
+                let flat_normal = || {
+                    Texture::from_color(
+                        renderer,
+                        [128, 128, 255, 255],
+                        wgpu::TextureFormat::Rgba8Unorm,
+                    )
+                };
+                let material = match material {
+                    SceneMaterial::Metal => GpuMaterial::new(
+                        renderer,
+                        include_bytes!("../res/metal/albedo.png"),
+                        include_bytes!("../res/metal/normal.png"),
+                    )
+                    .with_alpha_cutoff(renderer, 0.5),
+                    SceneMaterial::Glass => GpuMaterial::from_textures(
+                        renderer,
+                        Texture::from_color(
+                            renderer,
+                            [180, 220, 255, 255],
+                            wgpu::TextureFormat::Rgba8UnormSrgb,
+                        ),
+                        flat_normal(),
+                    )
+                    .with_transparency(renderer, 0.35),
+                    SceneMaterial::Matte([r, g, b]) => GpuMaterial::from_textures(
+                        renderer,
+                        Texture::from_color(
+                            renderer,
+                            [r, g, b, 255],
+                            wgpu::TextureFormat::Rgba8UnormSrgb,
+                        ),
+                        flat_normal(),
+                    ),
+                };
+
+                let (lods, wireframes) = upload_lods(renderer, &mesh, &lod_ratios);
+                Rc::new(ObjectAssets {
+                    center: mesh.center(),
+                    bounds: mesh.bounds(),
+                    lods,
+                    wireframes,
+                    material,
+                })
+            },
+        )
+        .collect()
+}
+
+/// The lowest point of all the objects, where the ground goes.
+fn lowest_point(objects: &[Rc<ObjectAssets>]) -> f32 {
+    objects
+        .iter()
+        .map(|object| object.bounds.0.y)
+        .fold(f32::INFINITY, f32::min)
+}
+
+/// A gray plane at `height`, wide enough to reach past the built-in scenes.
+fn ground_object(renderer: &Renderer, height: f32) -> ObjectAssets {
+    const EXTENT: f32 = 10.0;
+    let mut mesh = Mesh {
+        vertices: vec![
+            Vertex::raw(-EXTENT, height, -EXTENT, 0.0, 1.0, 0.0, 0.0, 0.0),
+            Vertex::raw(-EXTENT, height, EXTENT, 0.0, 1.0, 0.0, 0.0, 1.0),
+            Vertex::raw(EXTENT, height, EXTENT, 0.0, 1.0, 0.0, 1.0, 1.0),
+            Vertex::raw(EXTENT, height, -EXTENT, 0.0, 1.0, 0.0, 1.0, 0.0),
+        ],
+        indices: vec![0, 1, 2, 0, 2, 3],
+        ..Default::default()
+    };
+    mesh.update_tangents();
+
+    let material = GpuMaterial::from_textures(
+        renderer,
+        Texture::from_color(
+            renderer,
+            [90, 90, 90, 255],
+            wgpu::TextureFormat::Rgba8UnormSrgb,
+        ),
+        Texture::from_color(
+            renderer,
+            [128, 128, 255, 255],
+            wgpu::TextureFormat::Rgba8Unorm,
+        ),
+    );
+
+    ObjectAssets {
+        center: mesh.center(),
+        bounds: mesh.bounds(),
+        lods: vec![mesh.upload_to_gpu(renderer)],
+        wireframes: vec![WireframeMesh::new(renderer, &mesh)],
+        material,
+    }
+}
+
+/// A built-in scene with its objects uploaded to the GPU.
+pub struct LoadedScene {
+    scene: Scene,
+    /// The rays per vertex the ambient occlusion of the objects was baked
+    /// with.
+    ao_samples: u32,
+    objects: Vec<Rc<ObjectAssets>>,
+    /// A plane under the objects, kept apart from them so it doesn't count
+    /// towards the scene bounds and isn't drawn into its own reflection.
+    ground: Rc<ObjectAssets>,
+    ground_height: f32,
+    lights: Vec<PointLight>,
+    decals: Vec<cgmath::Matrix4<f32>>,
+    yaw: cgmath::Deg<f32>,
+    pitch: cgmath::Deg<f32>,
+    distance: f32,
+}
+
+impl LoadedScene {
+    fn load(renderer: &Renderer, scene: Scene, ao_samples: u32) -> Self {
+        let SceneDescription {
+            objects,
+            lights,
+            decals,
+            yaw,
+            pitch,
+            distance,
+        } = scene.describe();
+
+        let objects = scene_objects(renderer, objects, ao_samples);
+        // A ground plane under everything for the objects to reflect in.
+        let ground_height = lowest_point(&objects);
+        let ground = Rc::new(ground_object(renderer, ground_height));
+
+        Self {
+            scene,
+            ao_samples,
+            objects,
+            ground,
+            ground_height,
+            lights,
+            decals,
+            yaw,
+            pitch,
+            distance,
+        }
+    }
+}
+
+/// The scene shown by the apps of all the windows, so opening another window
+/// doesn't load the objects and bake them again. Loading a scene in one window
+/// shows it in the others too. See [App::with_shared_scene].
+pub type SharedScene = Rc<RefCell<Rc<LoadedScene>>>;
+
+/// The level of detail to draw an object with, given the part of the screen
+/// height its bounding sphere covers.
+fn select_lod(screen_size: f32, lod_count: usize) -> usize {
+    let lod = LOD_SCREEN_SIZES
+        .iter()
+        .filter(|&&size| screen_size < size)
+        .count();
+    lod.min(lod_count.saturating_sub(1))
+}
+
+/// The textures the passes render to, see [App::swap_render_targets].
+struct RenderTargets {
+    depth: Texture,
+    albedo: Texture,
+    position: Option<Texture>,
+    normal: Texture,
+    specular: Texture,
+    scene: Texture,
+}
+
+/// The size of the render targets for the given surface and render scale.
+fn render_size(surface_config: &wgpu::SurfaceConfiguration, render_scale: f32) -> (u32, u32) {
+    let width = (surface_config.width as f32 * render_scale).round() as u32;
+    let height = (surface_config.height as f32 * render_scale).round() as u32;
+    (width.max(1), height.max(1))
 }
 
 impl App {
-    pub fn new(renderer: &Renderer) -> Self {
-        let Renderer {
-            device,
-            surface_config,
-            ..
-        } = renderer;
+    pub fn new(renderer: &Renderer, options: AppOptions) -> Self {
+        let loaded_scene = LoadedScene::load(renderer, Scene::default(), AO_SAMPLES);
+        Self::with_shared_scene(
+            renderer,
+            options,
+            Rc::new(RefCell::new(Rc::new(loaded_scene))),
+        )
+    }
+
+    /// An app for another window, showing the same scene as the app that
+    /// `shared_scene` came from, with its own camera. See [App::shared_scene].
+    pub fn with_shared_scene(
+        renderer: &Renderer,
+        options: AppOptions,
+        shared_scene: SharedScene,
+    ) -> Self {
+        let Renderer { surface_config, .. } = renderer;
+
+        let render_scale = 1.0;
+        let (width, height) = render_size(surface_config, render_scale);
 
-        let depth_texture =
-            create_depth_texture(device, surface_config.width, surface_config.height);
+        let depth_texture = create_depth_texture(renderer, width, height, renderer.depth_format);
         let albedo_g_texture = create_fullscreen_texture(
-            device,
-            surface_config,
-            wgpu::TextureFormat::Bgra8UnormSrgb,
+            renderer,
+            width,
+            height,
+            gbuffer::ALBEDO_FORMAT,
+            wgpu::FilterMode::Linear,
             "albedo texture",
         );
-        let position_g_texture = create_fullscreen_texture(
-            device,
-            surface_config,
-            wgpu::TextureFormat::Rgba16Float,
-            "position texture",
-        );
+        let position_g_texture = options.store_position.then(|| {
+            create_fullscreen_texture(
+                renderer,
+                width,
+                height,
+                gbuffer::POSITION_FORMAT,
+                wgpu::FilterMode::Nearest,
+                "position texture",
+            )
+        });
         let normal_g_texture = create_fullscreen_texture(
-            device,
-            surface_config,
-            wgpu::TextureFormat::Rgba16Float,
+            renderer,
+            width,
+            height,
+            gbuffer::normal_format(renderer.limits_profile),
+            wgpu::FilterMode::Nearest,
             "normal texture",
         );
-
-        let reader =
-            std::io::BufReader::new(std::io::Cursor::new(include_bytes!("../res/cube.obj")));
-        let mut mesh = match Mesh::<Vertex>::from_reader(reader) {
-            Ok(mesh) => mesh,
-            Err(err) => panic!("Error: {:?}", err),
-        };
-        mesh.update_tangents();
-        let mesh = mesh.upload_to_gpu(renderer);
-
-        let material = GpuMaterial::new(
+        let specular_g_texture = create_fullscreen_texture(
+            renderer,
+            width,
+            height,
+            gbuffer::SPECULAR_FORMAT,
+            wgpu::FilterMode::Linear,
+            "specular texture",
+        );
+        let scene_texture = create_fullscreen_texture(
             renderer,
-            include_bytes!("../res/metal/albedo.png"),
-            include_bytes!("../res/metal/normal.png"),
+            width,
+            height,
+            surface_config.format,
+            wgpu::FilterMode::Linear,
+            "scene texture",
         );
 
+        let loaded_scene = Rc::clone(&shared_scene.borrow());
+        let objects = loaded_scene
+            .objects
+            .iter()
+            .map(RenderObject::new)
+            .collect::<Vec<_>>();
+        let ground = RenderObject::new(&loaded_scene.ground);
+
         let camera = Camera::new(renderer);
 
-        let lights = Lights::new(
+        renderer.push_error_scope();
+        let shadow_atlas = ShadowAtlas::new(renderer);
+        renderer.pop_error_scope("shadow atlas");
+
+        let lights = Lights::new(renderer, vec![], &shadow_atlas);
+
+        renderer.push_error_scope();
+        let mesh_render_pipeline = MeshRenderPipeline::new(
             renderer,
-            PointLight::new([3.0, 3.0, 3.0], 1.0, [1.0, 1.0, 1.0], 10.0, 0.1),
+            &camera.bind_group_layout,
+            &objects[0].assets.material.bind_group_layout,
+            &gbuffer::target_formats(renderer.limits_profile, options.store_position),
         );
+        renderer.pop_error_scope("mesh render pipeline");
 
-        let mesh_render_pipeline = MeshRenderPipeline::new(
+        renderer.push_error_scope();
+        let transparent_render_pipeline = TransparentRenderPipeline::new(
             renderer,
             &camera.bind_group_layout,
-            &material.bind_group_layout,
+            &objects[0].assets.material.bind_group_layout,
+            &lights.bind_group_layout,
         );
+        renderer.pop_error_scope("transparent render pipeline");
 
-        let fullscreen_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-            label: Some("fullscreen shader module"),
-            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("fullscreen.wgsl"))),
-        });
+        renderer.push_error_scope();
+        let deferred_renderer = DeferredRenderer::new(
+            renderer,
+            &camera,
+            &lights,
+            surface_config.format,
+            options.store_position,
+        );
+        renderer.pop_error_scope("fullscreen render pipelines");
 
-        let fullscreen_bind_group_layout =
-            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                label: Some("fullscreen bind group layout"),
-                entries: &[
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 0,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        ty: wgpu::BindingType::Texture {
-                            sample_type: wgpu::TextureSampleType::Depth,
-                            view_dimension: wgpu::TextureViewDimension::D2,
-                            multisampled: false,
-                        },
-                        count: None,
-                    },
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 1,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        ty: wgpu::BindingType::Texture {
-                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
-                            view_dimension: wgpu::TextureViewDimension::D2,
-                            multisampled: false,
-                        },
-                        count: None,
-                    },
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 2,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
-                        count: None,
-                    },
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 3,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        ty: wgpu::BindingType::Texture {
-                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
-                            view_dimension: wgpu::TextureViewDimension::D2,
-                            multisampled: false,
-                        },
-                        count: None,
-                    },
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 4,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
-                        count: None,
-                    },
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 5,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        ty: wgpu::BindingType::Texture {
-                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
-                            view_dimension: wgpu::TextureViewDimension::D2,
-                            multisampled: false,
-                        },
-                        count: None,
-                    },
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 6,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
-                        count: None,
-                    },
-                ],
-            });
+        renderer.push_error_scope();
+        let mut reflection = PlanarReflection::new(
+            renderer,
+            &camera,
+            surface_config.format,
+            options.store_position,
+        );
+        reflection.height = loaded_scene.ground_height;
+        renderer.pop_error_scope("planar reflection");
 
-        let fullscreen_pipeline_layout =
-            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                label: Some("fullscreen pipeline layout"),
-                bind_group_layouts: &[
-                    &fullscreen_bind_group_layout,
-                    &camera.bind_group_layout,
-                    &lights.bind_group_layout,
-                ],
-                push_constant_ranges: &[],
-            });
+        renderer.push_error_scope();
+        let wireframe = WireframeOverlay::new(renderer, &camera, surface_config.format);
+        renderer.pop_error_scope("wireframe overlay");
 
-        let fullscreen_render_pipeline =
-            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-                label: Some("fullscreen render pipeline"),
-                layout: Some(&fullscreen_pipeline_layout),
-                vertex: wgpu::VertexState {
-                    module: &fullscreen_module,
-                    entry_point: "vertex_main",
-                    compilation_options: wgpu::PipelineCompilationOptions::default(),
-                    buffers: &[],
-                },
-                primitive: wgpu::PrimitiveState::default(),
-                depth_stencil: None,
-                multisample: wgpu::MultisampleState::default(),
-                fragment: Some(wgpu::FragmentState {
-                    module: &fullscreen_module,
-                    entry_point: "fragment_main",
-                    compilation_options: wgpu::PipelineCompilationOptions::default(),
-                    targets: &[Some(wgpu::ColorTargetState {
-                        format: surface_config.format,
-                        blend: Some(wgpu::BlendState::REPLACE),
-                        write_mask: wgpu::ColorWrites::ALL,
-                    })],
-                }),
-                multiview: None,
-                cache: None,
-            });
+        renderer.push_error_scope();
+        let matcap = Matcap::new(
+            renderer,
+            &camera,
+            surface_config.format,
+            options.store_position,
+        );
+        renderer.pop_error_scope("matcap");
 
-        let debug_render_pipeline =
-            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-                label: Some("debug render pipeline"),
-                layout: Some(&fullscreen_pipeline_layout),
-                vertex: wgpu::VertexState {
-                    module: &fullscreen_module,
-                    entry_point: "vertex_main",
-                    compilation_options: wgpu::PipelineCompilationOptions::default(),
-                    buffers: &[],
-                },
-                primitive: wgpu::PrimitiveState::default(),
-                depth_stencil: None,
-                multisample: wgpu::MultisampleState::default(),
-                fragment: Some(wgpu::FragmentState {
-                    module: &fullscreen_module,
-                    entry_point: "fragment_debug",
-                    compilation_options: wgpu::PipelineCompilationOptions::default(),
-                    targets: &[Some(wgpu::ColorTargetState {
-                        format: surface_config.format,
-                        blend: Some(wgpu::BlendState::REPLACE),
-                        write_mask: wgpu::ColorWrites::ALL,
-                    })],
-                }),
-                multiview: None,
-                cache: None,
-            });
+        renderer.push_error_scope();
+        let decals = Decals::new(
+            renderer,
+            &camera,
+            gbuffer::ALBEDO_FORMAT,
+            gbuffer::normal_format(renderer.limits_profile),
+            options.store_position,
+        );
+        renderer.pop_error_scope("decals");
 
+        renderer.push_error_scope();
         let gizmos = Gizmos::new(renderer, &camera);
+        renderer.pop_error_scope("gizmos");
+
+        renderer.push_error_scope();
+        let particles = Particles::new(renderer, &camera, 1024);
+        renderer.pop_error_scope("particles");
+
+        renderer.push_error_scope();
+        let depth_of_field = DepthOfField::new(renderer, &camera, surface_config.format);
+        renderer.pop_error_scope("depth of field");
+
+        renderer.push_error_scope();
+        let motion_blur = MotionBlur::new(renderer, &camera, surface_config.format);
+        renderer.pop_error_scope("motion blur");
+
+        renderer.push_error_scope();
+        let post_effects = PostEffects::new(renderer, &camera, surface_config.format);
+        renderer.pop_error_scope("post effects");
 
+        renderer.push_error_scope();
+        let blit = Blit::new(renderer, surface_config.format);
+        renderer.pop_error_scope("blit");
+
+        renderer.push_error_scope();
         let ui = ui::UserInterface::new(renderer);
+        renderer.pop_error_scope("ui");
 
         let light_x = ui::Slider::new(Arc::clone(&ui.fonts), "Light X").with_min_max(-5.0, 5.0);
         let light_y = ui::Slider::new(Arc::clone(&ui.fonts), "Light Y").with_min_max(-5.0, 5.0);
@@ -275,104 +934,454 @@ impl App {
             .insert(ui::Slider::new(Arc::clone(&ui.fonts), "Intensity").with_min_max(0.1, 10.0));
         let shininess_id = sliders.insert(
             ui::Slider::new(Arc::clone(&ui.fonts), "Shininess")
+                .with_log_scale(true)
                 .with_min_max(0.1, 100.0)
                 .with_value(10.0),
         );
-        let ambient_id = sliders.insert(
-            ui::Slider::new(Arc::clone(&ui.fonts), "Ambient")
-                .with_min_max(0.0, 0.1)
-                .with_value(0.01),
+        let render_scale_id = sliders.insert(
+            ui::Slider::new(Arc::clone(&ui.fonts), "Render scale")
+                .with_min_max(0.5, 2.0)
+                .with_value(render_scale),
+        );
+        let day_speed_id = sliders.insert(
+            ui::Slider::new(Arc::clone(&ui.fonts), "Day speed")
+                .with_min_max(0.0, 4.0)
+                .with_value(1.0),
+        );
+        // In degrees per second.
+        let turntable_speed_id = sliders.insert(
+            ui::Slider::new(Arc::clone(&ui.fonts), "Turntable speed")
+                .with_min_max(-90.0, 90.0)
+                .with_value(20.0),
+        );
+        let shadow_softness_id = sliders.insert(
+            ui::Slider::new(Arc::clone(&ui.fonts), "Shadow softness")
+                .with_min_max(0.0, 4.0)
+                .with_value(1.0),
         );
+        let shadow_bias_id = sliders.insert(
+            ui::Slider::new(Arc::clone(&ui.fonts), "Shadow bias")
+                .with_min_max(0.0, 0.2)
+                .with_value(0.02),
+        );
+        let shadow_slope_bias_id = sliders.insert(
+            ui::Slider::new(Arc::clone(&ui.fonts), "Slope bias")
+                .with_min_max(0.0, 0.2)
+                .with_value(0.02),
+        );
+        let rotation_sensitivity_id = sliders.insert(
+            ui::Slider::new(Arc::clone(&ui.fonts), "Look speed")
+                .with_log_scale(true)
+                .with_min_max(0.05, 2.0)
+                .with_value(0.5),
+        );
+        // The ambient color channels sit next to each other in a row.
+        let [ambient_r_id, ambient_g_id, ambient_b_id] = ["Amb R", "Amb G", "Amb B"].map(|label| {
+            sliders.insert(
+                ui::Slider::new(Arc::clone(&ui.fonts), label)
+                    .with_orientation(ui::Orientation::Vertical)
+                    .with_min_max(0.0, 0.1)
+                    .with_value(0.01),
+            )
+        });
 
-        Self {
+        let mut app = Self {
             depth_texture,
+            depth_sampler: create_depth_sampler(renderer),
             mesh_render_pipeline,
-
-            mesh,
-            material,
+            transparent_render_pipeline,
+
+            shared_scene,
+            loaded_scene: Rc::clone(&loaded_scene),
+            next_scene: None,
+            objects,
+            ground,
+            reflection,
+            decals,
+            wireframe,
+            matcap,
 
             albedo_g_texture,
             position_g_texture,
             normal_g_texture,
+            specular_g_texture,
+
+            scene_texture,
+            gbuffer_clear_values: GBufferClearValues::new(renderer.depth_convention),
+            render_scale,
+            blit,
 
-            fullscreen_render_pipeline,
-            debug_render_pipeline,
-            fullscreen_bind_group_layout,
+            deferred_renderer,
 
             camera,
             lights,
+            shadow_atlas,
 
-            rotating: None,
+            drag_target: None,
             last_mouse_position: (0.0, 0.0),
-            yaw: cgmath::Deg(90.0),
-            pitch: cgmath::Deg(0.0),
-            distance: 10.0,
+            last_click: None,
+            yaw: loaded_scene.yaw,
+            pitch: loaded_scene.pitch,
+            distance: loaded_scene.distance,
+            min_distance: 0.1,
+            max_distance: 100.0,
+            camera_presets: Vec::new(),
+            last_camera_preset: None,
+            camera_transition: None,
+            turntable: false,
+            up_vector: cgmath::Vector3::unit_y(),
+            rotation_sensitivity: 0.5,
+            invert_y: false,
+            scale_factor: 1.0,
 
             render_source: RenderSource::Final,
 
+            material_override: None,
+            shading: Shading::Smooth,
+            ao_samples: loaded_scene.ao_samples,
+            debug_textures: DebugTextures::new(renderer),
+
             light_angle: None,
+            headlight: false,
+            show_light_grid: false,
+            light_grid: None,
+
+            time_of_day: None,
+
+            dump_gbuffer: false,
+            show_debug_overlay: false,
+            screenshot_scale: None,
+            camera_position: cgmath::Point3::new(0.0, 0.0, 0.0),
 
             gizmos,
+            particles,
+            spark_accumulator: 0.0,
+            depth_of_field,
+            focus_at_cursor: false,
+            motion_blur,
+            post_effects,
+            passes: {
+                // Depth of field and motion blur are opt in, they hide detail
+                // while looking around the scene.
+                let mut passes = PassList::default();
+                passes.set_enabled(Pass::DepthOfField, false);
+                passes.set_enabled(Pass::MotionBlur, false);
+                passes
+            },
 
             last_frame_time: std::time::Instant::now(),
+            fixed_time_step: options.fixed_time_step,
+            min_frame_delta: options.min_frame_delta,
+            paused: false,
+            step_once: false,
+            frame_count: 0,
+            max_frames_in_flight: options.max_frames_in_flight.map(|max| max.clamp(1, 3)),
+            frames_in_flight: std::collections::VecDeque::new(),
+            frame_stats: FrameStats::new(),
+            has_rendered: false,
 
             ui,
+            controls_panel: ui::Panel::new(
+                options
+                    .controls_background
+                    .unwrap_or(epaint::Color32::TRANSPARENT),
+            )
+            .with_rounding(4.0)
+            .with_padding(5.0),
             sliders,
-            active_slider: None,
+            focused_slider: None,
+            modifiers: winit::keyboard::ModifiersState::empty(),
             light_x_id,
             light_y_id,
             light_z_id,
             intensity_id,
             shininess_id,
-            ambient_id,
-        }
+            ambient_r_id,
+            ambient_g_id,
+            ambient_b_id,
+            render_scale_id,
+            day_speed_id,
+            turntable_speed_id,
+            shadow_softness_id,
+            shadow_bias_id,
+            shadow_slope_bias_id,
+            rotation_sensitivity_id,
+            light_grid_id: None,
+        };
+
+        app.show_scene(renderer, loaded_scene);
+
+        app
     }
 
-    pub fn resize(&mut self, renderer: &Renderer) {
-        let Renderer {
-            device,
-            surface_config,
+    /// The built-in scene that is shown.
+    #[allow(dead_code)]
+    pub fn scene(&self) -> Scene {
+        self.loaded_scene.scene
+    }
+
+    /// The scene to show in the apps of other windows, see
+    /// [App::with_shared_scene].
+    pub fn shared_scene(&self) -> SharedScene {
+        Rc::clone(&self.shared_scene)
+    }
+
+    /// Replace the objects, lights, decals and camera framing with those of
+    /// `scene`, in the other windows too. The objects are only loaded again
+    /// if the scene or the ambient occlusion samples changed.
+    pub fn load_scene(&mut self, renderer: &Renderer, scene: Scene) {
+        let current = Rc::clone(&self.shared_scene.borrow());
+        let loaded_scene = if current.scene == scene && current.ao_samples == self.ao_samples {
+            current
+        } else {
+            Rc::new(LoadedScene::load(renderer, scene, self.ao_samples))
+        };
+        *self.shared_scene.borrow_mut() = Rc::clone(&loaded_scene);
+
+        self.show_scene(renderer, loaded_scene);
+    }
+
+    /// Show the objects of `loaded_scene`, and set up the lights, decals and
+    /// camera framing it comes with.
+    fn show_scene(&mut self, renderer: &Renderer, loaded_scene: Rc<LoadedScene>) {
+        self.objects = loaded_scene.objects.iter().map(RenderObject::new).collect();
+        self.ground = RenderObject::new(&loaded_scene.ground);
+        self.reflection.height = loaded_scene.ground_height;
+
+        let LoadedScene {
+            ref lights,
+            ref decals,
+            yaw,
+            pitch,
+            distance,
             ..
-        } = renderer;
+        } = *loaded_scene;
+        let lights = lights.clone();
+        let decals = decals.clone();
+        self.loaded_scene = loaded_scene;
+
+        // The first light follows the sliders, so move the sliders to it.
+        if let Some(key_light) = lights.first() {
+            let [x, y, z] = key_light.position;
+            for (id, value) in [
+                (self.light_x_id, x),
+                (self.light_y_id, y),
+                (self.light_z_id, z),
+                (self.intensity_id, key_light.intensity),
+                (self.shininess_id, key_light.shininess),
+            ] {
+                if let Some(slider) = self.sliders.get_mut(id) {
+                    slider.set_value(value);
+                }
+            }
+        }
+        // The grid is spread over the new scene on the next frame.
+        self.light_grid = None;
+        self.lights.set_point_lights(renderer, lights);
 
-        self.depth_texture =
-            create_depth_texture(device, surface_config.width, surface_config.height);
-        self.albedo_g_texture = create_fullscreen_texture(
-            device,
-            surface_config,
-            wgpu::TextureFormat::Rgba8UnormSrgb,
-            "albedo texture",
-        );
-        self.position_g_texture = create_fullscreen_texture(
-            device,
-            surface_config,
-            wgpu::TextureFormat::Rgba16Float,
-            "position texture",
-        );
-        self.normal_g_texture = create_fullscreen_texture(
-            device,
-            surface_config,
-            wgpu::TextureFormat::Rgba16Float,
-            "normal texture",
+        self.decals.clear();
+        for transform in decals {
+            self.add_decal(renderer, transform, ring_texture(renderer));
+        }
+
+        self.yaw = yaw;
+        self.pitch = pitch;
+        self.distance = distance;
+        self.camera_transition = None;
+        self.add_camera_preset(
+            "home",
+            CameraPreset {
+                yaw,
+                pitch,
+                distance,
+            },
         );
 
+        // Keep the camera outside the scene and close enough to see it.
+        let (min, max) = self.scene_bounds();
+        let radius = min.distance(max) * 0.5;
+        if radius > 0.0 {
+            self.set_zoom_limits(radius, radius * 10.0);
+        }
+    }
+
+    /// Replace the point lights of the scene. With no point lights, the scene
+    /// is lit by the ambient color and the sun only. The first light follows
+    /// the light sliders.
+    #[allow(dead_code)]
+    pub fn set_point_lights(&mut self, renderer: &Renderer, point_lights: Vec<PointLight>) {
+        self.light_grid = None;
+        self.lights.set_point_lights(renderer, point_lights);
+    }
+
+    /// Show a grid of extra lights over the scene, for stress testing. The
+    /// size of the grid is set with the "Light grid" slider.
+    #[allow(dead_code)]
+    pub fn set_light_grid(&mut self, show_light_grid: bool) {
+        self.show_light_grid = show_light_grid;
+    }
+
+    /// Keep orbiting the camera at the speed of the "Turntable speed" slider.
+    /// It turns by the fixed time step each frame when one is set, so
+    /// screenshots of consecutive frames are evenly spaced.
+    #[allow(dead_code)]
+    pub fn set_turntable(&mut self, turntable: bool) {
+        self.turntable = turntable;
+    }
+
+    /// Project `texture` onto the scene inside the unit box placed by
+    /// `transform`. See [`Decals`].
+    pub fn add_decal(
+        &mut self,
+        renderer: &Renderer,
+        transform: cgmath::Matrix4<f32>,
+        texture: Texture,
+    ) {
+        self.decals.add(renderer, transform, texture);
+    }
+
+    pub fn resize(&mut self, renderer: &Renderer) {
+        let Renderer { surface_config, .. } = renderer;
+
+        self.resize_render_targets(renderer);
+
         self.ui.resize(
             renderer,
             [surface_config.width as f32, surface_config.height as f32],
         );
 
+        self.layout_controls(renderer);
+    }
+
+    /// Place the sliders in a column along the right edge of the surface.
+    fn layout_controls(&mut self, renderer: &Renderer) {
+        let Renderer { surface_config, .. } = renderer;
+
         const SLIDER_SIZE: epaint::Vec2 = epaint::Vec2 { x: 300.0, y: 40.0 };
+        const VERTICAL_SLIDER_SIZE: epaint::Vec2 = epaint::Vec2 { x: 100.0, y: 150.0 };
+
+        // Horizontal sliders are stacked in a column, vertical sliders are put
+        // next to each other in rows as wide as the column.
+        let right = surface_config.width as f32 - 10.0;
+        let left = right - SLIDER_SIZE.x;
         let mut top = 0.0;
+        let mut row_left = left;
+        let mut row_height = 0.0;
         for (_, slider) in self.sliders.iter_mut() {
-            slider.bounds = epaint::Rect {
-                min: epaint::pos2(surface_config.width as f32 - 10.0 - SLIDER_SIZE.x, top),
-                max: epaint::pos2(surface_config.width as f32 - 10.0, top + SLIDER_SIZE.y),
-            };
-            top += SLIDER_SIZE.y;
+            match slider.orientation() {
+                ui::Orientation::Horizontal => {
+                    top += row_height;
+                    row_left = left;
+                    row_height = 0.0;
+
+                    slider.bounds =
+                        epaint::Rect::from_min_size(epaint::pos2(left, top), SLIDER_SIZE);
+                    top += SLIDER_SIZE.y;
+                }
+                ui::Orientation::Vertical => {
+                    if row_left + VERTICAL_SLIDER_SIZE.x > right {
+                        top += row_height;
+                        row_left = left;
+                    }
+
+                    slider.bounds = epaint::Rect::from_min_size(
+                        epaint::pos2(row_left, top),
+                        VERTICAL_SLIDER_SIZE,
+                    );
+                    row_left += VERTICAL_SLIDER_SIZE.x;
+                    row_height = VERTICAL_SLIDER_SIZE.y;
+                }
+            }
+        }
+
+        self.controls_panel
+            .fit(self.sliders.values().map(|slider| slider.bounds));
+    }
+
+    /// Recreate the G-buffer, depth and scene textures at the surface size
+    /// scaled by `render_scale`.
+    fn resize_render_targets(&mut self, renderer: &Renderer) {
+        let (width, height) = render_size(&renderer.surface_config, self.render_scale);
+        let mut targets = self.create_render_targets(renderer, width, height);
+        self.swap_render_targets(&mut targets);
+        // The new scene texture is blank until the next frame.
+        self.has_rendered = false;
+    }
+
+    /// A new set of the textures the passes render to, `width` by `height`.
+    fn create_render_targets(&self, renderer: &Renderer, width: u32, height: u32) -> RenderTargets {
+        RenderTargets {
+            depth: create_depth_texture(renderer, width, height, renderer.depth_format),
+            albedo: create_fullscreen_texture(
+                renderer,
+                width,
+                height,
+                gbuffer::ALBEDO_FORMAT,
+                wgpu::FilterMode::Linear,
+                "albedo texture",
+            ),
+            position: self.position_g_texture.is_some().then(|| {
+                create_fullscreen_texture(
+                    renderer,
+                    width,
+                    height,
+                    gbuffer::POSITION_FORMAT,
+                    wgpu::FilterMode::Nearest,
+                    "position texture",
+                )
+            }),
+            normal: create_fullscreen_texture(
+                renderer,
+                width,
+                height,
+                gbuffer::normal_format(renderer.limits_profile),
+                wgpu::FilterMode::Nearest,
+                "normal texture",
+            ),
+            specular: create_fullscreen_texture(
+                renderer,
+                width,
+                height,
+                gbuffer::SPECULAR_FORMAT,
+                wgpu::FilterMode::Linear,
+                "specular texture",
+            ),
+            scene: create_fullscreen_texture(
+                renderer,
+                width,
+                height,
+                renderer.surface_config.format,
+                wgpu::FilterMode::Linear,
+                "scene texture",
+            ),
         }
     }
 
+    /// Exchange the textures the passes render to with `targets`.
+    fn swap_render_targets(&mut self, targets: &mut RenderTargets) {
+        std::mem::swap(&mut self.depth_texture, &mut targets.depth);
+        std::mem::swap(&mut self.albedo_g_texture, &mut targets.albedo);
+        std::mem::swap(&mut self.position_g_texture, &mut targets.position);
+        std::mem::swap(&mut self.normal_g_texture, &mut targets.normal);
+        std::mem::swap(&mut self.specular_g_texture, &mut targets.specular);
+        std::mem::swap(&mut self.scene_texture, &mut targets.scene);
+    }
+
     pub fn on_mouse_down(&mut self, button: winit::event::MouseButton) {
+        // Other buttons don't start a new drag while one is in progress.
+        if self.drag_target.is_some() {
+            return;
+        }
+
+        if matches!(button, winit::event::MouseButton::Left) && self.is_double_click() {
+            let (x, y) = self.last_mouse_position;
+            for (_, slider) in self.sliders.iter_mut() {
+                if slider.bounds.contains(epaint::pos2(x, y)) && slider.on_double_click(x, y) {
+                    return;
+                }
+            }
+        }
+
         for (id, slider) in self.sliders.iter_mut() {
             let x = self.last_mouse_position.0;
             let y = self.last_mouse_position.1;
@@ -380,86 +1389,462 @@ impl App {
                 // If the widget returns true from an `on_mouse_down` event, we
                 // lock that widget as the active one and start sending `on_mouse_dragged`
                 // events instead of `on_mouse_moved` events when the mouse moves.
-                self.active_slider = Some(id);
+                self.drag_target = Some(DragTarget::Slider(id));
                 return;
             }
         }
 
         if matches!(button, winit::event::MouseButton::Left) {
-            self.rotating = Some(self.last_mouse_position);
+            self.drag_target = Some(DragTarget::Rotate(self.last_mouse_position));
         }
     }
 
-    pub fn on_mouse_up(&mut self, button: winit::event::MouseButton) {
-        if let Some(id) = self.active_slider {
-            if let Some(ref mut slider) = self.sliders.get_mut(id) {
-                slider.on_mouse_up();
-                self.active_slider = None;
-                return;
-            }
-        }
+    /// Record a left click and return true if it completes a double-click with
+    /// the previous one.
+    fn is_double_click(&mut self) -> bool {
+        const DOUBLE_CLICK_TIME: std::time::Duration = std::time::Duration::from_millis(400);
+        const DOUBLE_CLICK_DISTANCE: f32 = 4.0;
 
-        self.active_slider = None;
+        let now = std::time::Instant::now();
+        let (x, y) = self.last_mouse_position;
 
-        if matches!(button, winit::event::MouseButton::Left) {
-            self.rotating = None;
+        let is_double_click = self.last_click.is_some_and(|(time, (last_x, last_y))| {
+            now - time <= DOUBLE_CLICK_TIME
+                && (x - last_x).hypot(y - last_y) <= DOUBLE_CLICK_DISTANCE
+        });
+
+        // A third click starts a new double-click instead of completing another.
+        self.last_click = if is_double_click {
+            None
+        } else {
+            Some((now, (x, y)))
+        };
+
+        is_double_click
+    }
+
+    pub fn on_mouse_up(&mut self, button: winit::event::MouseButton) {
+        match self.drag_target {
+            Some(DragTarget::Slider(id)) => {
+                if let Some(slider) = self.sliders.get_mut(id) {
+                    slider.on_mouse_up();
+                }
+                self.drag_target = None;
+            }
+            Some(DragTarget::Rotate(_)) => {
+                if matches!(button, winit::event::MouseButton::Left) {
+                    self.drag_target = None;
+                }
+            }
+            None => {}
         }
     }
 
     pub fn on_mouse_wheel(&mut self, delta: f32) {
-        self.distance -= delta * (self.distance * 0.1);
+        self.camera_transition = None;
+        self.distance = (self.distance - delta * (self.distance * 0.1))
+            .clamp(self.min_distance, self.max_distance);
+    }
+
+    #[allow(dead_code)]
+    pub fn up_vector(&self) -> cgmath::Vector3<f32> {
+        self.up_vector
+    }
+
+    /// Set the direction that is up on screen, e.g. `unit_z` for models
+    /// authored Z-up. Zero length vectors are ignored.
+    #[allow(dead_code)]
+    pub fn set_up_vector(&mut self, up_vector: cgmath::Vector3<f32>) {
+        if up_vector.magnitude2() > f32::EPSILON {
+            self.up_vector = up_vector.normalize();
+        }
+    }
+
+    /// The closest and furthest the camera can be from its target.
+    #[allow(dead_code)]
+    pub fn zoom_limits(&self) -> (f32, f32) {
+        (self.min_distance, self.max_distance)
+    }
+
+    /// Where the camera is now, e.g. to store with [App::add_camera_preset].
+    pub fn camera_preset(&self) -> CameraPreset {
+        CameraPreset {
+            yaw: self.yaw,
+            pitch: self.pitch,
+            distance: self.distance,
+        }
+    }
+
+    /// Remember a view of the scene as `name`, replacing the view that had
+    /// that name. Every scene adds its starting view as "home". F5 goes
+    /// through the views in the order they were added.
+    pub fn add_camera_preset(&mut self, name: impl Into<String>, preset: CameraPreset) {
+        let name = name.into();
+        match self
+            .camera_presets
+            .iter_mut()
+            .find(|(other, _)| *other == name)
+        {
+            Some((_, existing)) => *existing = preset,
+            None => self.camera_presets.push((name, preset)),
+        }
+    }
+
+    /// Move the camera to the view added as `name` over `duration`, or right
+    /// away if `duration` is zero. Moving the camera by hand stops the move.
+    /// Returns `false` if there is no view called `name`.
+    pub fn go_to_camera(&mut self, name: &str, duration: std::time::Duration) -> bool {
+        let Some(index) = self
+            .camera_presets
+            .iter()
+            .position(|(other, _)| other == name)
+        else {
+            return false;
+        };
+        self.last_camera_preset = Some(index);
+
+        let to = self.camera_presets[index].1;
+        if duration.is_zero() {
+            self.camera_transition = None;
+            self.set_camera(to);
+        } else {
+            self.camera_transition = Some(CameraTransition {
+                from: self.camera_preset(),
+                to,
+                elapsed: std::time::Duration::ZERO,
+                duration,
+            });
+        }
+        true
+    }
+
+    fn set_camera(&mut self, preset: CameraPreset) {
+        self.yaw = preset.yaw;
+        self.pitch = preset.pitch;
+        self.distance = preset.distance.clamp(self.min_distance, self.max_distance);
+    }
+
+    /// Limit how far the camera can zoom in and out. The current distance is
+    /// clamped to the new limits.
+    pub fn set_zoom_limits(&mut self, min_distance: f32, max_distance: f32) {
+        self.min_distance = min_distance.min(max_distance);
+        self.max_distance = max_distance.max(min_distance);
+        self.distance = self.distance.clamp(self.min_distance, self.max_distance);
     }
 
     pub fn on_mouse_moved(&mut self, x: f32, y: f32) {
         self.last_mouse_position = (x, y);
 
-        if let Some(id) = self.active_slider {
-            if let Some(ref mut slider) = self.sliders.get_mut(id) {
-                slider.on_mouse_dragged(x, y);
-                return;
+        match self.drag_target {
+            Some(DragTarget::Slider(id)) => {
+                if let Some(slider) = self.sliders.get_mut(id) {
+                    slider.on_mouse_dragged(x, y);
+                }
             }
-        } else {
-            for (_, slider) in self.sliders.iter_mut() {
-                if slider.bounds.contains(epaint::pos2(x, y)) {
-                    slider.on_mouse_moved(x, y);
-                    return;
+
+            Some(DragTarget::Rotate(ref mut last_position)) => {
+                // Mouse positions are in physical pixels, so convert to logical
+                // pixels to rotate at the same speed on any display.
+                let degrees_per_pixel = self.rotation_sensitivity / self.scale_factor;
+                let delta = (x - last_position.0, y - last_position.1);
+
+                let pitch_delta = if self.invert_y { -delta.1 } else { delta.1 };
+
+                if !self.turntable {
+                    self.camera_transition = None;
+                    self.yaw += cgmath::Deg(delta.0 * degrees_per_pixel);
+                    self.pitch += cgmath::Deg(pitch_delta * degrees_per_pixel);
+                }
+
+                *last_position = (x, y);
+            }
+
+            None => {
+                for (_, slider) in self.sliders.iter_mut() {
+                    if slider.bounds.contains(epaint::pos2(x, y)) {
+                        slider.on_mouse_moved(x, y);
+                        return;
+                    }
                 }
             }
         }
+    }
 
-        if let Some(ref mut start_drag_position) = self.rotating {
-            let delta = (x - start_drag_position.0, y - start_drag_position.1);
+    #[allow(dead_code)]
+    pub fn render_source(&self) -> RenderSource {
+        self.render_source
+    }
+
+    /// The number of frames rendered so far, including the one being
+    /// rendered during [App::render].
+    #[allow(dead_code)]
+    pub fn frame_count(&self) -> u64 {
+        self.frame_count
+    }
+
+    pub fn set_render_source(&mut self, render_source: RenderSource) {
+        self.render_source = render_source;
+    }
+
+    /// Use the PNG in `bytes` as the matcap of [RenderSource::Matcap], an
+    /// image of a lit sphere that fills the whole image.
+    pub fn load_matcap(&mut self, renderer: &Renderer, bytes: &[u8]) -> Result<(), ()> {
+        let texture = Texture::from_bytes(renderer, bytes, true)?;
+        self.matcap.set_texture(texture);
+        Ok(())
+    }
+
+    /// Stop or restart the animations, like the light orbit and the
+    /// particles. The scene is still rendered every frame.
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+        self.step_once = false;
+    }
+
+    /// Advance the animations by a single frame on the next render, if they
+    /// are paused.
+    pub fn step_once(&mut self) {
+        if self.paused {
+            self.step_once = true;
+        }
+    }
+
+    /// Light whatever the camera looks at with a point light that follows
+    /// the camera, on top of the lights of the scene.
+    #[allow(dead_code)]
+    pub fn set_headlight(&mut self, enabled: bool) {
+        self.headlight = enabled;
+    }
+
+    /// Leave the background transparent instead of drawing the sky, so
+    /// screenshots and [App::read_pixels] give a cutout of the scene with the
+    /// coverage in alpha.
+    #[allow(dead_code)]
+    pub fn set_transparent_background(&mut self, renderer: &Renderer, transparent: bool) {
+        self.lights.set_transparent_sky(renderer, transparent);
+    }
+
+    /// Set the color and the width in pixels of the lines drawn by
+    /// [RenderSource::ShadedWireframe].
+    #[allow(dead_code)]
+    pub fn set_wireframe_style(&mut self, color: [f32; 4], thickness: f32) {
+        self.wireframe.color = color;
+        self.wireframe.thickness = thickness;
+    }
+
+    #[allow(dead_code)]
+    pub fn material_override(&self) -> Option<MaterialOverride> {
+        self.material_override
+    }
+
+    #[allow(dead_code)]
+    pub fn set_material_override(&mut self, material_override: Option<MaterialOverride>) {
+        self.material_override = material_override;
+    }
+
+    /// Show the triangles of opaque objects with [Shading::Flat], or blend
+    /// their normals with [Shading::Smooth].
+    #[allow(dead_code)]
+    pub fn set_shading(&mut self, shading: Shading) {
+        self.shading = shading;
+    }
+
+    /// Bake ambient occlusion with `samples` rays per vertex, or not at all
+    /// with 0. More rays give smoother shading, but make loading slower. Takes
+    /// effect when the next scene is loaded.
+    #[allow(dead_code)]
+    pub fn set_ao_samples(&mut self, samples: u32) {
+        self.ao_samples = samples;
+    }
+
+    pub fn on_scale_factor_changed(&mut self, scale_factor: f64) {
+        self.scale_factor = scale_factor as f32;
+    }
 
-            self.yaw += cgmath::Deg(delta.0);
-            self.pitch += cgmath::Deg(delta.1);
+    pub fn on_modifiers_changed(&mut self, modifiers: winit::keyboard::ModifiersState) {
+        self.modifiers = modifiers;
+    }
 
-            *start_drag_position = (x, y);
+    /// Move the keyboard focus to the next slider, or the previous one if
+    /// `backwards`, wrapping around at the ends.
+    fn cycle_focus(&mut self, backwards: bool) {
+        let ids = self.sliders.keys().collect::<Vec<_>>();
+        if ids.is_empty() {
+            return;
         }
+
+        let current = self
+            .focused_slider
+            .and_then(|focused| ids.iter().position(|&id| id == focused));
+        let next = match (current, backwards) {
+            (None, false) => 0,
+            (None, true) => ids.len() - 1,
+            (Some(i), false) => (i + 1) % ids.len(),
+            (Some(i), true) => (i + ids.len() - 1) % ids.len(),
+        };
+        self.focused_slider = Some(ids[next]);
     }
 
     pub fn on_key_pressed(&mut self, key_code: KeyCode) {
         match key_code {
+            KeyCode::Tab => {
+                self.cycle_focus(self.modifiers.shift_key());
+            }
+
+            KeyCode::Enter | KeyCode::Space => {
+                if let Some(slider) = self.focused_slider.and_then(|id| self.sliders.get_mut(id)) {
+                    slider.on_activate();
+                } else if key_code == KeyCode::Space {
+                    self.set_paused(!self.paused);
+                }
+            }
+
+            KeyCode::Period => {
+                self.step_once();
+            }
+
+            KeyCode::Escape => {
+                self.focused_slider = None;
+            }
+
+            KeyCode::KeyC => {
+                self.next_scene = Some(self.scene().next());
+            }
+
             KeyCode::KeyR => {
+                self.camera_transition = None;
                 self.pitch = cgmath::Deg(0.0);
                 self.yaw = cgmath::Deg(0.0);
             }
 
             KeyCode::Digit1 => {
-                self.render_source = RenderSource::Final;
+                self.set_render_source(RenderSource::Final);
             }
 
             KeyCode::Digit2 => {
-                self.render_source = RenderSource::Albedo;
+                self.set_render_source(RenderSource::Albedo);
             }
 
             KeyCode::Digit3 => {
-                self.render_source = RenderSource::Position;
+                self.set_render_source(RenderSource::Position);
             }
 
             KeyCode::Digit4 => {
-                self.render_source = RenderSource::Normal;
+                self.set_render_source(RenderSource::Normal);
+            }
+
+            KeyCode::Digit5 => {
+                self.set_render_source(RenderSource::Depth);
+            }
+
+            KeyCode::Digit6 => {
+                self.set_render_source(RenderSource::ShadedWireframe);
+            }
+
+            KeyCode::Digit7 => {
+                self.set_render_source(RenderSource::Coverage);
+            }
+
+            KeyCode::Digit8 => {
+                self.set_render_source(RenderSource::Matcap);
             }
 
+            KeyCode::KeyG => {
+                self.dump_gbuffer = true;
+            }
+
+            KeyCode::F5 if self.modifiers.shift_key() => {
+                let name = format!("view {}", self.camera_presets.len());
+                println!("Saved the camera as \"{name}\"");
+                self.add_camera_preset(name, self.camera_preset());
+            }
+            KeyCode::F5 if !self.camera_presets.is_empty() => {
+                let next = self
+                    .last_camera_preset
+                    .map_or(0, |index| (index + 1) % self.camera_presets.len());
+                let name = self.camera_presets[next].0.clone();
+                self.go_to_camera(&name, CAMERA_TRANSITION_DURATION);
+            }
+
+            KeyCode::F3 => {
+                self.show_debug_overlay = !self.show_debug_overlay;
+            }
+
+            KeyCode::F12 => {
+                self.screenshot_scale = Some(if self.modifiers.shift_key() { 4 } else { 2 });
+            }
+
+            KeyCode::KeyH => {
+                self.passes.toggle(Pass::Gizmos);
+            }
+
+            KeyCode::KeyO => {
+                self.passes.toggle(Pass::DepthOfField);
+            }
+
+            KeyCode::KeyB => {
+                self.passes.toggle(Pass::MotionBlur);
+            }
+
+            KeyCode::KeyE => {
+                self.passes.toggle(Pass::Reflections);
+            }
+
+            KeyCode::KeyF => {
+                self.focus_at_cursor = true;
+            }
+
+            KeyCode::KeyK => {
+                self.passes.toggle(Pass::Particles);
+            }
+
+            KeyCode::KeyT => {
+                self.set_turntable(!self.turntable);
+            }
+            // X-ray: show the gizmos through the scene.
+            KeyCode::KeyX => {
+                self.gizmos.depth_test = !self.gizmos.depth_test;
+            }
+
+            KeyCode::KeyN => {
+                self.time_of_day = match self.time_of_day {
+                    // Start the cycle at sunrise.
+                    None => Some(6.0),
+                    Some(_) => None,
+                };
+            }
+
+            KeyCode::KeyM if self.modifiers.shift_key() => {
+                self.shading = match self.shading {
+                    Shading::Smooth => Shading::Flat,
+                    Shading::Flat => Shading::Smooth,
+                };
+            }
+            KeyCode::KeyM => {
+                self.material_override = match self.material_override {
+                    None => Some(MaterialOverride::FlatWhite),
+                    Some(material_override) => material_override.next(),
+                };
+            }
+
+            KeyCode::KeyI => {
+                self.invert_y = !self.invert_y;
+            }
+
+            KeyCode::KeyP => {
+                self.lights.shadow_filter = match self.lights.shadow_filter {
+                    ShadowFilter::Pcf => ShadowFilter::Pcss,
+                    ShadowFilter::Pcss => ShadowFilter::Pcf,
+                };
+            }
+
+            KeyCode::KeyV => {
+                self.headlight = !self.headlight;
+            }
+
+            KeyCode::KeyL if self.modifiers.shift_key() => {
+                self.set_light_grid(!self.show_light_grid);
+            }
             KeyCode::KeyL => {
                 if self.light_angle.is_none() {
                     self.light_angle = Some(cgmath::Deg(0.0));
@@ -469,59 +1854,539 @@ impl App {
             }
 
             KeyCode::ArrowLeft => {
-                self.lights.point_light.position[0] -= 0.5;
+                if let Some(point_light) = self.lights.get_mut(0) {
+                    point_light.position[0] -= 0.5;
+                }
             }
             KeyCode::ArrowRight => {
-                self.lights.point_light.position[0] += 0.5;
+                if let Some(point_light) = self.lights.get_mut(0) {
+                    point_light.position[0] += 0.5;
+                }
             }
 
             KeyCode::ArrowUp => {
-                self.lights.point_light.position[2] += 0.5;
+                if let Some(point_light) = self.lights.get_mut(0) {
+                    point_light.position[2] += 0.5;
+                }
             }
             KeyCode::ArrowDown => {
-                self.lights.point_light.position[2] -= 0.5;
+                if let Some(point_light) = self.lights.get_mut(0) {
+                    point_light.position[2] -= 0.5;
+                }
             }
 
             KeyCode::PageUp => {
-                self.lights.point_light.position[1] += 0.5;
+                if let Some(point_light) = self.lights.get_mut(0) {
+                    point_light.position[1] += 0.5;
+                }
             }
             KeyCode::PageDown => {
-                self.lights.point_light.position[1] -= 0.5;
+                if let Some(point_light) = self.lights.get_mut(0) {
+                    point_light.position[1] -= 0.5;
+                }
             }
 
             _ => {}
         }
     }
 
+    /// Read back the last rendered frame as tightly packed RGBA8 pixels, along
+    /// with its width and height. This is the lit scene at the render scale,
+    /// without the UI, so it does not change with the fps counter. Returns
+    /// `None` if no frame has been rendered since the last resize.
+    #[allow(dead_code)]
+    pub fn read_pixels(&self, renderer: &Renderer) -> Option<(u32, u32, Vec<u8>)> {
+        if !self.has_rendered {
+            return None;
+        }
+
+        let texture = &self.scene_texture.texture;
+        let pixels = self.scene_texture.read_pixels(renderer);
+        let pixels = match texture.format() {
+            wgpu::TextureFormat::Rgba8Unorm | wgpu::TextureFormat::Rgba8UnormSrgb => pixels,
+            wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb => {
+                debug_dump::bgra_to_rgba(pixels)
+            }
+            format => {
+                eprintln!("Can not read pixels with format {format:?}");
+                return None;
+            }
+        };
+
+        Some((texture.width(), texture.height(), pixels))
+    }
+
+    /// Render the scene at `scale` times the surface size and save it to a PNG
+    /// at the surface size, halving it with the linear sampler until it fits,
+    /// so every output pixel averages the samples it covers. Gizmos and the UI
+    /// are left out. Uses the camera of the last rendered frame. The shot gets
+    /// its own render targets, so the last frame is still there afterwards.
+    pub fn capture_screenshot(
+        &mut self,
+        renderer: &Renderer,
+        path: impl AsRef<std::path::Path>,
+        scale: u32,
+    ) -> Result<(), image::ImageError> {
+        let Renderer {
+            device,
+            queue,
+            surface_config,
+            ..
+        } = renderer;
+
+        // Stay within the largest texture the device supports.
+        let max_size = device.limits().max_texture_dimension_2d;
+        let largest_side = surface_config.width.max(surface_config.height).max(1);
+        let scale = scale.clamp(1, (max_size / largest_side).max(1));
+
+        let (width, height) = render_size(surface_config, scale as f32);
+        let mut targets = self.create_render_targets(renderer, width, height);
+        self.swap_render_targets(&mut targets);
+
+        renderer.push_error_scope();
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("screenshot command encoder"),
+        });
+
+        let passes = self
+            .passes
+            .enabled()
+            .filter(|pass| !matches!(pass, Pass::Gizmos | Pass::Upscale | Pass::Ui))
+            .collect::<Vec<_>>();
+        // Only the skipped passes draw to the surface view.
+        let unused_view = self
+            .scene_texture
+            .texture
+            .create_view(&wgpu::TextureViewDescriptor::default());
+        for pass in passes {
+            encoder.push_debug_group(pass.name());
+            self.render_pass(
+                pass,
+                renderer,
+                &mut encoder,
+                &unused_view,
+                self.camera_position,
+            );
+            encoder.pop_debug_group();
+        }
+
+        let mut size = (
+            self.scene_texture.texture.width(),
+            self.scene_texture.texture.height(),
+        );
+        let target_size = (surface_config.width.max(1), surface_config.height.max(1));
+        let mut output = None;
+        while size != target_size {
+            size = (
+                (size.0 / 2).max(target_size.0),
+                (size.1 / 2).max(target_size.1),
+            );
+            let target = create_fullscreen_texture(
+                renderer,
+                size.0,
+                size.1,
+                surface_config.format,
+                wgpu::FilterMode::Linear,
+                "screenshot texture",
+            );
+            let source = output.as_ref().unwrap_or(&self.scene_texture);
+            self.blit
+                .render(renderer, &mut encoder, source, &target.view);
+            output = Some(target);
+        }
+
+        queue.submit(std::iter::once(encoder.finish()));
+        renderer.pop_error_scope("screenshot");
+
+        // Put the live targets back, the shot's scene texture is in `targets`.
+        self.swap_render_targets(&mut targets);
+
+        let output = output.as_ref().unwrap_or(&targets.scene);
+        let (width, height) = (output.texture.width(), output.texture.height());
+        let pixels = output.read_pixels(renderer);
+        let pixels = match output.texture.format() {
+            wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb => {
+                debug_dump::bgra_to_rgba(pixels)
+            }
+            _ => pixels,
+        };
+
+        image::save_buffer(path, &pixels, width, height, image::ColorType::Rgba8)
+    }
+
+    /// Add or remove the light grid and its slider to match
+    /// `show_light_grid`, and spread out a new grid when its size changes.
+    fn update_light_grid(&mut self, renderer: &Renderer) {
+        match (self.show_light_grid, self.light_grid_id) {
+            (true, None) => {
+                self.light_grid_id = Some(
+                    self.sliders.insert(
+                        ui::Slider::new(Arc::clone(&self.ui.fonts), "Light grid")
+                            .with_min_max(1.0, 16.0)
+                            .with_value(4.0),
+                    ),
+                );
+                self.layout_controls(renderer);
+            }
+            (false, Some(id)) => {
+                self.sliders.remove(id);
+                if self.focused_slider == Some(id) {
+                    self.focused_slider = None;
+                }
+                self.light_grid_id = None;
+                self.layout_controls(renderer);
+            }
+            _ => {}
+        }
+
+        let size = self
+            .light_grid_id
+            .and_then(|id| self.sliders.get(id))
+            .map(|slider| slider.value().round().max(1.0) as u32);
+
+        match (size, self.light_grid.take()) {
+            (Some(size), Some((spawned_size, scene_lights))) if size == spawned_size => {
+                self.light_grid = Some((size, scene_lights));
+            }
+            (Some(size), spawned) => {
+                let scene_lights = match spawned {
+                    Some((_, scene_lights)) => scene_lights,
+                    None => self.lights.iter().copied().collect(),
+                };
+                // The scene lights go first, so the first light still follows
+                // the light sliders.
+                let mut lights = scene_lights.clone();
+                lights.extend(light_grid(size, self.scene_bounds()));
+                self.lights.set_point_lights(renderer, lights);
+                self.light_grid = Some((size, scene_lights));
+            }
+            (None, Some((_, scene_lights))) => {
+                self.lights.set_point_lights(renderer, scene_lights);
+            }
+            (None, None) => {}
+        }
+    }
+
+    /// The lines of the debug overlay, shown with F3.
+    fn debug_overlay_text(&self, renderer: &Renderer) -> String {
+        let mut lines = vec![
+            format!(
+                "camera: ({:0.2}, {:0.2}, {:0.2})",
+                self.camera_position.x, self.camera_position.y, self.camera_position.z
+            ),
+            format!(
+                "yaw: {:0.1}, pitch: {:0.1}, distance: {:0.2}",
+                self.yaw.0, self.pitch.0, self.distance
+            ),
+        ];
+        lines.push(format!(
+            "gpu memory: {:0.1} MiB",
+            renderer.allocated_bytes() as f64 / (1024.0 * 1024.0)
+        ));
+        lines.push(format!(
+            "lods: {:?}",
+            self.objects.iter().map(|o| o.lod).collect::<Vec<_>>()
+        ));
+        if self.passes.is_enabled(Pass::DepthOfField) {
+            lines.push(format!(
+                "focus: {:0.2}, aperture: {:0.2}",
+                self.depth_of_field.focus_distance, self.depth_of_field.aperture
+            ));
+        }
+        // A light grid can have hundreds of lights, so only list the first few.
+        const MAX_LISTED_LIGHTS: usize = 8;
+        lines.extend(self.lights.iter().enumerate().take(MAX_LISTED_LIGHTS).map(
+            |(index, light)| {
+                let [x, y, z] = light.position;
+                format!(
+                    "light {index}: ({x:0.2}, {y:0.2}, {z:0.2}), intensity {:0.2}",
+                    light.intensity
+                )
+            },
+        ));
+        if self.lights.len() > MAX_LISTED_LIGHTS {
+            lines.push(format!(
+                "... and {} more lights",
+                self.lights.len() - MAX_LISTED_LIGHTS
+            ));
+        }
+        lines.join("\n")
+    }
+
+    /// The box bounding all the objects in the scene.
+    fn scene_bounds(&self) -> (cgmath::Point3<f32>, cgmath::Point3<f32>) {
+        let mut objects = self.objects.iter();
+        let Some(first) = objects.next() else {
+            return (
+                cgmath::Point3::new(0.0, 0.0, 0.0),
+                cgmath::Point3::new(0.0, 0.0, 0.0),
+            );
+        };
+
+        objects.fold(first.assets.bounds, |(min, max), object| {
+            let (object_min, object_max) = object.assets.bounds;
+            (
+                cgmath::Point3::new(
+                    min.x.min(object_min.x),
+                    min.y.min(object_min.y),
+                    min.z.min(object_min.z),
+                ),
+                cgmath::Point3::new(
+                    max.x.max(object_max.x),
+                    max.y.max(object_max.y),
+                    max.z.max(object_max.z),
+                ),
+            )
+        })
+    }
+
+    /// Whether `position` is in view and not hidden behind anything drawn to
+    /// the depth buffer by the last frame. This reads a single texel back from
+    /// the GPU and blocks until it arrives, so it is meant for the odd query,
+    /// like placing a label, rather than for every object every frame.
+    ///
+    /// If depth can't be copied, only the view is checked.
+    #[allow(dead_code)]
+    pub fn is_point_visible(&self, renderer: &Renderer, position: cgmath::Point3<f32>) -> bool {
+        let Some(ndc) = self.camera.world_to_ndc(position) else {
+            return false;
+        };
+
+        let width = self.depth_texture.texture.width();
+        let height = self.depth_texture.texture.height();
+        let x = (((ndc.x + 1.0) * 0.5 * width as f32) as u32).min(width - 1);
+        let y = (((1.0 - ndc.y) * 0.5 * height as f32) as u32).min(height - 1);
+
+        let Some(depth) = self.read_depth(renderer, x, y) else {
+            return true;
+        };
+
+        // Leave some room for the point being on the surface that wrote the
+        // depth.
+        const BIAS: f32 = 1.0e-4;
+        !renderer.depth_convention.is_closer(depth, ndc.z) || (ndc.z - depth).abs() <= BIAS
+    }
+
+    /// The distance along the view direction to whatever the last frame drew
+    /// under the window position `x`, `y`, e.g. the cursor. Returns `None` if
+    /// nothing was drawn there or the depth format can't be copied. Like
+    /// [App::is_point_visible], this blocks on a single texel readback.
+    pub fn depth_at(&self, renderer: &Renderer, x: f32, y: f32) -> Option<f32> {
+        let surface_config = &renderer.surface_config;
+        if x < 0.0
+            || y < 0.0
+            || x >= surface_config.width as f32
+            || y >= surface_config.height as f32
+        {
+            return None;
+        }
+
+        // The depth buffer is scaled along with the rest of the scene.
+        let width = self.depth_texture.texture.width();
+        let height = self.depth_texture.texture.height();
+        let texel_x = ((x * width as f32 / surface_config.width as f32) as u32).min(width - 1);
+        let texel_y = ((y * height as f32 / surface_config.height as f32) as u32).min(height - 1);
+
+        let depth = self.read_depth(renderer, texel_x, texel_y)?;
+        if renderer.depth_convention.is_far(depth) {
+            return None;
+        }
+
+        // Unproject the depth to view space to undo the perspective divide.
+        let inverse_projection = self.camera.projection_matrix.invert()?;
+        let view_position = inverse_projection * cgmath::vec4(0.0, 0.0, depth, 1.0);
+        Some(-view_position.z / view_position.w)
+    }
+
+    /// Read a single texel of the depth buffer. Returns `None` if the depth
+    /// format or the device doesn't support copying depth.
+    fn read_depth(&self, renderer: &Renderer, x: u32, y: u32) -> Option<f32> {
+        if !renderer
+            .downlevel_flags
+            .contains(wgpu::DownlevelFlags::DEPTH_TEXTURE_AND_BUFFER_COPIES)
+        {
+            return None;
+        }
+
+        let format = self.depth_texture.texture.format();
+        let bytes_per_texel = format.block_copy_size(Some(wgpu::TextureAspect::DepthOnly))?;
+        // Only 32 bit float depth can be copied. Rows of the copy are padded
+        // to the copy alignment by `read_region`.
+        debug_assert_eq!(bytes_per_texel, 4);
+
+        let texel = self.depth_texture.read_region(renderer, x, y, 1, 1);
+        Some(f32::from_le_bytes([texel[0], texel[1], texel[2], texel[3]]))
+    }
+
+    /// The depth of the scene, for custom shaders. It is recreated when the
+    /// window is resized or the render scale changes, so don't hold on to
+    /// bind groups that use it across frames.
+    #[allow(dead_code)]
+    pub fn depth_texture(&self) -> &Texture {
+        &self.depth_texture
+    }
+
+    /// The view of [App::depth_texture], covering only the depth aspect.
+    #[allow(dead_code)]
+    pub fn depth_view(&self) -> &wgpu::TextureView {
+        &self.depth_texture.view
+    }
+
+    /// A non-filtering sampler to read [App::depth_texture] with. The sampler
+    /// of the texture itself is a comparison sampler, meant for shadow tests.
+    #[allow(dead_code)]
+    pub fn depth_sampler(&self) -> &wgpu::Sampler {
+        &self.depth_sampler
+    }
+
+    /// Add a fullscreen effect that runs over the lit scene after the built-in
+    /// effects, see [PostEffects] for what `wgsl_source` has to define.
+    /// Returns `None` if the shader doesn't compile.
+    #[allow(dead_code)]
+    pub fn add_post_effect(
+        &mut self,
+        renderer: &Renderer,
+        wgsl_source: &str,
+    ) -> Option<PostEffectId> {
+        self.post_effects.add(renderer, wgsl_source)
+    }
+
+    #[allow(dead_code)]
+    pub fn remove_post_effect(&mut self, id: PostEffectId) {
+        self.post_effects.remove(id);
+    }
+
+    #[allow(dead_code)]
+    pub fn is_post_effect_enabled(&self, id: PostEffectId) -> bool {
+        self.post_effects.is_enabled(id)
+    }
+
+    #[allow(dead_code)]
+    pub fn set_post_effect_enabled(&mut self, id: PostEffectId, enabled: bool) {
+        self.post_effects.set_enabled(id, enabled);
+    }
+
+    /// What each G-buffer target is cleared to at the start of each frame.
+    /// Start from [GBufferClearValues::new] with `renderer.depth_convention`
+    /// to change only some of them.
+    #[allow(dead_code)]
+    pub fn set_gbuffer_clear_values(&mut self, clear_values: GBufferClearValues) {
+        self.gbuffer_clear_values = clear_values;
+    }
+
+    /// What the scene depth is cleared to at the start of each frame. `None`
+    /// keeps the depth of the previous frame. Use
+    /// [DepthConvention::far](crate::texture::DepthConvention::far) of
+    /// `renderer.depth_convention` for the normal clear.
+    #[allow(dead_code)]
+    pub fn set_depth_clear_value(&mut self, depth: Option<f32>) {
+        self.gbuffer_clear_values.depth = depth;
+    }
+
+    /// Write each of the G-buffer targets to a PNG in the working directory.
+    fn save_gbuffer(&self, renderer: &Renderer) {
+        for (texture, path) in [
+            (Some(&self.albedo_g_texture), "gbuffer_albedo.png"),
+            (self.position_g_texture.as_ref(), "gbuffer_position.png"),
+            (Some(&self.normal_g_texture), "gbuffer_normal.png"),
+            (Some(&self.specular_g_texture), "gbuffer_specular.png"),
+            (Some(&self.depth_texture), "gbuffer_depth.png"),
+        ] {
+            let Some(texture) = texture else {
+                continue;
+            };
+
+            match debug_dump::save_texture(renderer, texture, path) {
+                Ok(()) => println!("Saved {path}"),
+                Err(err) => eprintln!("Could not save {path}: {err}"),
+            }
+        }
+    }
+
     pub fn on_key_released(&mut self, _key_code: KeyCode) {}
 
     pub fn render(&mut self, renderer: &Renderer) {
         let Renderer {
             device,
             queue,
-            surface,
             surface_config,
+            ..
         } = renderer;
 
+        // Wait here rather than before submitting, so the input handled for
+        // this frame is as recent as possible.
+        if let Some(max_frames_in_flight) = self.max_frames_in_flight {
+            while self.frames_in_flight.len() >= max_frames_in_flight as usize {
+                let Some(submission) = self.frames_in_flight.pop_front() else {
+                    break;
+                };
+                device.poll(wgpu::Maintain::WaitForSubmissionIndex(submission));
+            }
+        }
+
+        if let Some(scene) = self.next_scene.take() {
+            println!("Loading the {} scene", scene.name());
+            self.load_scene(renderer, scene);
+        } else if !Rc::ptr_eq(&self.loaded_scene, &self.shared_scene.borrow()) {
+            // Another window loaded a scene.
+            let loaded_scene = Rc::clone(&self.shared_scene.borrow());
+            self.show_scene(renderer, loaded_scene);
+        }
+
         let now = std::time::Instant::now();
-        let last_frame_duration = now - self.last_frame_time;
+        let last_frame_duration = (now - self.last_frame_time).max(self.min_frame_delta);
         self.last_frame_time = now;
 
-        let fps = 1.0 / last_frame_duration.as_secs_f32();
+        self.frame_stats.push(last_frame_duration);
+        let (min_fps, max_fps) = self.frame_stats.min_max_fps();
         self.ui.push_shape(epaint::ClippedShape {
             clip_rect: epaint::Rect::EVERYTHING,
             shape: epaint::Shape::Text(epaint::TextShape::new(
                 epaint::pos2(10.0, 10.0),
                 self.ui.fonts.layout_no_wrap(
-                    format!("fps: {:0.2}", fps),
+                    format!(
+                        "fps: {:0.2} (min {:0.1}, max {:0.1})",
+                        self.frame_stats.average_fps(),
+                        min_fps,
+                        max_fps
+                    ),
                     epaint::FontId::monospace(16.0),
                     epaint::Color32::GREEN,
                 ),
                 epaint::Color32::default(),
             )),
         });
+        self.ui.push_shape(epaint::ClippedShape {
+            clip_rect: epaint::Rect::EVERYTHING,
+            shape: epaint::Shape::Text(epaint::TextShape::new(
+                epaint::pos2(10.0, 30.0),
+                self.ui.fonts.layout_no_wrap(
+                    format!(
+                        "distance: {:0.2} ({:0.2}..{:0.2})",
+                        self.distance, self.min_distance, self.max_distance
+                    ),
+                    epaint::FontId::monospace(16.0),
+                    epaint::Color32::GREEN,
+                ),
+                epaint::Color32::default(),
+            )),
+        });
+
+        let unpaused_frame_delta = self
+            .fixed_time_step
+            .unwrap_or(last_frame_duration)
+            .max(self.min_frame_delta);
+        let frame_delta = if self.paused && !std::mem::take(&mut self.step_once) {
+            std::time::Duration::ZERO
+        } else {
+            unpaused_frame_delta
+        };
+        self.frame_count += 1;
+        self.post_effects.advance(frame_delta);
 
-        let time_delta = 1.0 / ((1.0 / 60.0) / last_frame_duration.as_secs_f32());
+        // The frame delta in frames at 60 fps.
+        let time_delta = frame_delta.as_secs_f32() * 60.0;
 
         let intensity = self
             .sliders
@@ -535,52 +2400,143 @@ impl App {
             .map(|s| s.value())
             .unwrap_or(1.0);
 
-        let ambient = self
-            .sliders
-            .get(self.ambient_id)
-            .map(|s| s.value())
-            .unwrap_or(1.0);
+        let ambient_color = [self.ambient_r_id, self.ambient_g_id, self.ambient_b_id]
+            .map(|id| self.sliders.get(id).map(|s| s.value()).unwrap_or(0.0));
+        self.lights.set_ambient_color(renderer, ambient_color);
+
+        self.update_light_grid(renderer);
 
         if let Some(ref mut light_angle) = self.light_angle {
             *light_angle += cgmath::Deg(1.0 * time_delta);
             let x = light_angle.cos() * 3.0;
             let y = light_angle.sin() * 3.0;
-            self.lights.move_to(
-                renderer,
-                [x, 1.0, y],
-                intensity,
-                [1.0, 1.0, 1.0],
-                shininess,
-                ambient,
-            );
+            self.lights
+                .move_to(renderer, [x, 1.0, y], intensity, [1.0, 1.0, 1.0], shininess);
         } else {
             let x = self.sliders.get(self.light_x_id).unwrap().value();
             let y = self.sliders.get(self.light_y_id).unwrap().value();
             let z = self.sliders.get(self.light_z_id).unwrap().value();
-            self.lights.move_to(
+            self.lights
+                .move_to(renderer, [x, y, z], intensity, [1.0, 1.0, 1.0], shininess);
+        }
+
+        if let Some(ref mut time_of_day) = self.time_of_day {
+            let day_speed = self
+                .sliders
+                .get(self.day_speed_id)
+                .map(|s| s.value())
+                .unwrap_or(1.0);
+            *time_of_day = (*time_of_day + day_speed * frame_delta.as_secs_f32()) % 24.0;
+
+            let (sun, sky_color) = sun_at(*time_of_day);
+            self.lights.set_directional_light(renderer, sun, sky_color);
+        } else {
+            self.lights.set_directional_light(
                 renderer,
-                [x, y, z],
-                intensity,
-                [1.0, 1.0, 1.0],
-                shininess,
-                ambient,
+                DirectionalLight::new([0.0, -1.0, 0.0], 0.0, [1.0, 1.0, 1.0]),
+                [0.0, 0.0, 0.0],
             );
         }
 
+        let shadow_softness = self
+            .sliders
+            .get(self.shadow_softness_id)
+            .map(|s| s.value())
+            .unwrap_or(1.0);
+        self.lights
+            .set_shadow_filter(renderer, self.lights.shadow_filter, shadow_softness);
+
+        let [shadow_bias, shadow_slope_bias] = [self.shadow_bias_id, self.shadow_slope_bias_id]
+            .map(|id| self.sliders.get(id).map(|s| s.value()).unwrap_or(0.0));
+        self.lights
+            .set_shadow_bias(renderer, shadow_bias, shadow_slope_bias);
+
+        self.rotation_sensitivity = self
+            .sliders
+            .get(self.rotation_sensitivity_id)
+            .map(|s| s.value())
+            .unwrap_or(self.rotation_sensitivity);
+
+        let render_scale = self
+            .sliders
+            .get(self.render_scale_id)
+            .map(|s| s.value())
+            .unwrap_or(1.0);
+        if render_scale != self.render_scale {
+            self.render_scale = render_scale;
+            self.resize_render_targets(renderer);
+        }
+
+        // The override bind groups are created the first frame an override
+        // is used, because key presses don't have access to the renderer.
+        if let Some(material_override) = self.material_override {
+            let (albedo, normal) = self.debug_textures.textures(material_override);
+            for object in self.objects.iter_mut().chain([&mut self.ground]) {
+                let created_for = object
+                    .override_bind_group
+                    .as_ref()
+                    .map(|(created_for, _)| *created_for);
+                if created_for != Some(material_override) {
+                    let bind_group = object
+                        .assets
+                        .material
+                        .bind_group_with_textures(renderer, albedo, normal);
+                    object.override_bind_group = Some((material_override, bind_group));
+                }
+            }
+        }
+
         let aspect_ratio = surface_config.width as f32 / (surface_config.height as f32).max(0.001);
 
         let projection_matrix = cgmath::perspective(cgmath::Deg(45.0), aspect_ratio, 0.01, 100.0);
 
+        // Like moving the camera by hand, camera moves go on while paused.
+        if let Some(transition) = self.camera_transition.as_mut() {
+            transition.elapsed += unpaused_frame_delta;
+            let t = (transition.elapsed.as_secs_f32() / transition.duration.as_secs_f32()).min(1.0);
+            // Ease in and out.
+            let preset = transition.from.lerp(transition.to, t * t * (3.0 - 2.0 * t));
+            if t >= 1.0 {
+                self.camera_transition = None;
+            }
+            self.set_camera(preset);
+        } else if self.turntable {
+            let speed = self
+                .sliders
+                .get(self.turntable_speed_id)
+                .map(|s| s.value())
+                .unwrap_or(20.0);
+            // Keep the angle small, it turns for as long as the app runs.
+            self.yaw =
+                cgmath::Deg((self.yaw.0 + speed * unpaused_frame_delta.as_secs_f32()) % 360.0);
+        }
+
         let (camera_position, view_matrix) = {
             // Calculate the camera position
             let camera_x = self.distance * self.yaw.cos() * self.pitch.cos();
             let camera_y = self.distance * self.pitch.sin();
             let camera_z = self.distance * self.yaw.sin() * self.pitch.cos();
 
-            let camera_position = cgmath::Point3::new(camera_x, camera_y, camera_z);
+            // The orbit is calculated around Y and then turned to orbit
+            // around the up vector.
+            let rotation =
+                cgmath::Quaternion::from_arc(cgmath::Vector3::unit_y(), self.up_vector, None);
+            let camera_position =
+                cgmath::Point3::from_vec(rotation * cgmath::vec3(camera_x, camera_y, camera_z));
 
             let target = cgmath::Point3::new(0.0, 0.0, 0.0);
-            let up = cgmath::Vector3::unit_y();
+            // Looking straight along the up vector makes look_at_rh divide by
+            // zero, so use the direction towards the camera's side of the orbit
+            // instead.
+            let up = if (target - camera_position)
+                .cross(self.up_vector)
+                .magnitude2()
+                > 1.0e-6
+            {
+                self.up_vector
+            } else {
+                rotation * -cgmath::vec3(self.yaw.cos(), 0.0, self.yaw.sin())
+            };
             (
                 camera_position,
                 cgmath::Matrix4::look_at_rh(camera_position, target, up),
@@ -589,228 +2545,508 @@ impl App {
 
         self.camera
             .set_matrices(renderer, projection_matrix, view_matrix, camera_position);
+        self.camera_position = camera_position;
 
-        self.gizmos
-            .draw_axis(self.lights.point_light.position.into());
+        let headlight = self.headlight.then(|| {
+            PointLight::default()
+                .with_position(camera_position.into())
+                .with_intensity(0.5)
+        });
+        self.lights.set_headlight(renderer, headlight);
+
+        // How much of the screen height an object of radius 1 covers at a
+        // distance of 1.
+        let screen_scale = projection_matrix.y.y;
+        for object in self.objects.iter_mut() {
+            let (min, max) = object.assets.bounds;
+            let radius = min.distance(max) * 0.5;
+            let distance = object
+                .assets
+                .center
+                .distance(camera_position)
+                .max(f32::EPSILON);
+            object.lod = select_lod(radius * screen_scale / distance, object.assets.lods.len());
+        }
 
-        let output = surface.get_current_texture().expect("get current texture");
+        let mut overlay_top = 50.0;
+        if let Some((size, _)) = self.light_grid {
+            self.ui.push_shape(epaint::ClippedShape {
+                clip_rect: epaint::Rect::EVERYTHING,
+                shape: epaint::Shape::Text(epaint::TextShape::new(
+                    epaint::pos2(10.0, overlay_top),
+                    self.ui.fonts.layout_no_wrap(
+                        format!(
+                            "light grid: {size}x{size}, {} lights ({} lit), {:0.2} ms per frame",
+                            self.lights.len(),
+                            self.lights.lit_len(),
+                            1000.0 / self.frame_stats.average_fps()
+                        ),
+                        epaint::FontId::monospace(16.0),
+                        epaint::Color32::GREEN,
+                    ),
+                    epaint::Color32::default(),
+                )),
+            });
+            overlay_top += 20.0;
+        }
 
-        let surface_view = output
-            .texture
-            .create_view(&wgpu::TextureViewDescriptor::default());
+        if self.show_debug_overlay {
+            self.ui.push_shape(epaint::ClippedShape {
+                clip_rect: epaint::Rect::EVERYTHING,
+                shape: epaint::Shape::Text(epaint::TextShape::new(
+                    epaint::pos2(10.0, overlay_top),
+                    self.ui.fonts.layout_no_wrap(
+                        self.debug_overlay_text(renderer),
+                        epaint::FontId::monospace(14.0),
+                        epaint::Color32::WHITE,
+                    ),
+                    epaint::Color32::default(),
+                )),
+            });
+        }
+
+        // Leave out the light grid, it would hide the scene.
+        let scene_light_count = match self.light_grid {
+            Some((_, ref scene_lights)) => scene_lights.len(),
+            None => self.lights.len(),
+        };
+        for (index, point_light) in self.lights.iter().enumerate().take(scene_light_count) {
+            let light_position: cgmath::Vector3<f32> = point_light.position.into();
+            self.gizmos.draw_axis(light_position);
+            self.gizmos.draw_text_3d(
+                light_position,
+                format!("light {index}"),
+                [1.0, 1.0, 1.0, 1.0],
+            );
+            self.gizmos.draw_text_3d(
+                light_position + cgmath::Vector3::unit_x(),
+                "x",
+                [1.0, 0.0, 0.0, 1.0],
+            );
+            self.gizmos.draw_text_3d(
+                light_position + cgmath::Vector3::unit_y(),
+                "y",
+                [0.0, 1.0, 0.0, 1.0],
+            );
+            self.gizmos.draw_text_3d(
+                light_position + cgmath::Vector3::unit_z(),
+                "z",
+                [0.0, 0.0, 1.0, 1.0],
+            );
+        }
+
+        if self.passes.is_enabled(Pass::Particles) {
+            if let Some(point_light) = self.lights.iter().next() {
+                let origin: cgmath::Vector3<f32> = point_light.position.into();
+                self.spark_accumulator += SPARKS_PER_SECOND * frame_delta.as_secs_f32();
+                let count = self.spark_accumulator as usize;
+                self.spark_accumulator -= count as f32;
+                self.particles.emit_sparks(origin, count);
+            }
+        }
+        self.particles.update(frame_delta.as_secs_f32());
+
+        if self.light_angle.is_some() {
+            // Show the path the light is orbiting on.
+            let orbit = (0..=64)
+                .map(|i| {
+                    let angle = cgmath::Deg(i as f32 * 360.0 / 64.0);
+                    cgmath::vec3(angle.cos() * 3.0, 1.0, angle.sin() * 3.0)
+                })
+                .collect::<Vec<_>>();
+            self.gizmos.draw_polyline(&orbit, [1.0, 1.0, 0.0, 1.0]);
+        }
+
+        let Some(frame) = renderer.begin_frame() else {
+            // Nothing is drawn, so drop what was queued for this frame.
+            self.ui.clear_shapes();
+            self.gizmos.clear();
+            return;
+        };
+
+        renderer.push_error_scope();
 
         let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
             label: Some("main command encoder"),
         });
 
-        encoder.clear_texture(&output.texture, &wgpu::ImageSubresourceRange::default());
+        // The surface is not cleared, because the upscale pass writes every
+        // pixel of it.
 
-        encoder.clear_texture(
-            &self.albedo_g_texture.texture,
-            &wgpu::ImageSubresourceRange::default(),
-        );
-        encoder.clear_texture(
-            &self.position_g_texture.texture,
-            &wgpu::ImageSubresourceRange::default(),
-        );
-        encoder.clear_texture(
-            &self.normal_g_texture.texture,
-            &wgpu::ImageSubresourceRange::default(),
-        );
+        // Debug groups make the sections of the frame easy to find in GPU
+        // captures, e.g. RenderDoc.
+        let passes = self.passes.enabled().collect::<Vec<_>>();
+        for pass in passes {
+            encoder.push_debug_group(pass.name());
+            self.render_pass(pass, renderer, &mut encoder, &frame.view, camera_position);
+            encoder.pop_debug_group();
+        }
 
-        if true {
-            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                label: Some("gbuffer render pass"),
-                color_attachments: &[
-                    Some(wgpu::RenderPassColorAttachment {
-                        view: &self.albedo_g_texture.view,
-                        resolve_target: None,
-                        ops: wgpu::Operations {
-                            load: wgpu::LoadOp::Load,
-                            store: wgpu::StoreOp::Store,
-                        },
-                    }),
-                    Some(wgpu::RenderPassColorAttachment {
-                        view: &self.position_g_texture.view,
-                        resolve_target: None,
-                        ops: wgpu::Operations {
-                            load: wgpu::LoadOp::Load,
-                            store: wgpu::StoreOp::Store,
-                        },
-                    }),
-                    Some(wgpu::RenderPassColorAttachment {
-                        view: &self.normal_g_texture.view,
-                        resolve_target: None,
-                        ops: wgpu::Operations {
-                            load: wgpu::LoadOp::Load,
-                            store: wgpu::StoreOp::Store,
-                        },
-                    }),
-                ],
-                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
-                    view: &self.depth_texture.view,
-                    depth_ops: Some(wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(1.0),
-                        store: wgpu::StoreOp::Store,
-                    }),
-                    stencil_ops: None,
-                }),
-                timestamp_writes: None,
-                occlusion_query_set: None,
-            });
+        // The UI and gizmos are queued every frame, drop them if they weren't
+        // drawn.
+        if !self.passes.is_enabled(Pass::Ui) {
+            self.ui.clear_shapes();
+        }
+        if !self.passes.is_enabled(Pass::Gizmos) {
+            self.gizmos.clear();
+        }
 
-            render_pass.set_pipeline(&self.mesh_render_pipeline.pipeline);
-            render_pass.set_vertex_buffer(0, self.mesh.vertex_buffer.slice(..));
-            render_pass
-                .set_index_buffer(self.mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
-            render_pass.set_bind_group(0, &self.camera.bind_group, &[]);
-            render_pass.set_bind_group(1, &self.material.bind_group, &[]);
-            render_pass.set_bind_group(2, &self.lights.bind_group, &[]);
-            render_pass.draw_indexed(0..self.mesh.index_count, 0, 0..1);
-        }
-
-        if true {
-            let fullscreen_bind_group = if matches!(self.render_source, RenderSource::Final) {
-                device.create_bind_group(&wgpu::BindGroupDescriptor {
-                    label: Some("fullscreen bind group"),
-                    layout: &self.fullscreen_bind_group_layout,
-                    entries: &[
-                        wgpu::BindGroupEntry {
-                            binding: 0,
-                            resource: wgpu::BindingResource::TextureView(&self.depth_texture.view),
-                        },
-                        wgpu::BindGroupEntry {
-                            binding: 1,
-                            resource: wgpu::BindingResource::TextureView(
-                                &self.albedo_g_texture.view,
-                            ),
-                        },
-                        wgpu::BindGroupEntry {
-                            binding: 2,
-                            resource: wgpu::BindingResource::Sampler(
-                                &self.albedo_g_texture.sampler,
-                            ),
-                        },
-                        wgpu::BindGroupEntry {
-                            binding: 3,
-                            resource: wgpu::BindingResource::TextureView(
-                                &self.position_g_texture.view,
-                            ),
-                        },
-                        wgpu::BindGroupEntry {
-                            binding: 4,
-                            resource: wgpu::BindingResource::Sampler(
-                                &self.position_g_texture.sampler,
-                            ),
-                        },
-                        wgpu::BindGroupEntry {
-                            binding: 5,
-                            resource: wgpu::BindingResource::TextureView(
-                                &self.normal_g_texture.view,
-                            ),
-                        },
-                        wgpu::BindGroupEntry {
-                            binding: 6,
-                            resource: wgpu::BindingResource::Sampler(
-                                &self.normal_g_texture.sampler,
-                            ),
-                        },
-                    ],
-                })
-            } else {
-                let fullscreen_texture = match self.render_source {
-                    RenderSource::Albedo => &self.albedo_g_texture,
-                    RenderSource::Position => &self.position_g_texture,
-                    RenderSource::Normal => &self.normal_g_texture,
-                    RenderSource::Final => unreachable!("handled above"),
-                };
+        let submission = queue.submit(std::iter::once(encoder.finish()));
+        if self.max_frames_in_flight.is_some() {
+            self.frames_in_flight.push_back(submission);
+        }
+        self.has_rendered = true;
 
-                device.create_bind_group(&wgpu::BindGroupDescriptor {
-                    label: Some("fullscreen bind group"),
-                    layout: &self.fullscreen_bind_group_layout,
-                    entries: &[
-                        wgpu::BindGroupEntry {
-                            binding: 0,
-                            resource: wgpu::BindingResource::TextureView(&self.depth_texture.view),
-                        },
-                        wgpu::BindGroupEntry {
-                            binding: 1,
-                            resource: wgpu::BindingResource::TextureView(&fullscreen_texture.view),
-                        },
-                        wgpu::BindGroupEntry {
-                            binding: 2,
-                            resource: wgpu::BindingResource::Sampler(&fullscreen_texture.sampler),
-                        },
-                        wgpu::BindGroupEntry {
-                            binding: 3,
-                            resource: wgpu::BindingResource::TextureView(&fullscreen_texture.view),
-                        },
-                        wgpu::BindGroupEntry {
-                            binding: 4,
-                            resource: wgpu::BindingResource::Sampler(&fullscreen_texture.sampler),
-                        },
-                        wgpu::BindGroupEntry {
-                            binding: 5,
-                            resource: wgpu::BindingResource::TextureView(&fullscreen_texture.view),
-                        },
-                        wgpu::BindGroupEntry {
-                            binding: 6,
-                            resource: wgpu::BindingResource::Sampler(&fullscreen_texture.sampler),
-                        },
-                    ],
-                })
-            };
+        renderer.pop_error_scope("frame");
 
-            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                label: Some("fullscreen render pass"),
-                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: &surface_view,
-                    resolve_target: None,
-                    ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(wgpu::Color {
-                            r: 0.1,
-                            g: 0.2,
-                            b: 0.3,
-                            a: 1.0,
-                        }),
-                        store: wgpu::StoreOp::Store,
-                    },
-                })],
-                depth_stencil_attachment: None,
-                timestamp_writes: None,
-                occlusion_query_set: None,
-            });
+        if self.dump_gbuffer {
+            self.dump_gbuffer = false;
+            self.save_gbuffer(renderer);
+        }
 
-            if matches!(self.render_source, RenderSource::Final) {
-                render_pass.set_pipeline(&self.fullscreen_render_pipeline);
-            } else {
-                render_pass.set_pipeline(&self.debug_render_pipeline);
+        if std::mem::take(&mut self.focus_at_cursor) {
+            let (x, y) = self.last_mouse_position;
+            if let Some(distance) = self.depth_at(renderer, x, y) {
+                self.depth_of_field.focus_distance = distance;
             }
-            render_pass.set_bind_group(0, &fullscreen_bind_group, &[]);
-            render_pass.set_bind_group(1, &self.camera.bind_group, &[]);
-            render_pass.set_bind_group(2, &self.lights.bind_group, &[]);
-            render_pass.draw(0..3, 0..1);
         }
 
-        if true {
-            self.gizmos.render(
-                renderer,
-                &mut encoder,
-                &surface_view,
-                &self.depth_texture.view,
-                &self.camera,
-            );
+        if let Some(scale) = self.screenshot_scale.take() {
+            let path = "screenshot.png";
+            match self.capture_screenshot(renderer, path, scale) {
+                Ok(()) => println!("Saved {path}"),
+                Err(err) => eprintln!("Could not save {path}: {err}"),
+            }
         }
 
-        for (_, slider) in self.sliders.iter_mut() {
-            let shapes = slider.shapes();
-            self.ui.push_shapes(shapes);
-        }
+        frame.present();
+    }
 
-        self.ui.render(renderer, &mut encoder, &surface_view);
+    fn render_pass(
+        &mut self,
+        pass: Pass,
+        renderer: &Renderer,
+        encoder: &mut wgpu::CommandEncoder,
+        surface_view: &wgpu::TextureView,
+        camera_position: cgmath::Point3<f32>,
+    ) {
+        match pass {
+            Pass::Shadows => {
+                let shadow_casters = self
+                    .objects
+                    .iter()
+                    .filter(|o| !o.assets.material.transparent)
+                    .map(|o| o.mesh())
+                    .collect::<Vec<_>>();
+                self.shadow_atlas.render(
+                    renderer,
+                    encoder,
+                    &self.lights.shadow_view_projections(),
+                    &shadow_casters,
+                );
+            }
 
-        queue.submit(std::iter::once(encoder.finish()));
+            Pass::GBuffer => {
+                let gbuffer = GBuffer {
+                    depth: &self.depth_texture,
+                    albedo: &self.albedo_g_texture,
+                    position: self.position_g_texture.as_ref(),
+                    normal: &self.normal_g_texture,
+                    specular: &self.specular_g_texture,
+                };
+                let mut render_pass = PassBuilder::gbuffer(
+                    "gbuffer render pass",
+                    &gbuffer,
+                    &self.gbuffer_clear_values,
+                )
+                .begin(encoder);
+
+                render_pass.set_bind_group(0, &self.camera.bind_group, &[]);
+                render_pass.set_bind_group(2, &self.lights.bind_group, &[]);
+                let opaque_objects = self
+                    .objects
+                    .iter()
+                    .filter(|o| !o.assets.material.transparent);
+                for object in opaque_objects.chain([&self.ground]) {
+                    render_pass.set_pipeline(
+                        self.mesh_render_pipeline
+                            .pipeline(object.mesh().front_face, self.shading),
+                    );
+                    render_pass.set_bind_group(
+                        1,
+                        object.material_bind_group(self.material_override),
+                        &[],
+                    );
+                    object.mesh().draw(&mut render_pass);
+                }
+            }
+
+            Pass::Decals => {
+                let gbuffer = GBuffer {
+                    depth: &self.depth_texture,
+                    albedo: &self.albedo_g_texture,
+                    position: self.position_g_texture.as_ref(),
+                    normal: &self.normal_g_texture,
+                    specular: &self.specular_g_texture,
+                };
+                self.decals
+                    .render(renderer, encoder, &gbuffer, &self.camera);
+            }
+
+            Pass::Lighting => {
+                let gbuffer = GBuffer {
+                    depth: &self.depth_texture,
+                    albedo: &self.albedo_g_texture,
+                    position: self.position_g_texture.as_ref(),
+                    normal: &self.normal_g_texture,
+                    specular: &self.specular_g_texture,
+                };
+                match self.render_source {
+                    RenderSource::Final => self.deferred_renderer.light_pass(
+                        renderer,
+                        encoder,
+                        &gbuffer,
+                        &self.lights,
+                        &self.camera,
+                        &self.scene_texture.view,
+                    ),
+                    RenderSource::Matcap => self.matcap.render(
+                        renderer,
+                        encoder,
+                        &self.normal_g_texture,
+                        &self.camera,
+                        &self.scene_texture.view,
+                    ),
+                    RenderSource::ShadedWireframe => {
+                        self.deferred_renderer.light_pass(
+                            renderer,
+                            encoder,
+                            &gbuffer,
+                            &self.lights,
+                            &self.camera,
+                            &self.scene_texture.view,
+                        );
+                        let wireframes = self
+                            .objects
+                            .iter()
+                            .chain([&self.ground])
+                            .map(RenderObject::wireframe);
+                        self.wireframe.render(
+                            renderer,
+                            encoder,
+                            &self.scene_texture.view,
+                            &self.depth_texture.view,
+                            &self.camera,
+                            wireframes,
+                        );
+                    }
+                    RenderSource::Albedo
+                    | RenderSource::Position
+                    | RenderSource::Normal
+                    | RenderSource::Depth
+                    | RenderSource::Coverage => {
+                        let view = match self.render_source {
+                            RenderSource::Albedo => DebugView::Albedo,
+                            RenderSource::Position => {
+                                let (min, max) = self.scene_bounds();
+                                DebugView::Position { min, max }
+                            }
+                            RenderSource::Normal => DebugView::Normal,
+                            RenderSource::Depth => {
+                                // Spread the gray values over the depth of the
+                                // scene, not the whole view frustum.
+                                let (min, max) = self.scene_bounds();
+                                let center = min + (max - min) * 0.5;
+                                let radius = min.distance(max) * 0.5;
+                                let distance = center.distance(camera_position);
+                                DebugView::Depth {
+                                    near: (distance - radius).max(0.0),
+                                    far: distance + radius,
+                                }
+                            }
+                            RenderSource::Coverage => DebugView::Coverage,
+                            RenderSource::Final
+                            | RenderSource::ShadedWireframe
+                            | RenderSource::Matcap => unreachable!("handled above"),
+                        };
+                        self.deferred_renderer.debug_pass(
+                            renderer,
+                            encoder,
+                            &gbuffer,
+                            &self.camera,
+                            view,
+                            &self.scene_texture.view,
+                        );
+                    }
+                }
+            }
+
+            Pass::Reflections => {
+                // The G-buffer debug views show the plane as it is, and the
+                // wireframe view keeps the lines on the plane visible.
+                if !matches!(self.render_source, RenderSource::Final) {
+                    return;
+                }
 
-        output.present();
+                let material_override = self.material_override;
+                let objects = self
+                    .objects
+                    .iter()
+                    .filter(|o| !o.assets.material.transparent)
+                    .map(|o| (o.mesh(), o.material_bind_group(material_override)));
+                self.reflection.render(
+                    renderer,
+                    encoder,
+                    &self.camera,
+                    camera_position,
+                    objects,
+                    &self.mesh_render_pipeline,
+                    self.shading,
+                    &self.deferred_renderer,
+                    &self.lights,
+                    (
+                        self.scene_texture.texture.width(),
+                        self.scene_texture.texture.height(),
+                    ),
+                );
+                self.reflection.composite(
+                    renderer,
+                    encoder,
+                    &self.scene_texture,
+                    &self.depth_texture,
+                    &self.camera,
+                    self.ground.mesh(),
+                );
+            }
+
+            Pass::Transparent => {
+                // Transparent objects are drawn back to front on top of the lit
+                // scene.
+                let mut transparent_objects = self
+                    .objects
+                    .iter()
+                    .filter(|o| o.assets.material.transparent)
+                    .collect::<Vec<_>>();
+                if transparent_objects.is_empty() {
+                    return;
+                }
+                transparent_objects.sort_by(|a, b| {
+                    let a = a.assets.center.distance2(camera_position);
+                    let b = b.assets.center.distance2(camera_position);
+                    b.total_cmp(&a)
+                });
+
+                let mut render_pass = PassBuilder::overlay(
+                    "transparent render pass",
+                    &self.scene_texture.view,
+                    &self.depth_texture.view,
+                )
+                .begin(encoder);
+
+                render_pass.set_pipeline(&self.transparent_render_pipeline.pipeline);
+                render_pass.set_bind_group(0, &self.camera.bind_group, &[]);
+                render_pass.set_bind_group(2, &self.lights.bind_group, &[]);
+                for object in transparent_objects {
+                    render_pass.set_bind_group(1, &object.assets.material.bind_group, &[]);
+                    object.mesh().draw(&mut render_pass);
+                }
+            }
+
+            Pass::Particles => {
+                self.particles.render(
+                    renderer,
+                    encoder,
+                    &self.scene_texture.view,
+                    &self.depth_texture.view,
+                    &self.camera,
+                );
+            }
+
+            Pass::DepthOfField => {
+                self.depth_of_field.render(
+                    renderer,
+                    encoder,
+                    &self.scene_texture,
+                    &self.depth_texture,
+                    &self.camera,
+                );
+            }
+
+            Pass::MotionBlur => {
+                self.motion_blur.render(
+                    renderer,
+                    encoder,
+                    &self.scene_texture,
+                    &self.depth_texture,
+                    &self.camera,
+                );
+            }
+
+            Pass::PostEffects => {
+                let gbuffer = GBuffer {
+                    depth: &self.depth_texture,
+                    albedo: &self.albedo_g_texture,
+                    position: self.position_g_texture.as_ref(),
+                    normal: &self.normal_g_texture,
+                    specular: &self.specular_g_texture,
+                };
+                self.post_effects.render(
+                    renderer,
+                    encoder,
+                    &self.scene_texture,
+                    &gbuffer,
+                    &self.camera,
+                );
+            }
+
+            Pass::Gizmos => {
+                self.gizmos.render(
+                    renderer,
+                    encoder,
+                    &self.scene_texture.view,
+                    &self.depth_texture.view,
+                    &self.camera,
+                );
+            }
+
+            Pass::Upscale => {
+                self.blit
+                    .render(renderer, encoder, &self.scene_texture, surface_view);
+            }
+
+            Pass::Ui => {
+                let surface_config = &renderer.surface_config;
+                let text_shapes = self.gizmos.text_shapes(
+                    &self.ui.fonts,
+                    &self.camera,
+                    [surface_config.width as f32, surface_config.height as f32],
+                );
+                self.ui.push_shapes(text_shapes);
+
+                self.ui.push_shapes(self.controls_panel.shapes());
+                let clip_rect = self.controls_panel.clip_rect();
+
+                for (id, slider) in self.sliders.iter_mut() {
+                    let mut shapes = slider.shapes();
+                    for shape in &mut shapes {
+                        shape.clip_rect = clip_rect;
+                    }
+                    self.ui.push_shapes(shapes);
+
+                    if self.focused_slider == Some(id) {
+                        self.ui.push_shape(epaint::ClippedShape {
+                            clip_rect,
+                            shape: epaint::Shape::rect_stroke(
+                                slider.bounds.shrink(2.0),
+                                epaint::Rounding::same(4.0),
+                                epaint::Stroke::new(2.0, epaint::Color32::YELLOW),
+                            ),
+                        });
+                    }
+                }
+
+                self.ui.render(renderer, encoder, surface_view);
+            }
+        }
     }
 }
@@ -1,9 +1,18 @@
-use cgmath::SquareMatrix;
+use cgmath::{InnerSpace, SquareMatrix};
 use wgpu::util::DeviceExt;
 
 use crate::Renderer;
 
 pub struct Camera {
+    pub projection_matrix: cgmath::Matrix4<f32>,
+    pub view_matrix: cgmath::Matrix4<f32>,
+    /// The projection and view of the previous call to
+    /// [Camera::set_matrices], to reproject positions to where they were on
+    /// screen in the previous frame.
+    pub previous_view_projection: cgmath::Matrix4<f32>,
+    /// Whether [Camera::set_matrices] has been called yet.
+    has_matrices: bool,
+
     buffer: wgpu::Buffer,
     pub bind_group_layout: wgpu::BindGroupLayout,
     pub bind_group: wgpu::BindGroup,
@@ -16,18 +25,24 @@ struct GpuCamera {
     view_matrix: [[f32; 4]; 4],
     position: [f32; 3],
     _padding: f32,
+    /// Maps clip space back to world space, to reconstruct positions from
+    /// depth.
+    inverse_view_projection: [[f32; 4]; 4],
+    previous_view_projection: [[f32; 4]; 4],
 }
 
 impl Camera {
     pub fn new(renderer: &Renderer) -> Self {
-        let projection_matrix = cgmath::Matrix4::identity().into();
-        let view_matrix = cgmath::Matrix4::identity().into();
+        let projection_matrix = cgmath::Matrix4::identity();
+        let view_matrix = cgmath::Matrix4::identity();
 
         let data = GpuCamera {
-            projection_matrix,
-            view_matrix,
+            projection_matrix: projection_matrix.into(),
+            view_matrix: view_matrix.into(),
             position: [0.0, 0.0, 0.0],
             _padding: 0.0,
+            inverse_view_projection: cgmath::Matrix4::identity().into(),
+            previous_view_projection: cgmath::Matrix4::identity().into(),
         };
 
         let buffer = renderer
@@ -67,6 +82,10 @@ impl Camera {
             });
 
         Self {
+            projection_matrix,
+            view_matrix,
+            previous_view_projection: projection_matrix * view_matrix,
+            has_matrices: false,
             buffer,
             bind_group_layout,
             bind_group,
@@ -80,15 +99,86 @@ impl Camera {
         view_matrix: cgmath::Matrix4<f32>,
         camera_position: cgmath::Point3<f32>,
     ) {
+        let view_projection = projection_matrix * view_matrix;
+        // Before the first frame there is no previous frame to blur towards.
+        self.previous_view_projection = if self.has_matrices {
+            self.projection_matrix * self.view_matrix
+        } else {
+            view_projection
+        };
+        self.has_matrices = true;
+
+        self.projection_matrix = projection_matrix;
+        self.view_matrix = view_matrix;
+
         let gpu_camera = GpuCamera {
             projection_matrix: projection_matrix.into(),
             view_matrix: view_matrix.into(),
             position: camera_position.into(),
             _padding: 0.0,
+            inverse_view_projection: view_projection
+                .invert()
+                .unwrap_or(cgmath::Matrix4::identity())
+                .into(),
+            previous_view_projection: self.previous_view_projection.into(),
         };
 
         renderer
             .queue
             .write_buffer(&self.buffer, 0, bytemuck::cast_slice(&[gpu_camera]));
     }
+
+    /// Project a world space position to pixel coordinates on a screen of
+    /// `screen_size`, with the origin at the top left. Returns `None` if the
+    /// position is behind the camera or outside the view.
+    pub fn world_to_screen(
+        &self,
+        position: cgmath::Point3<f32>,
+        screen_size: [f32; 2],
+    ) -> Option<[f32; 2]> {
+        let ndc = self.world_to_ndc(position)?;
+        let [width, height] = screen_size;
+        Some([(ndc.x + 1.0) * 0.5 * width, (1.0 - ndc.y) * 0.5 * height])
+    }
+
+    /// Whether any part of the sphere at `center` with `radius` may be in
+    /// view. It is tested against the planes of the view frustum, so spheres
+    /// just outside a corner of the frustum count as in view.
+    pub fn is_sphere_in_view(&self, center: cgmath::Point3<f32>, radius: f32) -> bool {
+        let view_projection = self.projection_matrix * self.view_matrix;
+        let row = |i: usize| {
+            cgmath::vec4(
+                view_projection.x[i],
+                view_projection.y[i],
+                view_projection.z[i],
+                view_projection.w[i],
+            )
+        };
+        let (x, y, z, w) = (row(0), row(1), row(2), row(3));
+
+        let center = center.to_homogeneous();
+        [w + x, w - x, w + y, w - y, z, w - z]
+            .into_iter()
+            .all(|plane| {
+                let length = plane.truncate().magnitude();
+                length == 0.0 || plane.dot(center) / length >= -radius
+            })
+    }
+
+    /// Project a world space position to normalized device coordinates, with
+    /// depth in 0..1. Returns `None` if the position is behind the camera or
+    /// outside the view.
+    pub fn world_to_ndc(&self, position: cgmath::Point3<f32>) -> Option<cgmath::Vector3<f32>> {
+        let clip = self.projection_matrix * self.view_matrix * position.to_homogeneous();
+        if clip.w <= 0.0 {
+            return None;
+        }
+
+        let ndc = clip.truncate() / clip.w;
+        if ndc.x.abs() > 1.0 || ndc.y.abs() > 1.0 || !(0.0..=1.0).contains(&ndc.z) {
+            return None;
+        }
+
+        Some(ndc)
+    }
 }
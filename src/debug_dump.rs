@@ -0,0 +1,109 @@
+use crate::{texture::Texture, Renderer};
+
+/// Save the contents of a render target to a PNG file. Color targets are saved
+/// as is, float targets are normalized to the range of values they contain
+/// and depth is saved as a normalized grayscale image.
+pub fn save_texture(
+    renderer: &Renderer,
+    texture: &Texture,
+    path: impl AsRef<std::path::Path>,
+) -> Result<(), image::ImageError> {
+    let format = texture.texture.format();
+    if format
+        .block_copy_size(Some(wgpu::TextureAspect::DepthOnly))
+        .is_none()
+        && format.is_depth_stencil_format()
+    {
+        eprintln!("Can not save texture with format {format:?}, its depth can't be copied");
+        return Ok(());
+    }
+
+    let width = texture.texture.width();
+    let height = texture.texture.height();
+    let pixels = texture.read_pixels(renderer);
+
+    match format {
+        wgpu::TextureFormat::Rgba8Unorm | wgpu::TextureFormat::Rgba8UnormSrgb => {
+            image::save_buffer(path, &pixels, width, height, image::ColorType::Rgba8)
+        }
+
+        wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb => {
+            let pixels = bgra_to_rgba(pixels);
+            image::save_buffer(path, &pixels, width, height, image::ColorType::Rgba8)
+        }
+
+        wgpu::TextureFormat::Rgba16Float => {
+            let values = pixels
+                .chunks_exact(2)
+                .map(|c| f16_to_f32(u16::from_le_bytes([c[0], c[1]])))
+                .collect::<Vec<_>>();
+            let pixels = normalize_channels::<4>(&values, |v| v.is_finite());
+            image::save_buffer(path, &pixels, width, height, image::ColorType::Rgba8)
+        }
+
+        wgpu::TextureFormat::Depth32Float | wgpu::TextureFormat::Depth32FloatStencil8 => {
+            let values = pixels
+                .chunks_exact(4)
+                .map(|c| f32::from_le_bytes([c[0], c[1], c[2], c[3]]))
+                .collect::<Vec<_>>();
+            // Leave the background at the far plane out of the range, so the
+            // geometry uses the full range of gray values.
+            let pixels = normalize_channels::<1>(&values, |v| v < 1.0);
+            image::save_buffer(path, &pixels, width, height, image::ColorType::L8)
+        }
+
+        format => {
+            eprintln!("Can not save texture with format {format:?}");
+            Ok(())
+        }
+    }
+}
+
+pub fn bgra_to_rgba(mut pixels: Vec<u8>) -> Vec<u8> {
+    for pixel in pixels.chunks_exact_mut(4) {
+        pixel.swap(0, 2);
+    }
+    pixels
+}
+
+/// Map each of the `N` interleaved channels from the range of values it
+/// contains to `0..=255`. Only values accepted by `include` are used to find the
+/// range, the rest are clamped.
+fn normalize_channels<const N: usize>(values: &[f32], include: impl Fn(f32) -> bool) -> Vec<u8> {
+    let mut min = [f32::MAX; N];
+    let mut max = [f32::MIN; N];
+    for pixel in values.chunks_exact(N) {
+        for (i, value) in pixel.iter().enumerate() {
+            if include(*value) {
+                min[i] = min[i].min(*value);
+                max[i] = max[i].max(*value);
+            }
+        }
+    }
+
+    values
+        .chunks_exact(N)
+        .flat_map(|pixel| {
+            pixel.iter().enumerate().map(move |(i, value)| {
+                let range = max[i] - min[i];
+                if range <= 0.0 || !value.is_finite() {
+                    return if *value >= max[i] { 255 } else { 0 };
+                }
+                (((value - min[i]) / range).clamp(0.0, 1.0) * 255.0) as u8
+            })
+        })
+        .collect()
+}
+
+fn f16_to_f32(bits: u16) -> f32 {
+    let sign = if bits & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = ((bits >> 10) & 0x1f) as i32;
+    let mantissa = (bits & 0x3ff) as f32;
+
+    match exponent {
+        0 => sign * mantissa * 2.0_f32.powi(-24),
+        0x1f if mantissa == 0.0 => sign * f32::INFINITY,
+        0x1f => f32::NAN,
+        _ => sign * (1.0 + mantissa / 1024.0) * 2.0_f32.powi(exponent - 15),
+    }
+}
@@ -0,0 +1,339 @@
+use cgmath::SquareMatrix;
+use wgpu::util::DeviceExt;
+
+use crate::{
+    camera::Camera,
+    gbuffer::{self, GBuffer},
+    pass_builder::PassBuilder,
+    texture::Texture,
+    Renderer,
+};
+
+#[derive(Clone, Copy, bytemuck::NoUninit)]
+#[repr(C)]
+struct GpuDecal {
+    model_matrix: [[f32; 4]; 4],
+    inverse_model_matrix: [[f32; 4]; 4],
+}
+
+struct Decal {
+    _buffer: wgpu::Buffer,
+    _texture: Texture,
+    bind_group: wgpu::BindGroup,
+}
+
+/// Projects textures onto the surfaces stored in the G-buffer. Each decal is a
+/// unit box, centered on the origin, placed with a transform. The decal texture
+/// is projected down the box's local Y axis and blended into the albedo target,
+/// and the box's local Y axis is blended into the normal target with the same
+/// weight, so lighting treats the decal as a flat sticker.
+pub struct Decals {
+    pipeline: wgpu::RenderPipeline,
+    vertex_buffer: wgpu::Buffer,
+    vertex_count: u32,
+    gbuffer_bind_group_layout: wgpu::BindGroupLayout,
+    decal_bind_group_layout: wgpu::BindGroupLayout,
+    decals: Vec<Decal>,
+    /// Bound in place of the position target when positions are
+    /// reconstructed from depth.
+    placeholder_position: Texture,
+}
+
+impl Decals {
+    /// Set `store_position` to `false` if the G-buffer has no position target,
+    /// so positions are reconstructed from depth.
+    pub fn new(
+        renderer: &Renderer,
+        camera: &Camera,
+        albedo_format: wgpu::TextureFormat,
+        normal_format: wgpu::TextureFormat,
+        store_position: bool,
+    ) -> Self {
+        let Renderer { device, .. } = renderer;
+
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("decals shader module"),
+            source: wgpu::ShaderSource::Wgsl(std::borrow::Cow::Borrowed(include_str!(
+                "decals.wgsl"
+            ))),
+        });
+
+        let gbuffer_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("decals gbuffer bind group layout"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                ],
+            });
+
+        let decal_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("decal bind group layout"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 2,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                        count: None,
+                    },
+                ],
+            });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("decals pipeline layout"),
+            bind_group_layouts: &[
+                &camera.bind_group_layout,
+                &gbuffer_bind_group_layout,
+                &decal_bind_group_layout,
+            ],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("decals render pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &module,
+                entry_point: "vertex_main",
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+                buffers: &[wgpu::VertexBufferLayout {
+                    array_stride: std::mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
+                    step_mode: wgpu::VertexStepMode::Vertex,
+                    attributes: &wgpu::vertex_attr_array![0 => Float32x3],
+                }],
+            },
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                front_face: wgpu::FrontFace::Ccw,
+                // Only draw the inside of the box, so decals still render when
+                // the camera is inside of it.
+                cull_mode: Some(wgpu::Face::Front),
+                ..Default::default()
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            fragment: Some(wgpu::FragmentState {
+                module: &module,
+                entry_point: "fragment_main",
+                compilation_options: wgpu::PipelineCompilationOptions {
+                    constants: &gbuffer::shader_constants(
+                        renderer.limits_profile,
+                        renderer.normal_space,
+                        renderer.depth_convention,
+                        store_position,
+                    ),
+                    ..Default::default()
+                },
+                // Only the colors are blended, the alphas keep the ambient
+                // occlusion and the coverage of the surfaces.
+                targets: &[
+                    Some(wgpu::ColorTargetState {
+                        format: albedo_format,
+                        blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                        write_mask: wgpu::ColorWrites::COLOR,
+                    }),
+                    Some(wgpu::ColorTargetState {
+                        format: normal_format,
+                        blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                        write_mask: wgpu::ColorWrites::COLOR,
+                    }),
+                ],
+            }),
+            multiview: None,
+            cache: None,
+        });
+
+        let vertices = unit_box_vertices();
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("decals vertex buffer"),
+            contents: bytemuck::cast_slice(&vertices),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+
+        Self {
+            pipeline,
+            vertex_buffer,
+            vertex_count: vertices.len() as u32,
+            gbuffer_bind_group_layout,
+            decal_bind_group_layout,
+            decals: vec![],
+            placeholder_position: Texture::from_color(
+                renderer,
+                [0, 0, 0, 0],
+                wgpu::TextureFormat::Rgba8Unorm,
+            ),
+        }
+    }
+
+    /// Remove all the decals.
+    pub fn clear(&mut self) {
+        self.decals.clear();
+    }
+
+    pub fn add(&mut self, renderer: &Renderer, transform: cgmath::Matrix4<f32>, texture: Texture) {
+        let Some(inverse_transform) = transform.invert() else {
+            eprintln!("Ignoring decal with a transform that can not be inverted.");
+            return;
+        };
+
+        let data = GpuDecal {
+            model_matrix: transform.into(),
+            inverse_model_matrix: inverse_transform.into(),
+        };
+
+        let buffer = renderer
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("decal buffer"),
+                contents: bytemuck::cast_slice(&[data]),
+                usage: wgpu::BufferUsages::UNIFORM,
+            });
+
+        let bind_group = renderer
+            .device
+            .create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("decal bind group"),
+                layout: &self.decal_bind_group_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: buffer.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::TextureView(&texture.view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: wgpu::BindingResource::Sampler(&texture.sampler),
+                    },
+                ],
+            });
+
+        self.decals.push(Decal {
+            _buffer: buffer,
+            _texture: texture,
+            bind_group,
+        });
+    }
+
+    /// Blend the decals into the albedo and normal targets of `gbuffer`. The
+    /// depth and position targets are only read.
+    pub fn render(
+        &self,
+        renderer: &Renderer,
+        encoder: &mut wgpu::CommandEncoder,
+        gbuffer: &GBuffer,
+        camera: &Camera,
+    ) {
+        if self.decals.is_empty() {
+            return;
+        }
+
+        let position_texture = gbuffer.position.unwrap_or(&self.placeholder_position);
+
+        let gbuffer_bind_group = renderer
+            .device
+            .create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("decals gbuffer bind group"),
+                layout: &self.gbuffer_bind_group_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(&gbuffer.depth.view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::TextureView(&position_texture.view),
+                    },
+                ],
+            });
+
+        let mut render_pass = PassBuilder::composite("decals render pass", &gbuffer.albedo.view)
+            .with_loaded_color(&gbuffer.normal.view)
+            .begin(encoder);
+
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        render_pass.set_bind_group(0, &camera.bind_group, &[]);
+        render_pass.set_bind_group(1, &gbuffer_bind_group, &[]);
+        for decal in self.decals.iter() {
+            render_pass.set_bind_group(2, &decal.bind_group, &[]);
+            render_pass.draw(0..self.vertex_count, 0..1);
+        }
+    }
+}
+
+/// Triangles of a unit box centered on the origin, wound counter clockwise
+/// when seen from the outside.
+fn unit_box_vertices() -> Vec<[f32; 3]> {
+    let mut vertices = Vec::with_capacity(36);
+    for axis in 0..3 {
+        for sign in [1.0, -1.0] {
+            let mut normal = [0.0_f32; 3];
+            normal[axis] = sign * 0.5;
+
+            // Pick the two tangents so that `u x v` points along the normal.
+            let mut u = [0.0_f32; 3];
+            let mut v = [0.0_f32; 3];
+            u[(axis + 1) % 3] = 0.5;
+            v[(axis + 2) % 3] = 0.5;
+            if sign < 0.0 {
+                std::mem::swap(&mut u, &mut v);
+            }
+
+            let corner = |a: f32, b: f32| -> [f32; 3] {
+                std::array::from_fn(|i| normal[i] + u[i] * a + v[i] * b)
+            };
+
+            vertices.extend_from_slice(&[
+                corner(-1.0, -1.0),
+                corner(1.0, -1.0),
+                corner(1.0, 1.0),
+                corner(-1.0, -1.0),
+                corner(1.0, 1.0),
+                corner(-1.0, 1.0),
+            ]);
+        }
+    }
+    vertices
+}
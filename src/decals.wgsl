@@ -0,0 +1,90 @@
+struct Camera {
+    projection_matrix: mat4x4<f32>,
+    view_matrix: mat4x4<f32>,
+    position: vec3<f32>,
+    inverse_view_projection: mat4x4<f32>,
+}
+
+@group(0) @binding(0) var<uniform> camera: Camera;
+
+// A float view of the depth target, so GL can read it too. Depth is in `.r`.
+@group(1) @binding(0) var t_depth: texture_2d<f32>;
+@group(1) @binding(1) var t_position: texture_2d<f32>;
+
+// Set when there is no position target, so positions are reconstructed from
+// depth.
+override RECONSTRUCT_POSITION: bool = false;
+// The depth the depth buffer is cleared to, left wherever nothing was drawn.
+override FAR_DEPTH: f32 = 1.0;
+// Set when the normal target can't store negative values, so normals are
+// packed into the 0..1 range.
+override PACK_NORMALS: bool = false;
+// Set when normals are stored in view space instead of world space.
+override VIEW_SPACE_NORMALS: bool = false;
+
+struct Decal {
+    model_matrix: mat4x4<f32>,
+    inverse_model_matrix: mat4x4<f32>,
+}
+
+@group(2) @binding(0) var<uniform> decal: Decal;
+@group(2) @binding(1) var t_decal: texture_2d<f32>;
+@group(2) @binding(2) var s_decal: sampler;
+
+@vertex
+fn vertex_main(@location(0) position: vec3<f32>) -> @builtin(position) vec4<f32> {
+    return camera.projection_matrix * camera.view_matrix * decal.model_matrix * vec4(position, 1.0);
+}
+
+struct FragmentOutput {
+    @location(0) albedo: vec4<f32>,
+    @location(1) normal: vec4<f32>,
+}
+
+// The normal of the decal, the local Y axis of the box in the space of the
+// normal target.
+fn decal_normal() -> vec3<f32> {
+    // Normals transform with the inverse transpose, so non uniform scales
+    // don't tilt them.
+    let inverse = decal.inverse_model_matrix;
+    var normal = normalize(vec3(inverse[0].y, inverse[1].y, inverse[2].y));
+    if VIEW_SPACE_NORMALS {
+        normal = normalize((camera.view_matrix * vec4(normal, 0.0)).xyz);
+    }
+    if PACK_NORMALS {
+        normal = normal * 0.5 + 0.5;
+    }
+    return normal;
+}
+
+@fragment
+fn fragment_main(@builtin(position) position: vec4<f32>) -> FragmentOutput {
+    let fullscreen_uv = vec2<i32>(floor(position.xy));
+
+    // Nothing to project onto.
+    let depth = textureLoad(t_depth, fullscreen_uv, 0).r;
+    if depth == FAR_DEPTH {
+        discard;
+    }
+
+    // Move the surface under this pixel into the space of the decal box.
+    var world_position: vec3<f32>;
+    if RECONSTRUCT_POSITION {
+        let uv = (vec2<f32>(fullscreen_uv) + 0.5) / vec2<f32>(textureDimensions(t_depth));
+        let ndc = vec4(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, depth, 1.0);
+        let clip_position = camera.inverse_view_projection * ndc;
+        world_position = clip_position.xyz / clip_position.w;
+    } else {
+        world_position = textureLoad(t_position, fullscreen_uv, 0).xyz;
+    }
+    let local_position = (decal.inverse_model_matrix * vec4(world_position, 1.0)).xyz;
+    if any(abs(local_position) > vec3(0.5)) {
+        discard;
+    }
+
+    // Project the decal texture down the local Y axis. Its alpha blends both
+    // the color and the normal of the decal over the surface.
+    let tex_coord = local_position.xz + vec2(0.5);
+    let color = textureSampleLevel(t_decal, s_decal, tex_coord, 0.0);
+    return FragmentOutput(color, vec4(decal_normal(), color.a));
+}
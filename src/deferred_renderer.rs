@@ -0,0 +1,355 @@
+use wgpu::util::DeviceExt;
+
+use crate::{
+    camera::Camera,
+    gbuffer::{self, GBuffer},
+    lights::Lights,
+    pass_builder::PassBuilder,
+    texture::Texture,
+    Renderer,
+};
+
+/// The G-buffer target shown by [DeferredRenderer::debug_pass].
+#[derive(Clone, Copy, Debug)]
+pub enum DebugView {
+    Albedo,
+    /// World positions, with the box from `min` to `max` mapped to 0..1.
+    Position {
+        min: cgmath::Point3<f32>,
+        max: cgmath::Point3<f32>,
+    },
+    /// World normals, mapped from -1..1 to 0..1.
+    Normal,
+    /// Distance from the camera, with `near` black and `far` white.
+    Depth {
+        near: f32,
+        far: f32,
+    },
+    /// White where geometry was drawn into the G-buffer, black elsewhere.
+    Coverage,
+}
+
+#[derive(Clone, Copy, bytemuck::NoUninit)]
+#[repr(C)]
+struct GpuDebugSettings {
+    bounds_min: [f32; 4],
+    bounds_max: [f32; 4],
+    view: u32,
+    _padding: [u32; 3],
+}
+
+impl From<DebugView> for GpuDebugSettings {
+    fn from(value: DebugView) -> Self {
+        let (view, min, max) = match value {
+            DebugView::Albedo => (0, [0.0; 3], [1.0; 3]),
+            DebugView::Position { min, max } => (1, min.into(), max.into()),
+            DebugView::Normal => (2, [0.0; 3], [1.0; 3]),
+            DebugView::Depth { near, far } => (3, [near; 3], [far; 3]),
+            DebugView::Coverage => (4, [0.0; 3], [1.0; 3]),
+        };
+        Self {
+            bounds_min: [min[0], min[1], min[2], 0.0],
+            bounds_max: [max[0], max[1], max[2], 0.0],
+            view,
+            _padding: [0; 3],
+        }
+    }
+}
+
+/// The lighting half of the deferred renderer. It reads a G-buffer filled by
+/// any geometry pass and writes the lit scene to an output target, so it can
+/// be composited into other render graphs.
+pub struct DeferredRenderer {
+    light_pipeline: wgpu::RenderPipeline,
+    debug_pipeline: wgpu::RenderPipeline,
+    bind_group_layout: wgpu::BindGroupLayout,
+    debug_buffer: wgpu::Buffer,
+    /// Bound in place of the position target when positions are
+    /// reconstructed from depth.
+    placeholder_position: Texture,
+}
+
+impl DeferredRenderer {
+    /// Set `store_position` to `false` if the G-buffer has no position target,
+    /// so positions are reconstructed from depth.
+    pub fn new(
+        renderer: &Renderer,
+        camera: &Camera,
+        lights: &Lights,
+        output_format: wgpu::TextureFormat,
+        store_position: bool,
+    ) -> Self {
+        let Renderer { device, .. } = renderer;
+
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("fullscreen shader module"),
+            source: wgpu::ShaderSource::Wgsl(std::borrow::Cow::Borrowed(include_str!(
+                "fullscreen.wgsl"
+            ))),
+        });
+
+        let texture_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        };
+        let sampler_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+            count: None,
+        };
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("fullscreen bind group layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                texture_entry(1),
+                sampler_entry(2),
+                texture_entry(3),
+                sampler_entry(4),
+                texture_entry(5),
+                sampler_entry(6),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 7,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                texture_entry(8),
+            ],
+        });
+
+        let debug_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("debug settings buffer"),
+            contents: bytemuck::cast_slice(&[GpuDebugSettings::from(DebugView::Albedo)]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("fullscreen pipeline layout"),
+            bind_group_layouts: &[
+                &bind_group_layout,
+                &camera.bind_group_layout,
+                &lights.bind_group_layout,
+            ],
+            push_constant_ranges: &[],
+        });
+
+        // The debug view only shows the G-buffer, so it doesn't need the
+        // lights. The camera is used to reconstruct positions.
+        let debug_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("debug pipeline layout"),
+                bind_group_layouts: &[&bind_group_layout, &camera.bind_group_layout],
+                push_constant_ranges: &[],
+            });
+
+        let constants = gbuffer::shader_constants(
+            renderer.limits_profile,
+            renderer.normal_space,
+            renderer.depth_convention,
+            store_position,
+        );
+
+        let create_pipeline = |label, layout, entry_point| {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(label),
+                layout: Some(layout),
+                vertex: wgpu::VertexState {
+                    module: &module,
+                    entry_point: "vertex_main",
+                    compilation_options: wgpu::PipelineCompilationOptions::default(),
+                    buffers: &[],
+                },
+                primitive: wgpu::PrimitiveState::default(),
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState::default(),
+                fragment: Some(wgpu::FragmentState {
+                    module: &module,
+                    entry_point,
+                    compilation_options: wgpu::PipelineCompilationOptions {
+                        constants: &constants,
+                        ..Default::default()
+                    },
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format: output_format,
+                        blend: Some(wgpu::BlendState::REPLACE),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                }),
+                multiview: None,
+                cache: None,
+            })
+        };
+
+        let light_pipeline = create_pipeline(
+            "fullscreen render pipeline",
+            &pipeline_layout,
+            "fragment_main",
+        );
+        let debug_pipeline = create_pipeline(
+            "debug render pipeline",
+            &debug_pipeline_layout,
+            "fragment_debug",
+        );
+
+        Self {
+            light_pipeline,
+            debug_pipeline,
+            bind_group_layout,
+            debug_buffer,
+            placeholder_position: Texture::from_color(
+                renderer,
+                [0, 0, 0, 0],
+                wgpu::TextureFormat::Rgba8Unorm,
+            ),
+        }
+    }
+
+    /// Light the contents of `gbuffer` and write the result to `output`,
+    /// clearing it first.
+    pub fn light_pass(
+        &self,
+        renderer: &Renderer,
+        encoder: &mut wgpu::CommandEncoder,
+        gbuffer: &GBuffer,
+        lights: &Lights,
+        camera: &Camera,
+        output: &wgpu::TextureView,
+    ) {
+        let bind_group = self.create_bind_group(renderer, gbuffer);
+        self.draw(
+            encoder,
+            &self.light_pipeline,
+            &[&bind_group, &camera.bind_group, &lights.bind_group],
+            output,
+        );
+    }
+
+    /// Write a single G-buffer target, picked by `view`, to `output` without
+    /// any lighting. Its values are remapped so they can be displayed.
+    pub fn debug_pass(
+        &self,
+        renderer: &Renderer,
+        encoder: &mut wgpu::CommandEncoder,
+        gbuffer: &GBuffer,
+        camera: &Camera,
+        view: DebugView,
+        output: &wgpu::TextureView,
+    ) {
+        renderer.queue.write_buffer(
+            &self.debug_buffer,
+            0,
+            bytemuck::cast_slice(&[GpuDebugSettings::from(view)]),
+        );
+
+        let bind_group = self.create_bind_group(renderer, gbuffer);
+        self.draw(
+            encoder,
+            &self.debug_pipeline,
+            &[&bind_group, &camera.bind_group],
+            output,
+        );
+    }
+
+    fn create_bind_group(&self, renderer: &Renderer, gbuffer: &GBuffer) -> wgpu::BindGroup {
+        let GBuffer {
+            depth,
+            albedo,
+            position,
+            normal,
+            specular,
+        } = gbuffer;
+        let position = position.unwrap_or(&self.placeholder_position);
+
+        renderer
+            .device
+            .create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("fullscreen bind group"),
+                layout: &self.bind_group_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(&depth.view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::TextureView(&albedo.view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: wgpu::BindingResource::Sampler(&albedo.sampler),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 3,
+                        resource: wgpu::BindingResource::TextureView(&position.view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 4,
+                        resource: wgpu::BindingResource::Sampler(&position.sampler),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 5,
+                        resource: wgpu::BindingResource::TextureView(&normal.view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 6,
+                        resource: wgpu::BindingResource::Sampler(&normal.sampler),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 7,
+                        resource: self.debug_buffer.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 8,
+                        resource: wgpu::BindingResource::TextureView(&specular.view),
+                    },
+                ],
+            })
+    }
+
+    fn draw(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        pipeline: &wgpu::RenderPipeline,
+        bind_groups: &[&wgpu::BindGroup],
+        output: &wgpu::TextureView,
+    ) {
+        let mut render_pass = PassBuilder::new("fullscreen render pass")
+            .with_color(
+                output,
+                wgpu::LoadOp::Clear(wgpu::Color {
+                    r: 0.1,
+                    g: 0.2,
+                    b: 0.3,
+                    a: 1.0,
+                }),
+            )
+            .begin(encoder);
+
+        render_pass.set_pipeline(pipeline);
+        for (index, bind_group) in bind_groups.iter().enumerate() {
+            render_pass.set_bind_group(index as u32, bind_group, &[]);
+        }
+        render_pass.draw(0..3, 0..1);
+    }
+}
@@ -0,0 +1,255 @@
+use crate::{
+    camera::Camera,
+    pass_builder::PassBuilder,
+    texture::{create_fullscreen_texture, Texture},
+    Renderer,
+};
+
+#[derive(Clone, Copy, bytemuck::NoUninit)]
+#[repr(C)]
+struct GpuSettings {
+    focus_distance: f32,
+    aperture: f32,
+    max_blur: f32,
+    _padding: f32,
+    direction: [f32; 2],
+    _padding2: [f32; 2],
+}
+
+/// Blurs the scene away from a focus distance, like the shallow depth of
+/// field of a camera lens. The blur radius of each pixel comes from its
+/// distance to the focus plane, and the blur is done in a horizontal and a
+/// vertical pass.
+pub struct DepthOfField {
+    pipeline: wgpu::RenderPipeline,
+    bind_group_layout: wgpu::BindGroupLayout,
+    /// One buffer for each direction of the blur.
+    settings_buffers: [wgpu::Buffer; 2],
+    /// Holds the result of the horizontal pass. Created to match the size of
+    /// the source.
+    intermediate: Option<Texture>,
+
+    /// The distance from the camera, along the view direction, that is sharp.
+    pub focus_distance: f32,
+    /// How quickly things get blurry away from the focus distance.
+    pub aperture: f32,
+    /// The largest blur radius in pixels.
+    pub max_blur: f32,
+}
+
+impl DepthOfField {
+    pub fn new(renderer: &Renderer, camera: &Camera, format: wgpu::TextureFormat) -> Self {
+        let Renderer { device, .. } = renderer;
+
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("depth of field shader module"),
+            source: wgpu::ShaderSource::Wgsl(std::borrow::Cow::Borrowed(include_str!("dof.wgsl"))),
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("depth of field bind group layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("depth of field pipeline layout"),
+            bind_group_layouts: &[&bind_group_layout, &camera.bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("depth of field render pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &module,
+                entry_point: "vertex_main",
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+                buffers: &[],
+            },
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            fragment: Some(wgpu::FragmentState {
+                module: &module,
+                entry_point: "fragment_main",
+                compilation_options: wgpu::PipelineCompilationOptions {
+                    constants: &renderer.depth_convention.shader_constants(),
+                    ..Default::default()
+                },
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            multiview: None,
+            cache: None,
+        });
+
+        let settings_buffers = ["horizontal", "vertical"].map(|direction| {
+            device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some(&format!("depth of field {direction} settings buffer")),
+                size: std::mem::size_of::<GpuSettings>() as wgpu::BufferAddress,
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            })
+        });
+
+        Self {
+            pipeline,
+            bind_group_layout,
+            settings_buffers,
+            intermediate: None,
+            focus_distance: 5.0,
+            aperture: 0.5,
+            max_blur: 8.0,
+        }
+    }
+
+    /// Blur `scene` in place, using `depth` for the distance of each pixel.
+    pub fn render(
+        &mut self,
+        renderer: &Renderer,
+        encoder: &mut wgpu::CommandEncoder,
+        scene: &Texture,
+        depth: &Texture,
+        camera: &Camera,
+    ) {
+        let width = scene.texture.width();
+        let height = scene.texture.height();
+        let intermediate = match self.intermediate.take() {
+            Some(texture)
+                if texture.texture.width() == width && texture.texture.height() == height =>
+            {
+                texture
+            }
+            _ => create_fullscreen_texture(
+                renderer,
+                width,
+                height,
+                scene.texture.format(),
+                wgpu::FilterMode::Linear,
+                "depth of field texture",
+            ),
+        };
+
+        let directions = [[1.0 / width as f32, 0.0], [0.0, 1.0 / height as f32]];
+        for (buffer, direction) in self.settings_buffers.iter().zip(directions) {
+            let settings = GpuSettings {
+                focus_distance: self.focus_distance,
+                aperture: self.aperture,
+                max_blur: self.max_blur,
+                _padding: 0.0,
+                direction,
+                _padding2: [0.0; 2],
+            };
+            renderer
+                .queue
+                .write_buffer(buffer, 0, bytemuck::cast_slice(&[settings]));
+        }
+
+        let [horizontal, vertical] = &self.settings_buffers;
+        self.blur(
+            renderer,
+            encoder,
+            scene,
+            depth,
+            horizontal,
+            &intermediate.view,
+            camera,
+        );
+        self.blur(
+            renderer,
+            encoder,
+            &intermediate,
+            depth,
+            vertical,
+            &scene.view,
+            camera,
+        );
+
+        self.intermediate = Some(intermediate);
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn blur(
+        &self,
+        renderer: &Renderer,
+        encoder: &mut wgpu::CommandEncoder,
+        source: &Texture,
+        depth: &Texture,
+        settings: &wgpu::Buffer,
+        target: &wgpu::TextureView,
+        camera: &Camera,
+    ) {
+        let bind_group = renderer
+            .device
+            .create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("depth of field bind group"),
+                layout: &self.bind_group_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(&source.view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::Sampler(&source.sampler),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: wgpu::BindingResource::TextureView(&depth.view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 3,
+                        resource: settings.as_entire_binding(),
+                    },
+                ],
+            });
+
+        let mut render_pass =
+            PassBuilder::composite("depth of field render pass", target).begin(encoder);
+
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &bind_group, &[]);
+        render_pass.set_bind_group(1, &camera.bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
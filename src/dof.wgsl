@@ -0,0 +1,104 @@
+@group(0) @binding(0) var t_color: texture_2d<f32>;
+@group(0) @binding(1) var s_color: sampler;
+// The depth target as an unfilterable float texture, with the depth in `.r`.
+@group(0) @binding(2) var t_depth: texture_2d<f32>;
+
+struct Settings {
+    // Distance from the camera along the view direction that is in focus.
+    focus_distance: f32,
+    // Scales how quickly things blur away from the focus distance.
+    aperture: f32,
+    // The largest blur radius, in pixels.
+    max_blur: f32,
+    _padding: f32,
+    // One pixel along the direction of this pass, in texture coordinates.
+    direction: vec2<f32>,
+}
+@group(0) @binding(3) var<uniform> settings: Settings;
+
+struct Camera {
+    projection_matrix: mat4x4<f32>,
+    view_matrix: mat4x4<f32>,
+    position: vec3<f32>,
+    inverse_view_projection: mat4x4<f32>,
+}
+@group(1) @binding(0) var<uniform> camera: Camera;
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) tex_coord: vec2<f32>,
+};
+
+@vertex
+fn vertex_main(
+    @builtin(vertex_index) vertex_index: u32
+) -> VertexOutput {
+    // Create a fullscreen texture.
+    let tex_coord = vec2<f32>(
+        f32(vertex_index >> 1u),
+        f32(vertex_index & 1u)
+    ) * 2.0;
+    let position = vec4<f32>(
+        tex_coord * vec2<f32>(2.0, -2.0) + vec2<f32>(-1.0, 1.0),
+        0.0,
+        1.0
+    );
+
+    return VertexOutput(position, tex_coord);
+}
+
+// The depth the depth buffer is cleared to, left wherever nothing was drawn.
+override FAR_DEPTH: f32 = 1.0;
+
+// The number of samples on each side of the pixel.
+const TAPS: i32 = 8;
+
+// The blur radius in pixels of the surface at `tex_coord`.
+fn circle_of_confusion(tex_coord: vec2<f32>) -> f32 {
+    let size = vec2<f32>(textureDimensions(t_depth));
+    let coord = clamp(vec2<i32>(tex_coord * size), vec2<i32>(0), vec2<i32>(size) - 1);
+    let depth = textureLoad(t_depth, coord, 0).r;
+
+    // The background is as far away as it gets.
+    if depth == FAR_DEPTH {
+        return settings.max_blur;
+    }
+
+    let ndc = vec4(tex_coord.x * 2.0 - 1.0, 1.0 - tex_coord.y * 2.0, depth, 1.0);
+    let world = camera.inverse_view_projection * ndc;
+    let view = camera.view_matrix * vec4(world.xyz / world.w, 1.0);
+    let distance = -view.z;
+
+    let coc = settings.aperture * abs(distance - settings.focus_distance) / max(distance, 0.0001);
+    return min(coc, 1.0) * settings.max_blur;
+}
+
+@fragment
+fn fragment_main(vertex_output: VertexOutput) -> @location(0) vec4<f32> {
+    let center_coc = circle_of_confusion(vertex_output.tex_coord);
+    let center = textureSampleLevel(t_color, s_color, vertex_output.tex_coord, 0.0);
+    if center_coc < 0.5 {
+        return center;
+    }
+
+    var color = center.rgb;
+    var total = 1.0;
+    for (var i = -TAPS; i <= TAPS; i += 1) {
+        if i == 0 {
+            continue;
+        }
+
+        let offset = f32(i) / f32(TAPS) * center_coc;
+        let tex_coord = vertex_output.tex_coord + settings.direction * offset;
+
+        // A sharp sample doesn't spread over its blurry neighbours, otherwise
+        // objects in focus would bleed into the background.
+        let sample_coc = circle_of_confusion(tex_coord);
+        let weight = smoothstep(0.0, 1.0, sample_coc - abs(offset) + 1.0);
+
+        color += textureSampleLevel(t_color, s_color, tex_coord, 0.0).rgb * weight;
+        total += weight;
+    }
+
+    return vec4(color / total, center.a);
+}
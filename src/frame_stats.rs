@@ -0,0 +1,61 @@
+use std::collections::VecDeque;
+
+/// Smooths the frame rate for display, so the readout doesn't flicker with
+/// every frame.
+pub struct FrameStats {
+    /// Exponential moving average of the frame rate, `None` until the first
+    /// frame.
+    average_fps: Option<f32>,
+    /// The frame rates of the last `WINDOW` frames, for the minimum and
+    /// maximum.
+    recent_fps: VecDeque<f32>,
+}
+
+impl FrameStats {
+    /// How much each new frame contributes to the average.
+    const SMOOTHING: f32 = 0.05;
+    /// The number of frames the minimum and maximum are taken over.
+    const WINDOW: usize = 120;
+
+    pub fn new() -> Self {
+        Self {
+            average_fps: None,
+            recent_fps: VecDeque::with_capacity(Self::WINDOW),
+        }
+    }
+
+    pub fn push(&mut self, frame_duration: std::time::Duration) {
+        let seconds = frame_duration.as_secs_f32();
+        if seconds <= 0.0 {
+            return;
+        }
+        let fps = 1.0 / seconds;
+
+        self.average_fps = Some(match self.average_fps {
+            Some(average) => average + (fps - average) * Self::SMOOTHING,
+            None => fps,
+        });
+
+        if self.recent_fps.len() == Self::WINDOW {
+            self.recent_fps.pop_front();
+        }
+        self.recent_fps.push_back(fps);
+    }
+
+    pub fn average_fps(&self) -> f32 {
+        self.average_fps.unwrap_or(0.0)
+    }
+
+    /// The lowest and highest frame rate over the last frames.
+    pub fn min_max_fps(&self) -> (f32, f32) {
+        self.recent_fps
+            .iter()
+            .fold(None, |range: Option<(f32, f32)>, &fps| {
+                Some(match range {
+                    Some((min, max)) => (min.min(fps), max.max(fps)),
+                    None => (fps, fps),
+                })
+            })
+            .unwrap_or((0.0, 0.0))
+    }
+}
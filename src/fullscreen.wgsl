@@ -1,28 +1,108 @@
 const PI: f32 = 3.14159265359;
 
-@group(0) @binding(0) var t_depth: texture_depth_2d;
+// The depth target, read with `textureLoad` and found in the red channel. It
+// is bound as a float texture, because GL can only read a `texture_depth_2d`
+// by comparing against a reference depth.
+@group(0) @binding(0) var t_depth: texture_2d<f32>;
 @group(0) @binding(1) var t_albedo: texture_2d<f32>;
 @group(0) @binding(2) var s_albedo: sampler;
 @group(0) @binding(3) var t_position: texture_2d<f32>;
 @group(0) @binding(4) var s_position: sampler;
 @group(0) @binding(5) var t_normal: texture_2d<f32>;
 @group(0) @binding(6) var s_normal: sampler;
+// The specular color in RGB and its strength in alpha.
+@group(0) @binding(8) var t_specular: texture_2d<f32>;
+
+const DEBUG_VIEW_ALBEDO: u32 = 0u;
+const DEBUG_VIEW_POSITION: u32 = 1u;
+const DEBUG_VIEW_NORMAL: u32 = 2u;
+const DEBUG_VIEW_DEPTH: u32 = 3u;
+const DEBUG_VIEW_COVERAGE: u32 = 4u;
+
+struct DebugSettings {
+    // The box positions are mapped to 0..1 in the position view. The depth
+    // view maps the distances in `x` to 0..1.
+    bounds_min: vec4<f32>,
+    bounds_max: vec4<f32>,
+    view: u32,
+}
+@group(0) @binding(7) var<uniform> debug_settings: DebugSettings;
+
+// Set when the normal target can't store negative values, so normals are
+// packed into the 0..1 range.
+override PACK_NORMALS: bool = false;
+// Set when the normal target holds view space normals, which are turned back
+// into world space for lighting.
+override VIEW_SPACE_NORMALS: bool = false;
+// Set when there is no position target, so positions are reconstructed from
+// depth.
+override RECONSTRUCT_POSITION: bool = false;
+// The depth the depth buffer is cleared to, where the sky shows.
+override FAR_DEPTH: f32 = 1.0;
 
 struct Camera {
     projection_matrix: mat4x4<f32>,
     view_matrix: mat4x4<f32>,
     position: vec3<f32>,
+    inverse_view_projection: mat4x4<f32>,
 }
 @group(1) @binding(0) var<uniform> camera: Camera;
 
+struct Shadow {
+    view_projection: mat4x4<f32>,
+    // Empty for lights that don't cast shadows.
+    atlas_rect: vec4<f32>,
+    // Near and far planes, and 1.0 for perspective projections.
+    depth_range: vec4<f32>,
+}
+
 struct PointLight {
     position: vec3<f32>,
     intensity: f32,
     color: vec3<f32>,
     shininess: f32,
-    ambient: f32,
 }
-@group(2) @binding(0) var<uniform> point_light: PointLight;
+
+struct DirectionalLight {
+    direction: vec3<f32>,
+    intensity: f32,
+    color: vec3<f32>,
+    shadow: Shadow,
+}
+
+struct ShadowSettings {
+    filter_mode: u32,
+    softness: f32,
+    // Both in world units along the view direction of the light. The slope
+    // bias is scaled by how steep the surface is to the light.
+    constant_bias: f32,
+    slope_bias: f32,
+}
+
+const MAX_POINT_LIGHTS: u32 = 320u;
+const MAX_SHADOWED_POINT_LIGHTS: u32 = 4u;
+
+struct Lights {
+    point_lights: array<PointLight, MAX_POINT_LIGHTS>,
+    // The shadows of the first point lights, the others don't cast any.
+    point_light_shadows: array<Shadow, MAX_SHADOWED_POINT_LIGHTS>,
+    directional_light: DirectionalLight,
+    sky_color: vec4<f32>,
+    ambient_color: vec4<f32>,
+    shadow_settings: ShadowSettings,
+    // Uniform buffers need members after a struct to start on 16 bytes.
+    @align(16) point_light_count: u32,
+}
+@group(2) @binding(0) var<uniform> lights: Lights;
+@group(2) @binding(1) var t_shadow_atlas: texture_depth_2d;
+@group(2) @binding(2) var s_shadow_atlas: sampler_comparison;
+// The same atlas as a float texture, to read the depths of the occluders. GL
+// can't `textureLoad` from a `texture_depth_2d`.
+@group(2) @binding(3) var t_shadow_depths: texture_2d<f32>;
+
+// Limits the slope bias on surfaces almost parallel to the light, where the
+// tangent of the angle goes to infinity.
+const MAX_SHADOW_SLOPE: f32 = 5.0;
 
 struct VertexOutput {
     @builtin(position) position: vec4<f32>,
@@ -67,9 +147,210 @@ fn geometry_smith(n_dot_v: f32, n_dot_l: f32, roughness: f32) -> f32 {
     return ggx1 + ggx2;
 }
 
+// The world space normal stored in the normal target, `normal.xyz`.
+fn world_normal_from(normal: vec3<f32>) -> vec3<f32> {
+    var n = normal;
+    if PACK_NORMALS {
+        n = n * 2.0 - 1.0;
+    }
+    if VIEW_SPACE_NORMALS {
+        // The view matrix only rotates directions, so its transpose undoes it.
+        let view = camera.view_matrix;
+        let rotation = mat3x3<f32>(view[0].xyz, view[1].xyz, view[2].xyz);
+        n = transpose(rotation) * n;
+    }
+    return n;
+}
+
+// The world position of the surface at the pixel `coord` with `depth`.
+fn world_position_at(coord: vec2<i32>, depth: f32) -> vec3<f32> {
+    if RECONSTRUCT_POSITION {
+        let uv = (vec2<f32>(coord) + 0.5) / vec2<f32>(textureDimensions(t_depth));
+        let ndc = vec4(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, depth, 1.0);
+        let world_position = camera.inverse_view_projection * ndc;
+        return world_position.xyz / world_position.w;
+    }
+
+    return textureLoad(t_position, coord, 0).xyz;
+}
+
 @fragment
 fn fragment_debug(vertex_output: VertexOutput) -> @location(0) vec4<f32> {
-    return textureSample(t_albedo, s_albedo, vertex_output.tex_coord);
+    let uv = vertex_output.tex_coord;
+    switch debug_settings.view {
+        case DEBUG_VIEW_POSITION: {
+            let coord = vec2<i32>(floor(vertex_output.position.xy));
+            let depth = textureLoad(t_depth, coord, 0).r;
+            // Keep the background black.
+            if depth == FAR_DEPTH {
+                return vec4(0.0, 0.0, 0.0, 1.0);
+            }
+
+            let position = world_position_at(coord, depth);
+            let size = max(debug_settings.bounds_max.xyz - debug_settings.bounds_min.xyz, vec3(0.0001));
+            let t = clamp((position - debug_settings.bounds_min.xyz) / size, vec3(0.0), vec3(1.0));
+            return vec4(t, 1.0);
+        }
+        case DEBUG_VIEW_NORMAL: {
+            let normal = textureSample(t_normal, s_normal, uv);
+            let world_normal = world_normal_from(normal.xyz);
+            // Normals have negative components that would clamp to black.
+            return vec4((world_normal * 0.5 + 0.5) * normal.w, 1.0);
+        }
+        case DEBUG_VIEW_DEPTH: {
+            let coord = vec2<i32>(floor(vertex_output.position.xy));
+            let depth = textureLoad(t_depth, coord, 0).r;
+            // The background is infinitely far away.
+            if depth == FAR_DEPTH {
+                return vec4(1.0, 1.0, 1.0, 1.0);
+            }
+
+            let distance = length(world_position_at(coord, depth) - camera.position);
+            let range = max(debug_settings.bounds_max.x - debug_settings.bounds_min.x, 0.0001);
+            let t = clamp((distance - debug_settings.bounds_min.x) / range, 0.0, 1.0);
+            return vec4(vec3(t), 1.0);
+        }
+        case DEBUG_VIEW_COVERAGE: {
+            // White where geometry was drawn, black elsewhere.
+            let coverage = textureSample(t_normal, s_normal, uv).w;
+            return vec4(vec3(coverage), 1.0);
+        }
+        default: {
+            // Leave out the ambient occlusion in the alpha.
+            return vec4(textureSample(t_albedo, s_albedo, uv).rgb, 1.0);
+        }
+    }
+}
+
+const SHADOW_FILTER_PCF: u32 = 0u;
+const SHADOW_FILTER_PCSS: u32 = 1u;
+
+// Returns 0.0 where the surface is in the shadow, and 1.0 where it is lit.
+fn shadow(
+    shadow: Shadow,
+    world_position: vec3<f32>,
+    world_normal: vec3<f32>,
+    direction_to_light: vec3<f32>,
+) -> f32 {
+    if shadow.atlas_rect.z <= 0.0 {
+        return 1.0;
+    }
+
+    let clip = shadow.view_projection * vec4<f32>(world_position, 1.0);
+    if clip.w <= 0.0 {
+        return 1.0;
+    }
+
+    // Anything outside of the light's view is lit.
+    let ndc = clip.xyz / clip.w;
+    if any(abs(ndc.xy) > vec2<f32>(1.0)) || ndc.z > 1.0 {
+        return 1.0;
+    }
+
+    let uv = ndc.xy * vec2<f32>(0.5, -0.5) + vec2<f32>(0.5);
+    let atlas_uv = shadow.atlas_rect.xy + uv * shadow.atlas_rect.zw;
+    let depth = biased_shadow_depth(shadow, ndc.z, world_normal, direction_to_light);
+
+    if lights.shadow_settings.filter_mode == SHADOW_FILTER_PCSS {
+        return pcss(shadow, atlas_uv, depth);
+    }
+    return pcf(shadow.atlas_rect, atlas_uv, depth, 1.0);
+}
+
+// Average a 5x5 grid of shadow map comparisons, `radius` texels apart.
+fn pcf(atlas_rect: vec4<f32>, atlas_uv: vec2<f32>, depth: f32, radius: f32) -> f32 {
+    let texel_size = 1.0 / vec2<f32>(textureDimensions(t_shadow_atlas));
+    // Don't sample the tiles of other lights.
+    let min_uv = atlas_rect.xy + texel_size;
+    let max_uv = atlas_rect.xy + atlas_rect.zw - texel_size;
+
+    var lit = 0.0;
+    for (var y = -2; y <= 2; y++) {
+        for (var x = -2; x <= 2; x++) {
+            let offset = vec2<f32>(f32(x), f32(y)) * texel_size * radius;
+            let uv = clamp(atlas_uv + offset, min_uv, max_uv);
+            lit += textureSampleCompareLevel(t_shadow_atlas, s_shadow_atlas, uv, depth);
+        }
+    }
+    return lit / 25.0;
+}
+
+fn linear_shadow_depth(shadow: Shadow, depth: f32) -> f32 {
+    let near = shadow.depth_range.x;
+    let far = shadow.depth_range.y;
+    if shadow.depth_range.z > 0.0 {
+        return near * far / (far - depth * (far - near));
+    }
+    return near + depth * (far - near);
+}
+
+// The inverse of `linear_shadow_depth`.
+fn shadow_depth_from_linear(shadow: Shadow, linear: f32) -> f32 {
+    let near = shadow.depth_range.x;
+    let far = shadow.depth_range.y;
+    if shadow.depth_range.z > 0.0 {
+        return far * (linear - near) / (max(linear, near) * (far - near));
+    }
+    return (linear - near) / (far - near);
+}
+
+// Move `depth` towards the light to stop surfaces from shadowing themselves.
+// The bias is applied to the linear depth, so it is the same distance close to
+// the near plane of a perspective shadow map as far away from it, where a
+// bias on the stored depth would be far too small or too large. Surfaces at a
+// steep angle to the light cover more depth per texel, so they get more.
+fn biased_shadow_depth(
+    shadow: Shadow,
+    depth: f32,
+    world_normal: vec3<f32>,
+    direction_to_light: vec3<f32>,
+) -> f32 {
+    let cos_angle = clamp(dot(world_normal, direction_to_light), 0.0, 1.0);
+    let sin_angle = sqrt(1.0 - cos_angle * cos_angle);
+    let slope = min(sin_angle / max(cos_angle, 0.0001), MAX_SHADOW_SLOPE);
+
+    let settings = lights.shadow_settings;
+    let bias = settings.constant_bias + settings.slope_bias * slope;
+    let linear = linear_shadow_depth(shadow, depth);
+    return shadow_depth_from_linear(shadow, linear - bias);
+}
+
+// Percentage-closer soft shadows: find the average depth of the occluders
+// around the sample and widen the filter the further away they are from the
+// receiver.
+fn pcss(shadow: Shadow, atlas_uv: vec2<f32>, depth: f32) -> f32 {
+    let atlas_size = vec2<f32>(textureDimensions(t_shadow_atlas));
+    let texel_size = 1.0 / atlas_size;
+    let min_uv = shadow.atlas_rect.xy + texel_size;
+    let max_uv = shadow.atlas_rect.xy + shadow.atlas_rect.zw - texel_size;
+
+    let softness = lights.shadow_settings.softness;
+    let search_radius = 2.0 + softness * 2.0;
+
+    var blocker_depth = 0.0;
+    var blocker_count = 0.0;
+    for (var y = -2; y <= 2; y++) {
+        for (var x = -2; x <= 2; x++) {
+            let offset = vec2<f32>(f32(x), f32(y)) * texel_size * search_radius;
+            let uv = clamp(atlas_uv + offset, min_uv, max_uv);
+            let sample_depth = textureLoad(t_shadow_depths, vec2<i32>(uv * atlas_size), 0).r;
+            if sample_depth < depth {
+                blocker_depth += sample_depth;
+                blocker_count += 1.0;
+            }
+        }
+    }
+
+    if blocker_count == 0.0 {
+        return 1.0;
+    }
+
+    let receiver = linear_shadow_depth(shadow, depth);
+    let blocker = linear_shadow_depth(shadow, blocker_depth / blocker_count);
+    let penumbra = (receiver - blocker) / blocker;
+    let radius = clamp(penumbra * softness * 8.0, 1.0, search_radius);
+
+    return pcf(shadow.atlas_rect, atlas_uv, depth, radius);
 }
 
 fn diffuse(
@@ -90,51 +371,88 @@ fn specular(
     shininess: f32,
 ) -> vec3<f32> {
     let x = dot(direction_to_light_reflected, direction_to_camera);
-    let radiance = pow(x, shininess);
+    // `pow` is undefined for negative bases, GL returns NaN.
+    let radiance = pow(max(x, 0.0), shininess);
     return color * intensity * max(radiance, 0.0);
 }
 
 @fragment
 fn fragment_main(vertex_output: VertexOutput) -> @location(0) vec4<f32> {
     let fullscreen_uv = vec2<i32>(floor(vertex_output.position.xy));
-    let depth = textureLoad(t_depth, fullscreen_uv, 0);
+    let depth = textureLoad(t_depth, fullscreen_uv, 0).r;
 
-    if depth >= 1.0 {
-        // Black background for infinite depth.
-        return vec4(0.0, 0.0, 0.0, 1.0);
+    if depth == FAR_DEPTH {
+        // Show the sky for infinite depth. Its alpha is 0.0 when the
+        // background is see-through.
+        return lights.sky_color;
     }
 
-    let world_position = textureLoad(t_position, fullscreen_uv, 0).xyz;
-    let world_normal = normalize(textureLoad(t_normal, fullscreen_uv, 0).xyz);
+    let world_position = world_position_at(fullscreen_uv, depth);
+    let normal = textureLoad(t_normal, fullscreen_uv, 0);
+    let coverage = normal.w;
+    let world_normal = normalize(world_normal_from(normal.xyz));
 
-    let direction_to_light = normalize(point_light.position - world_position);
     let direction_to_camera = normalize(camera.position - world_position);
 
-    let material_color = vec3(0.8, 0.1, 0.1);
+    // The alpha of the albedo is the baked ambient occlusion.
+    let albedo = textureLoad(t_albedo, fullscreen_uv, 0);
+    let material_color = albedo.rgb;
+    let material_specular = textureLoad(t_specular, fullscreen_uv, 0);
+    let specular_color = material_specular.rgb * material_specular.a;
+
+    // Without point lights the loop doesn't run, leaving the ambient and
+    // directional light.
+    var lit = vec3<f32>(0.0);
+    for (var i = 0u; i < min(lights.point_light_count, MAX_POINT_LIGHTS); i += 1u) {
+        let point_light = lights.point_lights[i];
+        let direction_to_light = normalize(point_light.position - world_position);
+
+        let diffuse = diffuse(
+            point_light.intensity,
+            material_color,
+            direction_to_light,
+            world_normal,
+        );
+
+        // Phong model
+        let r = reflect(-direction_to_light, world_normal);
+        // Blinn model
+        // let r = normalize(direction_to_light + direction_to_camera);
+
+        let specular = specular(
+            point_light.intensity,
+            specular_color,
+            direction_to_camera,
+            r,
+            point_light.shininess,
+        );
+
+        var lit_fraction = 1.0;
+        if i < MAX_SHADOWED_POINT_LIGHTS {
+            let point_light_shadow = lights.point_light_shadows[i];
+            lit_fraction = shadow(point_light_shadow, world_position, world_normal, direction_to_light);
+        }
+        lit += (diffuse + specular) * lit_fraction;
+    }
 
-    let diffuse = diffuse(
-        point_light.intensity,
-        material_color,
-        direction_to_light,
+    let ambient = material_color * lights.ambient_color.rgb * albedo.a;
+
+    let direction_to_sun = -normalize(lights.directional_light.direction);
+    let sun = diffuse(
+        lights.directional_light.intensity,
+        material_color * lights.directional_light.color,
+        direction_to_sun,
         world_normal,
     );
 
-    // Phong model
-    let r = reflect(-direction_to_light, world_normal);
-    // Blinn model
-    // let r = normalize(direction_to_light + direction_to_camera);
-
-    let specular = specular(
-        point_light.intensity,
-        material_color,
-        direction_to_camera,
-        r,
-        point_light.shininess,
+    let sun_shadow = shadow(
+        lights.directional_light.shadow,
+        world_position,
+        world_normal,
+        direction_to_sun,
     );
 
-    let ambient = material_color * point_light.ambient;
-
-    return vec4(diffuse + specular + ambient, 1.0);
+    return vec4(lit + ambient + sun * sun_shadow, coverage);
 
     /*
     let roughness = 0.1;
@@ -142,7 +460,7 @@ fn fragment_main(vertex_output: VertexOutput) -> @location(0) vec4<f32> {
 
     let depth = textureLoad(t_depth, vec2<i32>(floor(vertex_output.position.xy)), 0);
 
-    if depth >= 1.0 {
+    if depth == FAR_DEPTH {
         // Black background for infinite depth.
         return vec4(0.0, 0.0, 0.0, 1.0);
     }
@@ -154,12 +472,12 @@ fn fragment_main(vertex_output: VertexOutput) -> @location(0) vec4<f32> {
     let n = textureSample(t_normal, s_normal, vertex_output.tex_coord).xyz;
     let v = normalize(camera.position - fragment_position);
 
-    let l = normalize(point_light.position - fragment_position);
+    let l = normalize(lights.point_lights[0].position - fragment_position);
     let h = normalize(v + l);
 
-    let distance = length(point_light.position - fragment_position);
+    let distance = length(lights.point_lights[0].position - fragment_position);
     let attenuation = 1.0 / (distance * distance);
-    let radiance = point_light.color * attenuation;
+    let radiance = lights.point_lights[0].color * attenuation;
 
     let n_dot_v = max(dot(n, v), 0.000001);
     let n_dot_l = max(dot(n, l), 0.000001);
@@ -0,0 +1,243 @@
+use crate::{
+    renderer::LimitsProfile,
+    texture::{DepthConvention, Texture},
+};
+
+/// Name of the shader constant that tells the G-buffer shaders whether normals
+/// are packed into the 0..1 range.
+pub const PACK_NORMALS_CONSTANT: &str = "PACK_NORMALS";
+
+/// Name of the shader constant that tells the shaders reading the G-buffer to
+/// reconstruct world positions from depth, because there is no position target.
+pub const RECONSTRUCT_POSITION_CONSTANT: &str = "RECONSTRUCT_POSITION";
+
+/// Name of the shader constant that tells the G-buffer shaders that normals
+/// are stored in view space.
+pub const VIEW_SPACE_NORMALS_CONSTANT: &str = "VIEW_SPACE_NORMALS";
+
+/// The space of the normals in the normal target.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum NormalSpace {
+    #[default]
+    World,
+    /// Relative to the camera, for screen space effects like ambient
+    /// occlusion. The lighting pass turns them back into world space.
+    View,
+}
+
+// The formats of the G-buffer targets. Everything that creates a target or
+// renders into one uses these, so the textures and pipelines can't drift
+// apart. The normal target depends on the limits profile, see
+// `normal_format`.
+
+/// The format of the albedo target. Colors are stored in sRGB for precision in
+/// the darks and decoded to linear when read. The alpha holds the ambient
+/// occlusion baked into the vertices, see [crate::mesh::Mesh::bake_ao].
+pub const ALBEDO_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+
+/// The format of the optional position target, world positions.
+pub const POSITION_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+/// Whether the normal target stores normals packed into the 0..1 range.
+pub fn packs_normals(limits_profile: LimitsProfile) -> bool {
+    limits_profile == LimitsProfile::Compatibility
+}
+
+/// The format of the normal target. Float targets are not renderable on all
+/// constrained backends, so normals are packed into 8 bits per channel there.
+pub fn normal_format(limits_profile: LimitsProfile) -> wgpu::TextureFormat {
+    if packs_normals(limits_profile) {
+        wgpu::TextureFormat::Rgba8Unorm
+    } else {
+        wgpu::TextureFormat::Rgba16Float
+    }
+}
+
+/// The format of the specular target, the specular color in RGB and its
+/// strength in alpha.
+pub const SPECULAR_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8Unorm;
+
+/// The formats of the G-buffer color targets, in the order of the geometry
+/// shader outputs. The position target is `None` if positions are not stored.
+pub fn target_formats(
+    limits_profile: LimitsProfile,
+    store_position: bool,
+) -> [Option<wgpu::TextureFormat>; 4] {
+    [
+        Some(ALBEDO_FORMAT),
+        store_position.then_some(POSITION_FORMAT),
+        Some(normal_format(limits_profile)),
+        Some(SPECULAR_FORMAT),
+    ]
+}
+
+/// Pipeline constants for shaders that read or write the G-buffer, including
+/// the [DepthConvention::shader_constants] for reading its depth.
+pub fn shader_constants(
+    limits_profile: LimitsProfile,
+    normal_space: NormalSpace,
+    depth_convention: DepthConvention,
+    store_position: bool,
+) -> std::collections::HashMap<String, f64> {
+    let flag = |value: bool| if value { 1.0 } else { 0.0 };
+    let mut constants = depth_convention.shader_constants();
+    constants.extend([
+        (
+            PACK_NORMALS_CONSTANT.to_string(),
+            flag(packs_normals(limits_profile)),
+        ),
+        (
+            RECONSTRUCT_POSITION_CONSTANT.to_string(),
+            flag(!store_position),
+        ),
+        (
+            VIEW_SPACE_NORMALS_CONSTANT.to_string(),
+            flag(normal_space == NormalSpace::View),
+        ),
+    ]);
+    constants
+}
+
+/// The textures the geometry pass renders into and the lighting pass reads
+/// from.
+pub struct GBuffer<'a> {
+    pub depth: &'a Texture,
+    pub albedo: &'a Texture,
+    /// `None` when positions are reconstructed from depth instead.
+    pub position: Option<&'a Texture>,
+    pub normal: &'a Texture,
+    pub specular: &'a Texture,
+}
+
+/// The values each G-buffer target is cleared to before the geometry pass.
+/// Pixels not covered by any geometry keep these values, so the lighting pass
+/// can rely on them.
+///
+/// The color targets are always cleared, never loaded. The geometry pass
+/// leaves the background uncovered, and the G-buffer debug views, the
+/// coverage in the normal alpha and filtered reads along silhouettes all see
+/// those pixels, so loading would leak the previous frame into them.
+#[derive(Clone, Copy, Debug)]
+pub struct GBufferClearValues {
+    pub albedo: wgpu::Color,
+    pub position: wgpu::Color,
+    /// The alpha of the normal target is the coverage, so it should stay 0.0
+    /// for the background to come out transparent in a cutout.
+    pub normal: wgpu::Color,
+    pub specular: wgpu::Color,
+    /// `None` keeps the depth of the previous frame, which is useful to debug
+    /// what was drawn where.
+    pub depth: Option<f32>,
+}
+
+impl GBufferClearValues {
+    /// The clear values with the depth cleared to the far plane of
+    /// `depth_convention`.
+    pub fn new(depth_convention: DepthConvention) -> Self {
+        Self {
+            depth: Some(depth_convention.far()),
+            ..Default::default()
+        }
+    }
+
+    /// How to load the depth target at the start of the geometry pass.
+    pub fn depth_load_op(&self) -> wgpu::LoadOp<f32> {
+        match self.depth {
+            Some(depth) => wgpu::LoadOp::Clear(depth),
+            None => wgpu::LoadOp::Load,
+        }
+    }
+}
+
+impl Default for GBufferClearValues {
+    fn default() -> Self {
+        Self {
+            albedo: wgpu::Color::BLACK,
+            position: wgpu::Color::TRANSPARENT,
+            normal: wgpu::Color::TRANSPARENT,
+            specular: wgpu::Color::TRANSPARENT,
+            depth: Some(DepthConvention::default().far()),
+        }
+    }
+}
+
+/// Reasons a G-buffer layout can not be used as render targets on a device.
+#[derive(Debug)]
+pub enum GBufferLayoutError {
+    /// More color targets than the device can render to at once.
+    TooManyTargets { count: u32, max: u32 },
+    /// The targets combined use more bytes per sample than the device allows.
+    TooManyBytesPerSample { bytes: u32, max: u32 },
+    /// A format that can not be used as a color render target.
+    UnsupportedFormat(wgpu::TextureFormat),
+    /// The number of targets doesn't match the outputs of the geometry shader.
+    TargetCountMismatch { count: usize, expected: usize },
+}
+
+impl std::fmt::Display for GBufferLayoutError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::TooManyTargets { count, max } => write!(
+                f,
+                "G-buffer has {count} color targets, but the device supports at most {max}"
+            ),
+            Self::TooManyBytesPerSample { bytes, max } => write!(
+                f,
+                "G-buffer targets use {bytes} bytes per sample, but the device supports at most {max}"
+            ),
+            Self::UnsupportedFormat(format) => {
+                write!(f, "{format:?} can not be used as a G-buffer target")
+            }
+            Self::TargetCountMismatch { count, expected } => write!(
+                f,
+                "G-buffer has {count} color targets, but the geometry shader writes {expected}"
+            ),
+        }
+    }
+}
+
+/// Check that the G-buffer color target `formats` match the outputs of the
+/// geometry shader and can all be rendered to in a single pass within the
+/// device `limits`. `None` leaves an output without a target.
+pub fn validate_layout(
+    limits: &wgpu::Limits,
+    formats: &[Option<wgpu::TextureFormat>],
+) -> Result<(), GBufferLayoutError> {
+    const SHADER_OUTPUTS: usize = 4;
+    if formats.len() != SHADER_OUTPUTS {
+        return Err(GBufferLayoutError::TargetCountMismatch {
+            count: formats.len(),
+            expected: SHADER_OUTPUTS,
+        });
+    }
+
+    let count = formats.len() as u32;
+    if count > limits.max_color_attachments {
+        return Err(GBufferLayoutError::TooManyTargets {
+            count,
+            max: limits.max_color_attachments,
+        });
+    }
+
+    // Each target starts at an offset aligned to its component size, the same
+    // way the limit is calculated by wgpu.
+    let mut bytes: u32 = 0;
+    for format in formats.iter().flatten() {
+        let (Some(cost), Some(alignment)) = (
+            format.target_pixel_byte_cost(),
+            format.target_component_alignment(),
+        ) else {
+            return Err(GBufferLayoutError::UnsupportedFormat(*format));
+        };
+        bytes = bytes.div_ceil(alignment) * alignment + cost;
+    }
+
+    if bytes > limits.max_color_attachment_bytes_per_sample {
+        return Err(GBufferLayoutError::TooManyBytesPerSample {
+            bytes,
+            max: limits.max_color_attachment_bytes_per_sample,
+        });
+    }
+
+    Ok(())
+}
@@ -1,9 +1,13 @@
+use std::ops::Range;
+
+use cgmath::{EuclideanSpace, InnerSpace};
 use wgpu::util::DeviceExt;
 
 use crate::{
     camera::Camera,
     mesh::{GpuMesh, Mesh},
-    texture::DEPTH_FORMAT,
+    pass_builder::PassBuilder,
+    upload_buffer::UploadBuffer,
     Renderer,
 };
 
@@ -25,14 +29,171 @@ impl Vertex {
     }
 }
 
+/// Per-instance data for meshes drawn by the gizmos.
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+#[repr(C)]
+struct Instance {
+    position: [f32; 3],
+    scale: f32,
+    color: [f32; 4],
+    /// 1.0 to draw in `color`, 0.0 to keep the vertex colors.
+    use_color: f32,
+    _padding: [f32; 3],
+}
+
+impl Instance {
+    fn new(position: [f32; 3], scale: f32, color: Option<[f32; 4]>) -> Self {
+        Self {
+            position,
+            scale,
+            color: color.unwrap_or([1.0; 4]),
+            use_color: if color.is_some() { 1.0 } else { 0.0 },
+            _padding: [0.0; 3],
+        }
+    }
+}
+
+/// Vertices for a list of strips, each drawn with a separate draw call so they
+/// don't connect to each other.
+#[derive(Default)]
+struct Strips {
+    vertices: Vec<Vertex>,
+    ranges: Vec<Range<u32>>,
+    /// A sphere around each strip, as its center and radius, for culling.
+    bounds: Vec<(cgmath::Point3<f32>, f32)>,
+}
+
+impl Strips {
+    fn push(&mut self, points: &[cgmath::Vector3<f32>], color: [f32; 4]) {
+        let start = self.vertices.len() as u32;
+        self.vertices
+            .extend(points.iter().map(|&point| Vertex::new(point.into(), color)));
+        self.ranges.push(start..self.vertices.len() as u32);
+
+        let center = cgmath::Point3::centroid(
+            &points
+                .iter()
+                .map(|&point| cgmath::Point3::from_vec(point))
+                .collect::<Vec<_>>(),
+        );
+        let radius = points
+            .iter()
+            .map(|&point| (point - center.to_vec()).magnitude())
+            .fold(0.0, f32::max);
+        self.bounds.push((center, radius));
+    }
+
+    fn len(&self) -> usize {
+        self.vertices.len()
+    }
+
+    /// Drop the strips that are out of view of `camera`.
+    fn cull(&mut self, camera: &Camera) {
+        let mut vertices = Vec::with_capacity(self.vertices.len());
+        let mut ranges = Vec::with_capacity(self.ranges.len());
+        let mut bounds = Vec::with_capacity(self.bounds.len());
+        for (range, (center, radius)) in self.ranges.iter().zip(self.bounds.iter()) {
+            if !camera.is_sphere_in_view(*center, *radius) {
+                continue;
+            }
+            let start = vertices.len() as u32;
+            vertices.extend_from_slice(&self.vertices[range.start as usize..range.end as usize]);
+            ranges.push(start..vertices.len() as u32);
+            bounds.push((*center, *radius));
+        }
+        self.vertices = vertices;
+        self.ranges = ranges;
+        self.bounds = bounds;
+    }
+
+    /// Push the vertices to `buffer`, returning where they are, or `None` if
+    /// there are none.
+    fn push_to(&self, buffer: &mut UploadBuffer) -> Option<Range<wgpu::BufferAddress>> {
+        (!self.vertices.is_empty()).then(|| buffer.push(&self.vertices))
+    }
+
+    fn render(
+        &self,
+        render_pass: &mut wgpu::RenderPass,
+        pipeline: &wgpu::RenderPipeline,
+        vertices: wgpu::BufferSlice,
+        instance_buffer: &wgpu::Buffer,
+    ) {
+        render_pass.set_pipeline(pipeline);
+        render_pass.set_vertex_buffer(0, vertices);
+        render_pass.set_vertex_buffer(1, instance_buffer.slice(..));
+        for range in self.ranges.iter() {
+            render_pass.draw(range.clone(), 0..1);
+        }
+    }
+
+    fn clear(&mut self) {
+        self.vertices.clear();
+        self.ranges.clear();
+        self.bounds.clear();
+    }
+}
+
+/// A pipeline for each kind of primitive, all with the same depth test.
+struct Pipelines {
+    lines: wgpu::RenderPipeline,
+    line_strips: wgpu::RenderPipeline,
+    triangle_strips: wgpu::RenderPipeline,
+}
+
+impl Pipelines {
+    fn new(
+        renderer: &Renderer,
+        module: &wgpu::ShaderModule,
+        layout: &wgpu::PipelineLayout,
+        depth_test: bool,
+    ) -> Self {
+        let create = |topology, label| {
+            create_pipeline(renderer, module, layout, topology, depth_test, label)
+        };
+        Self {
+            lines: create(wgpu::PrimitiveTopology::LineList, "gizmos render pipeline"),
+            line_strips: create(
+                wgpu::PrimitiveTopology::LineStrip,
+                "gizmos line strip render pipeline",
+            ),
+            triangle_strips: create(
+                wgpu::PrimitiveTopology::TriangleStrip,
+                "gizmos triangle strip render pipeline",
+            ),
+        }
+    }
+}
+
 pub struct Gizmos {
-    pipeline: wgpu::RenderPipeline,
+    depth_tested_pipelines: Pipelines,
+    on_top_pipelines: Pipelines,
+    /// Hide gizmos behind the scene. Without it they are drawn on top of
+    /// everything. Toggled with X.
+    pub depth_test: bool,
+    /// Skip gizmos that are out of view, so they aren't uploaded.
+    pub cull: bool,
+    /// The most axis instances, or strip vertices of each kind, that can be
+    /// queued between two renders. Anything past it is dropped, so gizmos
+    /// queued every frame without being rendered can't grow without bound.
+    pub max_queued: usize,
+    /// Set when something was dropped because of `max_queued`, so it is only
+    /// reported once until the next render.
+    overflowed: bool,
+    origin_instance_buffer: wgpu::Buffer,
+    /// Axis instances and strip vertices, uploaded every frame.
+    upload_buffer: UploadBuffer,
 
     axis_mesh: GpuMesh,
-    axis: Vec<[f32; 3]>,
+    axis: Vec<Instance>,
+    line_strips: Strips,
+    triangle_strips: Strips,
+    texts: Vec<(cgmath::Point3<f32>, String, [f32; 4])>,
 }
 
 impl Gizmos {
+    const DEFAULT_MAX_QUEUED: usize = 65536;
+
     pub fn new(renderer: &Renderer, camera: &Camera) -> Self {
         let module = renderer
             .device
@@ -52,68 +213,19 @@ impl Gizmos {
                     push_constant_ranges: &[],
                 });
 
-        let pipeline = renderer
-            .device
-            .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-                label: Some("gizmos render pipeline"),
-                layout: Some(&pipeline_layout),
-                vertex: wgpu::VertexState {
-                    module: &module,
-                    entry_point: "vertex_main",
-                    compilation_options: wgpu::PipelineCompilationOptions::default(),
-                    buffers: &[
-                        wgpu::VertexBufferLayout {
-                            array_stride: std::mem::size_of::<Vertex>() as wgpu::BufferAddress,
-                            step_mode: wgpu::VertexStepMode::Vertex,
-                            attributes: &[
-                                wgpu::VertexAttribute {
-                                    format: wgpu::VertexFormat::Float32x3,
-                                    offset: 0,
-                                    shader_location: 0,
-                                },
-                                wgpu::VertexAttribute {
-                                    format: wgpu::VertexFormat::Float32x4,
-                                    offset: (4 * std::mem::size_of::<f32>()) as wgpu::BufferAddress,
-                                    shader_location: 1,
-                                },
-                            ],
-                        },
-                        wgpu::VertexBufferLayout {
-                            array_stride: std::mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
-                            step_mode: wgpu::VertexStepMode::Instance,
-                            attributes: &[wgpu::VertexAttribute {
-                                format: wgpu::VertexFormat::Float32x3,
-                                offset: 0,
-                                shader_location: 2,
-                            }],
-                        },
-                    ],
-                },
-                primitive: wgpu::PrimitiveState {
-                    topology: wgpu::PrimitiveTopology::LineList,
-                    ..wgpu::PrimitiveState::default()
-                },
-                depth_stencil: Some(wgpu::DepthStencilState {
-                    format: DEPTH_FORMAT,
-                    depth_write_enabled: true,
-                    depth_compare: wgpu::CompareFunction::Less,
-                    stencil: wgpu::StencilState::default(),
-                    bias: wgpu::DepthBiasState::default(),
-                }),
-                multisample: wgpu::MultisampleState::default(),
-                fragment: Some(wgpu::FragmentState {
-                    module: &module,
-                    entry_point: "fragment_main",
-                    compilation_options: wgpu::PipelineCompilationOptions::default(),
-                    targets: &[Some(wgpu::ColorTargetState {
-                        format: wgpu::TextureFormat::Bgra8UnormSrgb,
-                        blend: Some(wgpu::BlendState::REPLACE),
-                        write_mask: wgpu::ColorWrites::ALL,
-                    })],
-                }),
-                multiview: None,
-                cache: None,
-            });
+        let depth_tested_pipelines = Pipelines::new(renderer, &module, &pipeline_layout, true);
+        let on_top_pipelines = Pipelines::new(renderer, &module, &pipeline_layout, false);
+
+        // Strips are drawn in world space, so they use a single instance at
+        // the origin.
+        let origin_instance_buffer =
+            renderer
+                .device
+                .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some("gizmos origin instance"),
+                    contents: bytemuck::cast_slice(&[Instance::new([0.0; 3], 1.0, None)]),
+                    usage: wgpu::BufferUsages::VERTEX,
+                });
 
         let axis_mesh = Mesh {
             vertices: vec![
@@ -128,18 +240,130 @@ impl Gizmos {
                 Vertex::new([0.0, 0.0, 1.0], [0.0, 0.0, 1.0, 1.0]),
             ],
             indices: vec![0, 1, 2, 3, 4, 5],
+            ..Default::default()
         }
         .upload_to_gpu(renderer);
 
         Self {
-            pipeline,
+            depth_tested_pipelines,
+            on_top_pipelines,
+            depth_test: true,
+            cull: true,
+            max_queued: Self::DEFAULT_MAX_QUEUED,
+            overflowed: false,
+            origin_instance_buffer,
+            upload_buffer: UploadBuffer::new(
+                renderer,
+                "gizmos upload buffer",
+                wgpu::BufferUsages::VERTEX,
+            ),
             axis_mesh,
             axis: vec![],
+            line_strips: Strips::default(),
+            triangle_strips: Strips::default(),
+            texts: vec![],
         }
     }
 
     pub fn draw_axis(&mut self, position: cgmath::Vector3<f32>) {
-        self.axis.push(position.into());
+        self.draw_axis_with(position, 1.0, None);
+    }
+
+    /// Draw an axis with lines `scale` units long. With a `color` all three
+    /// lines are drawn in it, otherwise they are red, green and blue.
+    pub fn draw_axis_with(
+        &mut self,
+        position: cgmath::Vector3<f32>,
+        scale: f32,
+        color: Option<[f32; 4]>,
+    ) {
+        if self.has_room(self.axis.len(), 1) {
+            self.axis.push(Instance::new(position.into(), scale, color));
+        }
+    }
+
+    /// Draw a continuous line through all the `points`.
+    pub fn draw_polyline(&mut self, points: &[cgmath::Vector3<f32>], color: [f32; 4]) {
+        if self.has_room(self.line_strips.len(), points.len()) {
+            self.line_strips.push(points, color);
+        }
+    }
+
+    /// Draw a filled triangle strip through all the `points`.
+    #[allow(dead_code)]
+    pub fn draw_triangle_strip(&mut self, points: &[cgmath::Vector3<f32>], color: [f32; 4]) {
+        if self.has_room(self.triangle_strips.len(), points.len()) {
+            self.triangle_strips.push(points, color);
+        }
+    }
+
+    /// Drop everything queued since the last render. Call this on frames the
+    /// gizmos are not rendered.
+    pub fn clear(&mut self) {
+        self.axis.clear();
+        self.line_strips.clear();
+        self.triangle_strips.clear();
+        self.texts.clear();
+        self.overflowed = false;
+    }
+
+    /// Whether `count` more items fit in a queue that holds `queued`, logging
+    /// the first time something doesn't.
+    fn has_room(&mut self, queued: usize, count: usize) -> bool {
+        if queued + count <= self.max_queued {
+            return true;
+        }
+        if !self.overflowed {
+            self.overflowed = true;
+            eprintln!(
+                "Dropping gizmos, more than {} are queued. Are they drawn without being rendered?",
+                self.max_queued
+            );
+        }
+        false
+    }
+
+    /// Draw `text` centered on a position in the world, facing the camera. The
+    /// text is drawn by the UI, so it always appears on top of the scene.
+    pub fn draw_text_3d(
+        &mut self,
+        world_position: cgmath::Vector3<f32>,
+        text: impl Into<String>,
+        color: [f32; 4],
+    ) {
+        self.texts
+            .push((cgmath::Point3::from_vec(world_position), text.into(), color));
+    }
+
+    /// Project all the queued text to the screen and return the shapes that
+    /// should be pushed to the UI. Text behind the camera or off-screen is
+    /// skipped.
+    pub fn text_shapes(
+        &mut self,
+        fonts: &epaint::Fonts,
+        camera: &Camera,
+        screen_size: [f32; 2],
+    ) -> Vec<epaint::ClippedShape> {
+        self.texts
+            .drain(..)
+            .filter_map(|(position, text, [r, g, b, a])| {
+                let [x, y] = camera.world_to_screen(position, screen_size)?;
+                let galley = fonts.layout_no_wrap(
+                    text,
+                    epaint::FontId::monospace(14.0),
+                    epaint::Rgba::from_rgba_unmultiplied(r, g, b, a).into(),
+                );
+                let pos = epaint::pos2(x, y) - galley.size() * 0.5;
+                Some(epaint::ClippedShape {
+                    clip_rect: epaint::Rect::EVERYTHING,
+                    shape: epaint::Shape::Text(epaint::TextShape::new(
+                        pos,
+                        galley,
+                        epaint::Color32::default(),
+                    )),
+                })
+            })
+            .collect()
     }
 
     pub fn render(
@@ -150,47 +374,140 @@ impl Gizmos {
         depth_view: &wgpu::TextureView,
         camera: &Camera,
     ) {
-        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-            label: Some("gizmos render pass"),
-            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                view,
-                resolve_target: None,
-                ops: wgpu::Operations {
-                    load: wgpu::LoadOp::Load,
-                    store: wgpu::StoreOp::Store,
-                },
-            })],
-            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
-                view: depth_view,
-                depth_ops: Some(wgpu::Operations {
-                    load: wgpu::LoadOp::Load,
-                    store: wgpu::StoreOp::Store,
-                }),
-                stencil_ops: None,
-            }),
-            timestamp_writes: None,
-            occlusion_query_set: None,
-        });
+        let mut render_pass =
+            PassBuilder::overlay("gizmos render pass", view, depth_view).begin(encoder);
 
-        let instance_buffer =
-            renderer
-                .device
-                .create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                    label: Some("gizmos axis instances"),
-                    contents: bytemuck::cast_slice(self.axis.as_ref()),
-                    usage: wgpu::BufferUsages::VERTEX,
-                });
+        if self.cull {
+            self.axis.retain(|instance| {
+                camera.is_sphere_in_view(instance.position.into(), instance.scale.abs())
+            });
+            self.line_strips.cull(camera);
+            self.triangle_strips.cull(camera);
+        }
+
+        self.upload_buffer.clear();
+        let axis = (!self.axis.is_empty()).then(|| self.upload_buffer.push(&self.axis));
+        let line_strips = self.line_strips.push_to(&mut self.upload_buffer);
+        let triangle_strips = self.triangle_strips.push_to(&mut self.upload_buffer);
+        self.upload_buffer.upload(renderer);
+
+        let pipelines = if self.depth_test {
+            &self.depth_tested_pipelines
+        } else {
+            &self.on_top_pipelines
+        };
 
-        render_pass.set_pipeline(&self.pipeline);
-        render_pass.set_vertex_buffer(0, self.axis_mesh.vertex_buffer.slice(..));
-        render_pass.set_vertex_buffer(1, instance_buffer.slice(..));
-        render_pass.set_index_buffer(
-            self.axis_mesh.index_buffer.slice(..),
-            wgpu::IndexFormat::Uint16,
-        );
         render_pass.set_bind_group(0, &camera.bind_group, &[]);
-        render_pass.draw_indexed(0..self.axis_mesh.index_count, 0, 0..self.axis.len() as u32);
+
+        if let Some(axis) = axis {
+            render_pass.set_pipeline(&pipelines.lines);
+            render_pass.set_vertex_buffer(0, self.axis_mesh.vertex_buffer.slice(..));
+            render_pass.set_vertex_buffer(1, self.upload_buffer.slice(axis));
+            render_pass.set_index_buffer(
+                self.axis_mesh.index_buffer.slice(..),
+                wgpu::IndexFormat::Uint16,
+            );
+            render_pass.draw_indexed(0..self.axis_mesh.index_count, 0, 0..self.axis.len() as u32);
+        }
+
+        if let Some(line_strips) = line_strips {
+            self.line_strips.render(
+                &mut render_pass,
+                &pipelines.line_strips,
+                self.upload_buffer.slice(line_strips),
+                &self.origin_instance_buffer,
+            );
+        }
+        if let Some(triangle_strips) = triangle_strips {
+            self.triangle_strips.render(
+                &mut render_pass,
+                &pipelines.triangle_strips,
+                self.upload_buffer.slice(triangle_strips),
+                &self.origin_instance_buffer,
+            );
+        }
 
         self.axis.clear();
+        self.line_strips.clear();
+        self.triangle_strips.clear();
+        self.overflowed = false;
     }
 }
+
+fn create_pipeline(
+    renderer: &Renderer,
+    module: &wgpu::ShaderModule,
+    layout: &wgpu::PipelineLayout,
+    topology: wgpu::PrimitiveTopology,
+    depth_test: bool,
+    label: &str,
+) -> wgpu::RenderPipeline {
+    renderer
+        .device
+        .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some(label),
+            layout: Some(layout),
+            vertex: wgpu::VertexState {
+                module,
+                entry_point: "vertex_main",
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+                buffers: &[
+                    wgpu::VertexBufferLayout {
+                        array_stride: std::mem::size_of::<Vertex>() as wgpu::BufferAddress,
+                        step_mode: wgpu::VertexStepMode::Vertex,
+                        attributes: &[
+                            wgpu::VertexAttribute {
+                                format: wgpu::VertexFormat::Float32x3,
+                                offset: 0,
+                                shader_location: 0,
+                            },
+                            wgpu::VertexAttribute {
+                                format: wgpu::VertexFormat::Float32x4,
+                                offset: (4 * std::mem::size_of::<f32>()) as wgpu::BufferAddress,
+                                shader_location: 1,
+                            },
+                        ],
+                    },
+                    wgpu::VertexBufferLayout {
+                        array_stride: std::mem::size_of::<Instance>() as wgpu::BufferAddress,
+                        step_mode: wgpu::VertexStepMode::Instance,
+                        attributes: &wgpu::vertex_attr_array![
+                            2 => Float32x3,
+                            3 => Float32,
+                            4 => Float32x4,
+                            5 => Float32,
+                        ],
+                    },
+                ],
+            },
+            primitive: wgpu::PrimitiveState {
+                topology,
+                ..wgpu::PrimitiveState::default()
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: renderer.depth_format,
+                // Gizmos drawn on top don't hide each other either.
+                depth_write_enabled: depth_test,
+                depth_compare: if depth_test {
+                    renderer.depth_convention.closer()
+                } else {
+                    wgpu::CompareFunction::Always
+                },
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            fragment: Some(wgpu::FragmentState {
+                module,
+                entry_point: "fragment_main",
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: renderer.surface_config.format,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            multiview: None,
+            cache: None,
+        })
+}
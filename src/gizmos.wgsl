@@ -9,6 +9,9 @@ struct VertexInput {
     @location(0) position: vec3<f32>,
     @location(1) color: vec4<f32>,
     @location(2) obj_position: vec3<f32>,
+    @location(3) obj_scale: f32,
+    @location(4) obj_color: vec4<f32>,
+    @location(5) use_obj_color: f32,
 }
 
 struct VertexOutput {
@@ -17,11 +20,11 @@ struct VertexOutput {
 }
 
 @vertex fn vertex_main(vertex: VertexInput) -> VertexOutput {
-    let world_position = camera.projection_matrix * camera.view_matrix * vec4<f32>(vertex.position + vertex.obj_position, 1.0);
+    let world_position = camera.projection_matrix * camera.view_matrix * vec4<f32>(vertex.position * vertex.obj_scale + vertex.obj_position, 1.0);
 
     var output: VertexOutput;
     output.world_position = world_position;
-    output.color = vertex.color;
+    output.color = mix(vertex.color, vertex.obj_color, vertex.use_obj_color);
     return output;
 }
 
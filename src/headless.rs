@@ -0,0 +1,192 @@
+//! End to end tests that render on a software adapter, without a window. They
+//! pass without rendering if the platform has no fallback adapter.
+
+use std::sync::Mutex;
+
+use crate::{
+    app::{App, AppOptions},
+    lights::{PointLight, MAX_POINT_LIGHTS},
+    mesh::{Mesh, Vertex},
+    renderer::{Renderer, RendererBuilder},
+    scenes::Scene,
+};
+
+const WIDTH: u32 = 320;
+const HEIGHT: u32 = 180;
+
+/// Held by every test while it renders. Tests run in parallel, and software
+/// adapters don't cope well with several devices at once.
+static ADAPTER: Mutex<()> = Mutex::new(());
+
+/// A headless renderer on the fallback adapter, or `None` if there is none.
+/// Set `WGPU_BACKEND` to pick the backend.
+fn renderer() -> Option<Renderer> {
+    let renderer = RendererBuilder::default()
+        .with_backends(wgpu::util::backend_bits_from_env().unwrap_or(wgpu::Backends::all()))
+        .with_force_fallback_adapter(true)
+        .build_headless(WIDTH, HEIGHT);
+    if renderer.is_none() {
+        eprintln!("No fallback adapter available, skipping");
+    }
+    renderer
+}
+
+/// An app showing `scene`. Animations advance by a fixed step, so the frame is
+/// the same every run.
+fn app(renderer: &Renderer, scene: Scene) -> App {
+    let mut app = App::new(
+        renderer,
+        AppOptions {
+            fixed_time_step: Some(std::time::Duration::from_secs_f64(1.0 / 60.0)),
+            ..Default::default()
+        },
+    );
+    app.load_scene(renderer, scene);
+    // A window always gets a resize before its first frame.
+    app.resize(renderer);
+    app
+}
+
+/// Render a frame and read back the scene, without the UI.
+fn render(app: &mut App, renderer: &Renderer) -> Vec<u8> {
+    app.render(renderer);
+    let (width, height, pixels) = app
+        .read_pixels(renderer)
+        .expect("read back the rendered frame");
+    assert_eq!((width, height), (WIDTH, HEIGHT));
+    pixels
+}
+
+#[test]
+fn renders_the_cube() {
+    let _adapter = ADAPTER.lock().unwrap_or_else(|err| err.into_inner());
+    let Some(renderer) = renderer() else {
+        return;
+    };
+
+    let mut app = app(&renderer, Scene::Cube);
+    let pixels = render(&mut app, &renderer);
+
+    // The corner is always background, the cube is in the middle.
+    let background = &pixels[0..4];
+    let covered = pixels
+        .chunks_exact(4)
+        .filter(|pixel| *pixel != background)
+        .count();
+    assert!(covered > 0, "the frame only contains the background");
+
+    // A supersampled screenshot renders to its own targets.
+    let path = std::env::temp_dir().join("deferred_renderer_screenshot.png");
+    app.capture_screenshot(&renderer, &path, 2)
+        .expect("save the screenshot");
+    assert_eq!(image::image_dimensions(&path).ok(), Some((WIDTH, HEIGHT)));
+    std::fs::remove_file(path).ok();
+    let (_, _, kept) = app.read_pixels(&renderer).expect("the frame is kept");
+    assert!(kept == pixels, "the screenshot changed the last frame");
+
+    // Resizing replaces the scene texture with a blank one.
+    app.resize(&renderer);
+    assert!(app.read_pixels(&renderer).is_none());
+}
+
+#[test]
+fn renders_without_point_lights() {
+    let _adapter = ADAPTER.lock().unwrap_or_else(|err| err.into_inner());
+    let Some(renderer) = renderer() else {
+        return;
+    };
+
+    // The brightest color channel in the frame.
+    let brightest = |pixels: &[u8]| {
+        pixels
+            .chunks_exact(4)
+            .flat_map(|pixel| &pixel[..3])
+            .copied()
+            .max()
+            .unwrap_or(0)
+    };
+    // Well above the default ambient color, well below a lit surface.
+    const DARK: u8 = 48;
+
+    let mut app = app(&renderer, Scene::Cube);
+    let lit = render(&mut app, &renderer);
+    assert!(
+        brightest(&lit) > DARK,
+        "the key light doesn't light the cube"
+    );
+
+    // Only the ambient color is left, the sun is off without a day cycle.
+    app.set_point_lights(&renderer, vec![]);
+    let unlit = render(&mut app, &renderer);
+    assert!(brightest(&unlit) <= DARK, "the frame is lit without lights");
+}
+
+#[test]
+fn shades_the_last_point_light() {
+    let _adapter = ADAPTER.lock().unwrap_or_else(|err| err.into_inner());
+    let Some(renderer) = renderer() else {
+        return;
+    };
+
+    // As many lights as the lighting pass takes, all off. The first light
+    // follows the light sliders, so it is on either way.
+    let mut point_lights = vec![PointLight::default().with_intensity(0.0); MAX_POINT_LIGHTS];
+
+    let mut app = app(&renderer, Scene::Cube);
+    app.set_point_lights(&renderer, point_lights.clone());
+    let without = render(&mut app, &renderer);
+
+    point_lights[MAX_POINT_LIGHTS - 1] = PointLight::default()
+        .with_position([-2.0, 3.0, 2.0])
+        .with_intensity(2.0)
+        .with_color([1.0, 0.0, 0.0]);
+    app.set_point_lights(&renderer, point_lights);
+    let with = render(&mut app, &renderer);
+    assert!(with != without, "the last light is left out");
+}
+
+#[test]
+fn windows_share_the_scene() {
+    let _adapter = ADAPTER.lock().unwrap_or_else(|err| err.into_inner());
+    let Some(renderer) = renderer() else {
+        return;
+    };
+
+    let mut first = app(&renderer, Scene::Cube);
+    let mut second = App::with_shared_scene(&renderer, AppOptions::default(), first.shared_scene());
+    second.resize(&renderer);
+    assert_eq!(second.scene(), Scene::Cube);
+    render(&mut second, &renderer);
+
+    // A scene loaded in one window shows up in the other on its next frame.
+    first.load_scene(&renderer, Scene::SphereAndPlane);
+    assert_eq!(second.scene(), Scene::Cube);
+    render(&mut second, &renderer);
+    assert_eq!(second.scene(), Scene::SphereAndPlane);
+}
+
+#[test]
+fn mesh_survives_read_back() {
+    let _adapter = ADAPTER.lock().unwrap_or_else(|err| err.into_inner());
+    let Some(renderer) = renderer() else {
+        return;
+    };
+
+    let reader = std::io::Cursor::new(include_bytes!("../res/cube.obj"));
+    let mut mesh = Mesh::<Vertex>::from_reader(reader).expect("the bundled cube loads");
+    mesh.update_tangents();
+
+    let gpu_mesh = mesh.upload_to_gpu_with_usage(&renderer, wgpu::BufferUsages::COPY_SRC);
+    let read_back = gpu_mesh
+        .read_back(&renderer)
+        .expect("the buffers can be copied from");
+
+    assert_eq!(
+        bytemuck::cast_slice::<_, u8>(&mesh.vertices),
+        bytemuck::cast_slice::<_, u8>(&read_back.vertices),
+    );
+    assert_eq!(mesh.indices, read_back.indices);
+
+    // Buffers without COPY_SRC can't be read back.
+    assert!(mesh.upload_to_gpu(&renderer).read_back(&renderer).is_none());
+}
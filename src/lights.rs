@@ -1,90 +1,395 @@
-use wgpu::util::DeviceExt;
+use cgmath::InnerSpace;
 
-use crate::Renderer;
+use crate::{
+    shadows::{self, ShadowAtlas},
+    Renderer,
+};
 
-#[derive(Clone, Copy, bytemuck::NoUninit)]
-#[repr(C)]
+/// The most point lights the lighting pass reads. Lights past this are kept
+/// in [Lights], but don't light the scene. Enough for the largest light grid
+/// on top of the lights of a scene, while the uniform buffer stays within the
+/// 16 KiB of the compatibility profile.
+pub const MAX_POINT_LIGHTS: usize = 320;
+
+/// Only the first point lights can cast shadows, the atlas has no room for
+/// more.
+pub const MAX_SHADOWED_POINT_LIGHTS: usize = shadows::MAX_SHADOW_CASTERS;
+
+#[derive(Clone, Copy)]
 pub struct PointLight {
     pub position: [f32; 3],
     pub intensity: f32,
     pub color: [f32; 3],
     pub shininess: f32,
-    pub ambient: f32,
-    _dummy: [f32; 3],
+    /// Render a shadow map for this light into the shadow atlas. Ignored past
+    /// the first [MAX_SHADOWED_POINT_LIGHTS] lights.
+    pub casts_shadows: bool,
+}
+
+/// A white light at the origin that doesn't cast shadows.
+impl Default for PointLight {
+    fn default() -> Self {
+        Self {
+            position: [0.0, 0.0, 0.0],
+            intensity: 1.0,
+            color: [1.0, 1.0, 1.0],
+            shininess: 10.0,
+            casts_shadows: false,
+        }
+    }
 }
 
 impl PointLight {
-    pub fn new(
-        position: [f32; 3],
-        intensity: f32,
-        color: [f32; 3],
-        shininess: f32,
-        ambient: f32,
-    ) -> Self {
+    pub fn with_position(mut self, position: [f32; 3]) -> Self {
+        self.position = position;
+        self
+    }
+
+    pub fn with_intensity(mut self, intensity: f32) -> Self {
+        self.intensity = intensity;
+        self
+    }
+
+    pub fn with_color(mut self, color: [f32; 3]) -> Self {
+        self.color = color;
+        self
+    }
+
+    pub fn with_shininess(mut self, shininess: f32) -> Self {
+        self.shininess = shininess;
+        self
+    }
+
+    pub fn with_casts_shadows(mut self, casts_shadows: bool) -> Self {
+        self.casts_shadows = casts_shadows;
+        self
+    }
+
+    const SHADOW_NEAR: f32 = 0.1;
+    const SHADOW_FAR: f32 = 50.0;
+
+    /// The shadow map of a point light covers a 90 degree cone looking at the
+    /// center of the scene.
+    fn shadow_view_projection(&self) -> cgmath::Matrix4<f32> {
+        let position = cgmath::Point3::from(self.position);
+        let target = cgmath::Point3::new(0.0, 0.0, 0.0);
+        let up = if position.x.abs() < f32::EPSILON && position.z.abs() < f32::EPSILON {
+            cgmath::Vector3::unit_z()
+        } else {
+            cgmath::Vector3::unit_y()
+        };
+        shadows::OPENGL_TO_WGPU_MATRIX
+            * cgmath::perspective(cgmath::Deg(90.0), 1.0, Self::SHADOW_NEAR, Self::SHADOW_FAR)
+            * cgmath::Matrix4::look_at_rh(position, target, up)
+    }
+}
+
+/// A light infinitely far away, like the sun, lighting everything from the
+/// same direction.
+#[derive(Clone, Copy)]
+pub struct DirectionalLight {
+    /// The direction the light travels in.
+    pub direction: [f32; 3],
+    pub intensity: f32,
+    pub color: [f32; 3],
+    /// Render a shadow map for this light into the shadow atlas.
+    pub casts_shadows: bool,
+}
+
+impl DirectionalLight {
+    pub fn new(direction: [f32; 3], intensity: f32, color: [f32; 3]) -> Self {
         Self {
-            position,
+            direction,
             intensity,
             color,
-            shininess,
-            ambient,
-            _dummy: [0.0; 3],
+            casts_shadows: false,
         }
     }
+
+    const SHADOW_EXTENT: f32 = 6.0;
+    const SHADOW_NEAR: f32 = 0.1;
+    const SHADOW_FAR: f32 = Self::SHADOW_EXTENT * 4.0;
+
+    /// The shadow map of a directional light is an orthographic projection
+    /// covering the area around the center of the scene.
+    fn shadow_view_projection(&self) -> cgmath::Matrix4<f32> {
+        const EXTENT: f32 = DirectionalLight::SHADOW_EXTENT;
+
+        let direction = cgmath::Vector3::from(self.direction).normalize();
+        let target = cgmath::Point3::new(0.0, 0.0, 0.0);
+        let position = target - direction * EXTENT * 2.0;
+        let up = if direction.x.abs() < f32::EPSILON && direction.z.abs() < f32::EPSILON {
+            cgmath::Vector3::unit_z()
+        } else {
+            cgmath::Vector3::unit_y()
+        };
+        shadows::OPENGL_TO_WGPU_MATRIX
+            * cgmath::ortho(
+                -EXTENT,
+                EXTENT,
+                -EXTENT,
+                EXTENT,
+                Self::SHADOW_NEAR,
+                Self::SHADOW_FAR,
+            )
+            * cgmath::Matrix4::look_at_rh(position, target, up)
+    }
+}
+
+/// How the lighting pass filters shadow map lookups.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ShadowFilter {
+    /// Percentage-closer filtering with a fixed size kernel, which gives the
+    /// same soft edge to every shadow.
+    Pcf,
+    /// Percentage-closer soft shadows, where the penumbra grows with the
+    /// distance between the shadow caster and the receiver.
+    Pcss,
+}
+
+/// Where a light's shadow map is in the shadow atlas. Lights without a shadow
+/// have an empty `atlas_rect`.
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+#[repr(C)]
+struct GpuShadow {
+    view_projection: [[f32; 4]; 4],
+    atlas_rect: [f32; 4],
+    /// Near and far planes, and 1.0 for perspective projections. Used to
+    /// linearize depth values for PCSS.
+    depth_range: [f32; 4],
 }
 
+#[derive(Clone, Copy, bytemuck::NoUninit)]
+#[repr(C)]
+struct GpuShadowSettings {
+    filter_mode: u32,
+    softness: f32,
+    constant_bias: f32,
+    slope_bias: f32,
+}
+
+/// `Pod` rather than `NoUninit`, so it can be uploaded in an array.
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+#[repr(C)]
+struct GpuPointLight {
+    position: [f32; 3],
+    intensity: f32,
+    color: [f32; 3],
+    shininess: f32,
+}
+
+#[derive(Clone, Copy, bytemuck::NoUninit)]
+#[repr(C)]
+struct GpuDirectionalLight {
+    direction: [f32; 3],
+    intensity: f32,
+    color: [f32; 3],
+    _padding: f32,
+    shadow: GpuShadow,
+}
+
+#[derive(Clone, Copy, bytemuck::NoUninit)]
+#[repr(C)]
+struct GpuLights {
+    point_lights: [GpuPointLight; MAX_POINT_LIGHTS],
+    point_light_shadows: [GpuShadow; MAX_SHADOWED_POINT_LIGHTS],
+    directional_light: GpuDirectionalLight,
+    sky_color: [f32; 4],
+    ambient_color: [f32; 4],
+    shadow_settings: GpuShadowSettings,
+    point_light_count: u32,
+    _padding: [u32; 3],
+}
+
+/// The lights of the scene, uploaded to a uniform buffer shared by the
+/// lighting passes.
+///
+/// Having no point lights at all is fine: the buffer always has room for
+/// [MAX_POINT_LIGHTS] and the shaders only read up to the count, so surfaces
+/// are then lit by the ambient color and the directional light alone.
 pub struct Lights {
-    pub point_light: PointLight,
+    point_lights: Vec<PointLight>,
+    /// A light kept at the camera, lit before any of the other point lights
+    /// so it always gets a slot.
+    headlight: Option<PointLight>,
+    pub directional_light: DirectionalLight,
+    /// The color of the background where there is no geometry.
+    pub sky_color: [f32; 3],
+    /// Clear the background to transparent black instead of the sky color,
+    /// for cutouts of the scene.
+    pub transparent_sky: bool,
+    /// Light added to every surface, independent of any light source.
+    pub ambient_color: [f32; 3],
+    pub shadow_filter: ShadowFilter,
+    /// Scales the size of the shadow penumbra, like the size of the light.
+    pub shadow_softness: f32,
+    /// How far surfaces are moved towards the light before they are compared
+    /// with the shadow map, in world units. Too little causes shadow acne,
+    /// too much detaches shadows from their casters.
+    pub shadow_constant_bias: f32,
+    /// Added to [Lights::shadow_constant_bias], scaled by the tangent of the
+    /// angle between the surface normal and the light, so surfaces at grazing
+    /// angles get more bias than surfaces facing the light.
+    pub shadow_slope_bias: f32,
     buffer: wgpu::Buffer,
     pub bind_group_layout: wgpu::BindGroupLayout,
     pub bind_group: wgpu::BindGroup,
 }
 
 impl Lights {
-    pub fn new(renderer: &Renderer, point_light: PointLight) -> Self {
+    pub fn new(
+        renderer: &Renderer,
+        point_lights: Vec<PointLight>,
+        shadow_atlas: &ShadowAtlas,
+    ) -> Self {
+        // The directional light is off until it is set.
+        let directional_light = DirectionalLight::new([0.0, -1.0, 0.0], 0.0, [1.0, 1.0, 1.0]);
+
         let bind_group_layout =
             renderer
                 .device
                 .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
                     label: Some("lights bind group layout"),
-                    entries: &[wgpu::BindGroupLayoutEntry {
-                        binding: 0,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        ty: wgpu::BindingType::Buffer {
-                            ty: wgpu::BufferBindingType::Uniform,
-                            has_dynamic_offset: false,
-                            min_binding_size: None,
+                    entries: &[
+                        wgpu::BindGroupLayoutEntry {
+                            binding: 0,
+                            visibility: wgpu::ShaderStages::FRAGMENT,
+                            ty: wgpu::BindingType::Buffer {
+                                ty: wgpu::BufferBindingType::Uniform,
+                                has_dynamic_offset: false,
+                                min_binding_size: None,
+                            },
+                            count: None,
+                        },
+                        wgpu::BindGroupLayoutEntry {
+                            binding: 1,
+                            visibility: wgpu::ShaderStages::FRAGMENT,
+                            ty: wgpu::BindingType::Texture {
+                                sample_type: wgpu::TextureSampleType::Depth,
+                                view_dimension: wgpu::TextureViewDimension::D2,
+                                multisampled: false,
+                            },
+                            count: None,
                         },
-                        count: None,
-                    }],
+                        wgpu::BindGroupLayoutEntry {
+                            binding: 2,
+                            visibility: wgpu::ShaderStages::FRAGMENT,
+                            ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Comparison),
+                            count: None,
+                        },
+                        // The shadow atlas again, to read the depths themselves.
+                        wgpu::BindGroupLayoutEntry {
+                            binding: 3,
+                            visibility: wgpu::ShaderStages::FRAGMENT,
+                            ty: wgpu::BindingType::Texture {
+                                sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                                view_dimension: wgpu::TextureViewDimension::D2,
+                                multisampled: false,
+                            },
+                            count: None,
+                        },
+                    ],
                 });
 
-        let buffer = renderer
-            .device
-            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                label: Some("lights buffer"),
-                contents: bytemuck::cast_slice(&[point_light]),
-                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
-            });
+        let buffer = renderer.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("lights buffer"),
+            size: std::mem::size_of::<GpuLights>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
 
         let bind_group = renderer
             .device
             .create_bind_group(&wgpu::BindGroupDescriptor {
                 label: Some("lights bind group"),
                 layout: &bind_group_layout,
-                entries: &[wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: buffer.as_entire_binding(),
-                }],
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: buffer.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::TextureView(&shadow_atlas.texture.view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: wgpu::BindingResource::Sampler(&shadow_atlas.texture.sampler),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 3,
+                        resource: wgpu::BindingResource::TextureView(&shadow_atlas.texture.view),
+                    },
+                ],
             });
 
-        Self {
-            point_light,
+        let lights = Self {
+            point_lights,
+            headlight: None,
+            directional_light,
+            sky_color: [0.0, 0.0, 0.0],
+            transparent_sky: false,
+            ambient_color: [0.0, 0.0, 0.0],
+            shadow_filter: ShadowFilter::Pcf,
+            shadow_softness: 1.0,
+            shadow_constant_bias: 0.02,
+            shadow_slope_bias: 0.02,
             buffer,
             bind_group_layout,
             bind_group,
-        }
+        };
+        lights.update_buffer(renderer);
+        lights
     }
 
+    /// The view projection matrices of all the shadow casting lights, in the
+    /// order of their tiles in the shadow atlas.
+    pub fn shadow_view_projections(&self) -> Vec<cgmath::Matrix4<f32>> {
+        let point_lights = self
+            .lit_point_lights()
+            .into_iter()
+            .take(MAX_SHADOWED_POINT_LIGHTS)
+            .filter(|point_light| point_light.casts_shadows)
+            .map(PointLight::shadow_view_projection);
+        let directional_light = self
+            .directional_light
+            .casts_shadows
+            .then(|| self.directional_light.shadow_view_projection());
+
+        point_lights
+            .chain(directional_light)
+            .take(shadows::MAX_SHADOW_CASTERS)
+            .collect()
+    }
+
+    /// The number of point lights, including any past [MAX_POINT_LIGHTS].
+    #[allow(dead_code)]
+    pub fn len(&self) -> usize {
+        self.point_lights.len()
+    }
+
+    #[allow(dead_code)]
+    pub fn is_empty(&self) -> bool {
+        self.point_lights.is_empty()
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, PointLight> {
+        self.point_lights.iter()
+    }
+
+    /// Changes to the light are uploaded by the next call to
+    /// [Lights::update_buffer] or any of the setters.
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut PointLight> {
+        self.point_lights.get_mut(index)
+    }
+
+    /// Replace all the point lights. `point_lights` may be empty.
+    pub fn set_point_lights(&mut self, renderer: &Renderer, point_lights: Vec<PointLight>) {
+        self.point_lights = point_lights;
+        self.update_buffer(renderer);
+    }
+
+    /// Move the first point light, if there is one.
     pub fn move_to(
         &mut self,
         renderer: &Renderer,
@@ -92,15 +397,163 @@ impl Lights {
         intensity: f32,
         color: [f32; 3],
         shininess: f32,
-        ambient: f32,
     ) {
-        self.point_light.position = position;
-        self.point_light.intensity = intensity;
-        self.point_light.color = color;
-        self.point_light.shininess = shininess;
-        self.point_light.ambient = ambient;
+        if let Some(point_light) = self.point_lights.first_mut() {
+            point_light.position = position;
+            point_light.intensity = intensity;
+            point_light.color = color;
+            point_light.shininess = shininess;
+        }
+        self.update_buffer(renderer);
+    }
+
+    pub fn set_directional_light(
+        &mut self,
+        renderer: &Renderer,
+        directional_light: DirectionalLight,
+        sky_color: [f32; 3],
+    ) {
+        self.directional_light = directional_light;
+        self.sky_color = sky_color;
+        self.update_buffer(renderer);
+    }
+
+    pub fn set_transparent_sky(&mut self, renderer: &Renderer, transparent_sky: bool) {
+        self.transparent_sky = transparent_sky;
+        self.update_buffer(renderer);
+    }
+
+    pub fn set_ambient_color(&mut self, renderer: &Renderer, ambient_color: [f32; 3]) {
+        self.ambient_color = ambient_color;
+        self.update_buffer(renderer);
+    }
+
+    pub fn set_shadow_filter(
+        &mut self,
+        renderer: &Renderer,
+        shadow_filter: ShadowFilter,
+        shadow_softness: f32,
+    ) {
+        self.shadow_filter = shadow_filter;
+        self.shadow_softness = shadow_softness;
+        self.update_buffer(renderer);
+    }
+
+    /// Set the depth bias of all shadows, see [Lights::shadow_constant_bias]
+    /// and [Lights::shadow_slope_bias].
+    pub fn set_shadow_bias(&mut self, renderer: &Renderer, constant_bias: f32, slope_bias: f32) {
+        self.shadow_constant_bias = constant_bias;
+        self.shadow_slope_bias = slope_bias;
+        self.update_buffer(renderer);
+    }
+
+    /// Replace the headlight, or turn it off with `None`.
+    pub fn set_headlight(&mut self, renderer: &Renderer, headlight: Option<PointLight>) {
+        self.headlight = headlight;
+        self.update_buffer(renderer);
+    }
+
+    /// The number of point lights that light the scene, the headlight included.
+    /// At most [MAX_POINT_LIGHTS].
+    pub fn lit_len(&self) -> usize {
+        self.lit_point_lights().len()
+    }
+
+    /// The point lights that are uploaded to the lighting pass, the headlight
+    /// first.
+    fn lit_point_lights(&self) -> Vec<&PointLight> {
+        self.headlight
+            .iter()
+            .chain(self.point_lights.iter())
+            .take(MAX_POINT_LIGHTS)
+            .collect()
+    }
+
+    pub fn update_buffer(&self, renderer: &Renderer) {
+        // Hand out atlas tiles in the same order as `shadow_view_projections`.
+        let mut next_tile = 0;
+        let mut shadow =
+            |casts_shadows: bool, view_projection: cgmath::Matrix4<f32>, depth_range: [f32; 4]| {
+                let atlas_rect = if casts_shadows {
+                    let rect = shadows::tile_rect(next_tile);
+                    next_tile += 1;
+                    rect
+                } else {
+                    None
+                };
+                GpuShadow {
+                    view_projection: view_projection.into(),
+                    atlas_rect: atlas_rect.unwrap_or([0.0; 4]),
+                    depth_range,
+                }
+            };
+
+        // Entries past `point_light_count` aren't read, but still need a value.
+        let unused = PointLight::default().with_intensity(0.0);
+        let lit_point_lights = self.lit_point_lights();
+        let point_lights: [GpuPointLight; MAX_POINT_LIGHTS] = std::array::from_fn(|index| {
+            let point_light = lit_point_lights.get(index).copied().unwrap_or(&unused);
+            GpuPointLight {
+                position: point_light.position,
+                intensity: point_light.intensity,
+                color: point_light.color,
+                shininess: point_light.shininess,
+            }
+        });
+        let point_light_shadows: [GpuShadow; MAX_SHADOWED_POINT_LIGHTS] =
+            std::array::from_fn(|index| {
+                let point_light = lit_point_lights.get(index).copied().unwrap_or(&unused);
+                shadow(
+                    point_light.casts_shadows,
+                    point_light.shadow_view_projection(),
+                    [PointLight::SHADOW_NEAR, PointLight::SHADOW_FAR, 1.0, 0.0],
+                )
+            });
+        let directional_light = GpuDirectionalLight {
+            direction: self.directional_light.direction,
+            intensity: self.directional_light.intensity,
+            color: self.directional_light.color,
+            _padding: 0.0,
+            shadow: shadow(
+                self.directional_light.casts_shadows,
+                self.directional_light.shadow_view_projection(),
+                [
+                    DirectionalLight::SHADOW_NEAR,
+                    DirectionalLight::SHADOW_FAR,
+                    0.0,
+                    0.0,
+                ],
+            ),
+        };
+
+        let sky_color = if self.transparent_sky {
+            [0.0; 4]
+        } else {
+            let [r, g, b] = self.sky_color;
+            [r, g, b, 1.0]
+        };
+        let [r, g, b] = self.ambient_color;
+        let ambient_color = [r, g, b, 1.0];
+        let lights = GpuLights {
+            point_lights,
+            point_light_shadows,
+            directional_light,
+            sky_color,
+            ambient_color,
+            shadow_settings: GpuShadowSettings {
+                filter_mode: match self.shadow_filter {
+                    ShadowFilter::Pcf => 0,
+                    ShadowFilter::Pcss => 1,
+                },
+                softness: self.shadow_softness,
+                constant_bias: self.shadow_constant_bias,
+                slope_bias: self.shadow_slope_bias,
+            },
+            point_light_count: lit_point_lights.len() as u32,
+            _padding: [0; 3],
+        };
         renderer
             .queue
-            .write_buffer(&self.buffer, 0, bytemuck::cast_slice(&[self.point_light]));
+            .write_buffer(&self.buffer, 0, bytemuck::cast_slice(&[lights]));
     }
 }
@@ -1,42 +1,66 @@
-use std::sync::Arc;
+use std::{collections::HashMap, sync::Arc};
 
 use winit::{
     application::ApplicationHandler,
     dpi::{LogicalSize, PhysicalSize},
     event::{ElementState, WindowEvent},
     event_loop::{ActiveEventLoop, EventLoop},
-    keyboard::PhysicalKey,
+    keyboard::{KeyCode, ModifiersState, PhysicalKey},
     window::WindowId,
 };
 
+mod allocations;
 mod app;
+mod blit;
 mod camera;
+mod debug_dump;
+mod decals;
+mod deferred_renderer;
+mod dof;
+mod frame_stats;
+mod gbuffer;
 mod gizmos;
+#[cfg(test)]
+mod headless;
 mod lights;
+mod matcap;
 mod material;
 mod mesh;
 mod mesh_render_pipeline;
+mod motion_blur;
+mod particles;
+mod pass_builder;
+mod passes;
+mod planar_reflection;
+mod post_effects;
+mod renderer;
+mod scenes;
+mod shadows;
 mod texture;
+mod transparent_render_pipeline;
 mod ui;
+mod upload_buffer;
+mod wireframe;
 
-struct Renderer {
-    pub device: wgpu::Device,
-    pub queue: wgpu::Queue,
-    pub surface: wgpu::Surface<'static>,
-    pub surface_config: wgpu::SurfaceConfiguration,
+use gbuffer::NormalSpace;
+use renderer::{enumerate_adapters, AdapterSelector, LimitsProfile, Renderer, RendererBuilder};
+
+/// A window with its own surface and view of the scene.
+struct WindowState {
+    window: Arc<winit::window::Window>,
+    renderer: Renderer,
+    app: app::App,
 }
 
-enum AppState {
-    Uninitialized,
-    Initialized {
-        window: Arc<winit::window::Window>,
-        renderer: Renderer,
-        app: app::App,
-    },
+#[derive(Default)]
+struct AppState {
+    windows: HashMap<WindowId, WindowState>,
+    modifiers: ModifiersState,
 }
 
-impl ApplicationHandler for AppState {
-    fn resumed(&mut self, event_loop: &ActiveEventLoop) {
+impl AppState {
+    /// Open a new window showing the scene with its own camera.
+    fn open_window(&mut self, event_loop: &ActiveEventLoop) {
         let window = Arc::new(
             event_loop
                 .create_window(
@@ -47,131 +71,191 @@ impl ApplicationHandler for AppState {
                 .expect("create window"),
         );
 
-        let PhysicalSize { width, height } = window.inner_size();
-
-        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
-            backends: wgpu::Backends::PRIMARY,
+        let options = app::AppOptions {
+            // Set `WGPU_NO_POSITION` to reconstruct positions from depth.
+            store_position: std::env::var_os("WGPU_NO_POSITION").is_none(),
+            // Set `WGPU_FRAMES_IN_FLIGHT` to 1..3 to stop the uncapped
+            // frame rate from queueing up frames.
+            max_frames_in_flight: std::env::var("WGPU_FRAMES_IN_FLIGHT")
+                .ok()
+                .and_then(|value| value.parse().ok()),
             ..Default::default()
-        });
+        };
 
-        let adapter =
-            pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions::default()))
-                .expect("request adapter");
+        // Every window renders with the same device and shows the same scene,
+        // each with its own camera.
+        let (renderer, mut app) = match self.windows.values().next() {
+            Some(other) => {
+                let renderer = other.renderer.for_window(Arc::clone(&window));
+                let app = app::App::with_shared_scene(&renderer, options, other.app.shared_scene());
+                (renderer, app)
+            }
+            None => {
+                let renderer = create_renderer(Arc::clone(&window));
+                let app = app::App::new(&renderer, options);
+                (renderer, app)
+            }
+        };
+        app.on_scale_factor_changed(window.scale_factor());
+
+        // Set `WGPU_MATCAP` to the path of a PNG to use as the matcap.
+        if let Some(path) = std::env::var_os("WGPU_MATCAP") {
+            let loaded = std::fs::read(&path)
+                .map_err(|err| println!("error: {err:?}"))
+                .and_then(|bytes| app.load_matcap(&renderer, &bytes));
+            if loaded.is_err() {
+                eprintln!("Could not load the matcap from {}", path.to_string_lossy());
+            }
+        }
 
-        let (device, queue) = pollster::block_on(adapter.request_device(
-            &wgpu::DeviceDescriptor {
-                required_features: wgpu::Features::CLEAR_TEXTURE,
-                ..Default::default()
+        self.windows.insert(
+            window.id(),
+            WindowState {
+                window,
+                renderer,
+                app,
             },
-            None,
-        ))
-        .expect("request device");
-
-        let surface = instance
-            .create_surface(Arc::clone(&window))
-            .expect("create surface");
-
-        let surface_caps = surface.get_capabilities(&adapter);
-
-        // Find a sRGB surface format or use the first.
-        let format = surface_caps
-            .formats
-            .iter()
-            .find(|cap| cap.is_srgb())
-            .copied()
-            .unwrap_or(surface_caps.formats[0]);
-
-        let mut surface_config = surface
-            .get_default_config(&adapter, width, height)
-            .expect("surface get default configuration");
-        surface_config.format = format;
-        surface_config.present_mode = wgpu::PresentMode::AutoNoVsync;
-        // surface_config.present_mode = wgpu::PresentMode::AutoVsync;
-
-        surface.configure(&device, &surface_config);
-
-        let renderer = Renderer {
-            device,
-            queue,
-            surface,
-            surface_config,
-        };
+        );
+    }
+}
 
-        let app = app::App::new(&renderer);
+/// The renderer of the first window, set up from the environment.
+fn create_renderer(window: Arc<winit::window::Window>) -> Renderer {
+    let backends = wgpu::util::backend_bits_from_env().unwrap_or(wgpu::Backends::PRIMARY);
+
+    // Set `WGPU_LIST_ADAPTERS` to see what `WGPU_ADAPTER` can pick from.
+    if std::env::var_os("WGPU_LIST_ADAPTERS").is_some() {
+        for (index, info) in enumerate_adapters(backends).iter().enumerate() {
+            println!(
+                "Adapter {index}: {} ({:?}, {:?}, driver: {} {})",
+                info.name, info.device_type, info.backend, info.driver, info.driver_info
+            );
+        }
+    }
+
+    RendererBuilder::default()
+        .with_backends(backends)
+        // Prefer the discrete GPU, unless overridden with `WGPU_POWER_PREF=low`.
+        .with_power_preference(
+            wgpu::util::power_preference_from_env()
+                .unwrap_or(wgpu::PowerPreference::HighPerformance),
+        )
+        .with_present_mode(wgpu::PresentMode::AutoNoVsync)
+        // Set `WGPU_COMPAT` to try the constrained profile on any backend.
+        .with_limits_profile(if std::env::var_os("WGPU_COMPAT").is_some() {
+            LimitsProfile::Compatibility
+        } else {
+            LimitsProfile::Auto
+        })
+        // Set `WGPU_VIEW_NORMALS` to store view space normals in the
+        // G-buffer.
+        .with_normal_space(if std::env::var_os("WGPU_VIEW_NORMALS").is_some() {
+            NormalSpace::View
+        } else {
+            NormalSpace::World
+        })
+        // Set `WGPU_ADAPTER` to an index or part of a name to pick the
+        // adapter.
+        .with_adapter(
+            std::env::var("WGPU_ADAPTER")
+                .ok()
+                .and_then(|value| value.parse::<AdapterSelector>().ok()),
+        )
+        .build(window)
+}
+
+impl ApplicationHandler for AppState {
+    fn resumed(&mut self, event_loop: &ActiveEventLoop) {
+        if self.windows.is_empty() {
+            self.open_window(event_loop);
+            return;
+        }
 
-        *self = AppState::Initialized {
+        // Coming back from `suspended`, the windows need their surfaces again,
+        // and may have changed size in the meantime.
+        for WindowState {
             window,
             renderer,
             app,
+        } in self.windows.values_mut()
+        {
+            renderer.resume(Arc::clone(window));
+            app.resize(renderer);
+            window.request_redraw();
+        }
+    }
+
+    fn suspended(&mut self, _event_loop: &ActiveEventLoop) {
+        // The surfaces can't be used until the app resumes, everything else
+        // is kept.
+        for WindowState { renderer, .. } in self.windows.values_mut() {
+            renderer.suspend();
         }
     }
 
     fn window_event(
         &mut self,
-        _event_loop: &ActiveEventLoop,
-        _window_id: WindowId,
+        event_loop: &ActiveEventLoop,
+        window_id: WindowId,
         event: WindowEvent,
     ) {
         use winit::event::WindowEvent;
 
+        // Ctrl+N opens another window to compare views of the scene.
+        if let WindowEvent::KeyboardInput { event, .. } = &event {
+            if event.state.is_pressed()
+                && event.physical_key == PhysicalKey::Code(KeyCode::KeyN)
+                && self.modifiers.control_key()
+            {
+                self.open_window(event_loop);
+                return;
+            }
+        }
+
+        if let WindowEvent::ModifiersChanged(modifiers) = &event {
+            self.modifiers = modifiers.state();
+        }
+
+        let Some(WindowState {
+            window,
+            renderer,
+            app,
+        }) = self.windows.get_mut(&window_id)
+        else {
+            return;
+        };
+
         match event {
-            WindowEvent::CloseRequested => _event_loop.exit(),
+            WindowEvent::CloseRequested => {
+                self.windows.remove(&window_id);
+                if self.windows.is_empty() {
+                    event_loop.exit();
+                }
+            }
 
             WindowEvent::Resized(size) => {
-                let Self::Initialized {
-                    window,
-                    renderer,
-                    app,
-                } = self
-                else {
-                    return;
-                };
-
                 let PhysicalSize { width, height } = size;
-                renderer.surface_config.width = width;
-                renderer.surface_config.height = height;
-                renderer
-                    .surface
-                    .configure(&renderer.device, &renderer.surface_config);
-
+                renderer.resize(width, height);
                 app.resize(renderer);
 
                 window.request_redraw();
             }
 
-            WindowEvent::RedrawRequested => {
-                let Self::Initialized {
-                    window,
-                    renderer,
-                    app,
-                    ..
-                } = self
-                else {
-                    return;
-                };
-
+            // There is nothing to show while suspended, `resumed` asks for a
+            // redraw again.
+            WindowEvent::RedrawRequested if renderer.surface.is_some() => {
                 app.render(renderer);
                 window.request_redraw();
             }
 
-            WindowEvent::MouseInput { button, state, .. } => {
-                let Self::Initialized { app, .. } = self else {
-                    return;
-                };
-
-                match state {
-                    ElementState::Pressed => app.on_mouse_down(button),
-                    ElementState::Released => app.on_mouse_up(button),
-                }
-            }
+            WindowEvent::MouseInput { button, state, .. } => match state {
+                ElementState::Pressed => app.on_mouse_down(button),
+                ElementState::Released => app.on_mouse_up(button),
+            },
 
             WindowEvent::MouseWheel { delta, .. } => {
                 use winit::event::MouseScrollDelta;
 
-                let Self::Initialized { app, .. } = self else {
-                    return;
-                };
-
                 let delta = match delta {
                     MouseScrollDelta::LineDelta(_, y) => y,
                     MouseScrollDelta::PixelDelta(pos) => pos.y as f32,
@@ -180,18 +264,18 @@ impl ApplicationHandler for AppState {
             }
 
             WindowEvent::CursorMoved { position, .. } => {
-                let Self::Initialized { app, .. } = self else {
-                    return;
-                };
-
                 app.on_mouse_moved(position.x as f32, position.y as f32);
             }
 
-            WindowEvent::KeyboardInput { event, .. } => {
-                let Self::Initialized { app, .. } = self else {
-                    return;
-                };
+            WindowEvent::ScaleFactorChanged { scale_factor, .. } => {
+                app.on_scale_factor_changed(scale_factor);
+            }
 
+            WindowEvent::ModifiersChanged(modifiers) => {
+                app.on_modifiers_changed(modifiers.state());
+            }
+
+            WindowEvent::KeyboardInput { event, .. } => {
                 if let PhysicalKey::Code(key_code) = event.physical_key {
                     if event.state.is_pressed() {
                         app.on_key_pressed(key_code);
@@ -208,6 +292,6 @@ impl ApplicationHandler for AppState {
 
 fn main() {
     let event_loop = EventLoop::new().expect("create event loop");
-    let mut app = AppState::Uninitialized;
+    let mut app = AppState::default();
     event_loop.run_app(&mut app).expect("run app")
 }
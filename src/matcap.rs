@@ -0,0 +1,190 @@
+use cgmath::InnerSpace;
+
+use crate::{camera::Camera, gbuffer, pass_builder::PassBuilder, texture::Texture, Renderer};
+
+/// Shades the G-buffer with a matcap ("material capture"), an image of a lit
+/// sphere looked up by the view space normal. It needs no lights, which makes
+/// it handy to look at the shape of a model.
+pub struct Matcap {
+    pipeline: wgpu::RenderPipeline,
+    bind_group_layout: wgpu::BindGroupLayout,
+    texture: Texture,
+}
+
+impl Matcap {
+    /// Set `store_position` the same way as for the G-buffer pass. The matcap
+    /// starts out as a gray clay sphere, see [Matcap::set_texture].
+    pub fn new(
+        renderer: &Renderer,
+        camera: &Camera,
+        output_format: wgpu::TextureFormat,
+        store_position: bool,
+    ) -> Self {
+        let Renderer { device, .. } = renderer;
+
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("matcap shader module"),
+            source: wgpu::ShaderSource::Wgsl(std::borrow::Cow::Borrowed(include_str!(
+                "matcap.wgsl"
+            ))),
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("matcap bind group layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("matcap pipeline layout"),
+            bind_group_layouts: &[&bind_group_layout, &camera.bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let constants = gbuffer::shader_constants(
+            renderer.limits_profile,
+            renderer.normal_space,
+            renderer.depth_convention,
+            store_position,
+        );
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("matcap render pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &module,
+                entry_point: "vertex_main",
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+                buffers: &[],
+            },
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            fragment: Some(wgpu::FragmentState {
+                module: &module,
+                entry_point: "fragment_main",
+                compilation_options: wgpu::PipelineCompilationOptions {
+                    constants: &constants,
+                    ..Default::default()
+                },
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: output_format,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            multiview: None,
+            cache: None,
+        });
+
+        Self {
+            pipeline,
+            bind_group_layout,
+            texture: clay_texture(renderer),
+        }
+    }
+
+    /// Use `texture` as the matcap. The whole texture is the sphere, touching
+    /// the edges.
+    pub fn set_texture(&mut self, texture: Texture) {
+        self.texture = texture;
+    }
+
+    pub fn render(
+        &self,
+        renderer: &Renderer,
+        encoder: &mut wgpu::CommandEncoder,
+        normal: &Texture,
+        camera: &Camera,
+        target: &wgpu::TextureView,
+    ) {
+        let bind_group = renderer
+            .device
+            .create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("matcap bind group"),
+                layout: &self.bind_group_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(&normal.view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::TextureView(&self.texture.view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: wgpu::BindingResource::Sampler(&self.texture.sampler),
+                    },
+                ],
+            });
+
+        let mut render_pass = PassBuilder::new("matcap render pass")
+            .with_color(target, wgpu::LoadOp::Clear(wgpu::Color::BLACK))
+            .begin(encoder);
+
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &bind_group, &[]);
+        render_pass.set_bind_group(1, &camera.bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
+
+/// A gray clay sphere lit from the top left, the default matcap.
+fn clay_texture(renderer: &Renderer) -> Texture {
+    const SIZE: u32 = 128;
+
+    let light = cgmath::vec3(-0.4, 0.6, 0.7f32).normalize();
+
+    let pixels = (0..SIZE * SIZE)
+        .flat_map(|i| {
+            // -1..1 across the sphere, with y up.
+            let x = ((i % SIZE) as f32 + 0.5) / SIZE as f32 * 2.0 - 1.0;
+            let y = 1.0 - ((i / SIZE) as f32 + 0.5) / SIZE as f32 * 2.0;
+            let z = (1.0 - x * x - y * y).max(0.0).sqrt();
+
+            let diffuse = (x * light.x + y * light.y + z * light.z).max(0.0);
+            // The light reflected about the normal, towards the viewer at +z.
+            let reflected = 2.0 * diffuse * z - light.z;
+            let specular = reflected.max(0.0).powf(24.0);
+            let shade = 0.15 + diffuse * 0.7 + specular * 0.3;
+
+            let [r, g, b] = [200.0, 190.0, 180.0].map(|c: f32| (c * shade).min(255.0) as u8);
+            [r, g, b, 255]
+        })
+        .collect::<Vec<u8>>();
+
+    Texture::from_rgba8(
+        renderer,
+        SIZE,
+        SIZE,
+        &pixels,
+        wgpu::TextureFormat::Rgba8UnormSrgb,
+    )
+}
@@ -0,0 +1,63 @@
+@group(0) @binding(0) var t_normal: texture_2d<f32>;
+@group(0) @binding(1) var t_matcap: texture_2d<f32>;
+@group(0) @binding(2) var s_matcap: sampler;
+
+struct Camera {
+    projection_matrix: mat4x4<f32>,
+    view_matrix: mat4x4<f32>,
+    position: vec3<f32>,
+}
+@group(1) @binding(0) var<uniform> camera: Camera;
+
+// Set when the normal target can't store negative values, so normals are
+// packed into the 0..1 range.
+override PACK_NORMALS: bool = false;
+// Set when the normal target already holds view space normals.
+override VIEW_SPACE_NORMALS: bool = false;
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+};
+
+@vertex
+fn vertex_main(
+    @builtin(vertex_index) vertex_index: u32
+) -> VertexOutput {
+    // Create a fullscreen texture.
+    let tex_coord = vec2<f32>(
+        f32(vertex_index >> 1u),
+        f32(vertex_index & 1u)
+    ) * 2.0;
+    let position = vec4<f32>(
+        tex_coord * vec2<f32>(2.0, -2.0) + vec2<f32>(-1.0, 1.0),
+        0.0,
+        1.0
+    );
+
+    return VertexOutput(position);
+}
+
+@fragment
+fn fragment_main(vertex_output: VertexOutput) -> @location(0) vec4<f32> {
+    let coord = vec2<i32>(floor(vertex_output.position.xy));
+    let normal = textureLoad(t_normal, coord, 0);
+
+    var n = normal.xyz;
+    if PACK_NORMALS {
+        n = n * 2.0 - 1.0;
+    }
+    if !VIEW_SPACE_NORMALS {
+        n = (camera.view_matrix * vec4(n, 0.0)).xyz;
+    }
+    n = normalize(n);
+
+    // The matcap is a picture of a lit sphere, so the normal facing the
+    // camera picks its center, and normals at right angles to the view pick
+    // its rim. Texture coordinates grow downwards.
+    let uv = n.xy * vec2(0.5, -0.5) + 0.5;
+    let color = textureSample(t_matcap, s_matcap, uv).rgb;
+
+    // The alpha of the normal target is the coverage, keep the background
+    // black.
+    return vec4(color * normal.w, 1.0);
+}
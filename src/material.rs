@@ -1,26 +1,53 @@
-use std::io::{BufReader, Cursor};
+use wgpu::util::DeviceExt;
 
 use crate::{texture::Texture, Renderer};
 
+#[derive(Clone, Copy, bytemuck::NoUninit)]
+#[repr(C)]
+struct MaterialUniform {
+    opacity: f32,
+    alpha_cutoff: f32,
+    _padding: [f32; 2],
+    specular_color: [f32; 3],
+    specular_strength: f32,
+}
+
 pub struct GpuMaterial {
+    uniform: MaterialUniform,
+    buffer: wgpu::Buffer,
+    albedo: Texture,
+    normal: Texture,
     pub bind_group_layout: wgpu::BindGroupLayout,
     pub bind_group: wgpu::BindGroup,
+    /// Transparent materials can not be stored in the G-buffer, so geometry
+    /// using them is drawn in the forward transparent pass instead.
+    pub transparent: bool,
 }
 
 impl GpuMaterial {
     pub fn new(renderer: &Renderer, albedo: &[u8], normal: &[u8]) -> Self {
-        let albedo = Texture::from_reader(
-            renderer,
-            BufReader::new(Cursor::new(albedo)),
-            wgpu::TextureFormat::Rgba8UnormSrgb,
-        )
-        .unwrap();
-        let normal = Texture::from_reader(
-            renderer,
-            BufReader::new(Cursor::new(normal)),
-            wgpu::TextureFormat::Rgba8Unorm,
-        )
-        .unwrap();
+        let albedo = Texture::from_bytes(renderer, albedo, true).unwrap();
+        let normal = Texture::from_bytes(renderer, normal, false).unwrap();
+
+        Self::from_textures(renderer, albedo, normal)
+    }
+
+    pub fn from_textures(renderer: &Renderer, albedo: Texture, normal: Texture) -> Self {
+        let uniform = MaterialUniform {
+            opacity: 1.0,
+            alpha_cutoff: 0.0,
+            _padding: [0.0; 2],
+            specular_color: [1.0, 1.0, 1.0],
+            specular_strength: 1.0,
+        };
+
+        let buffer = renderer
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("material buffer"),
+                contents: bytemuck::cast_slice(&[uniform]),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            });
 
         let bind_group_layout =
             renderer
@@ -60,37 +87,120 @@ impl GpuMaterial {
                             ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
                             count: None,
                         },
+                        wgpu::BindGroupLayoutEntry {
+                            binding: 4,
+                            visibility: wgpu::ShaderStages::FRAGMENT,
+                            ty: wgpu::BindingType::Buffer {
+                                ty: wgpu::BufferBindingType::Uniform,
+                                has_dynamic_offset: false,
+                                min_binding_size: None,
+                            },
+                            count: None,
+                        },
                     ],
                 });
 
-        let bind_group = renderer
-            .device
-            .create_bind_group(&wgpu::BindGroupDescriptor {
-                label: Some("material bind group"),
-                layout: &bind_group_layout,
-                entries: &[
-                    wgpu::BindGroupEntry {
-                        binding: 0,
-                        resource: wgpu::BindingResource::TextureView(&albedo.view),
-                    },
-                    wgpu::BindGroupEntry {
-                        binding: 1,
-                        resource: wgpu::BindingResource::Sampler(&albedo.sampler),
-                    },
-                    wgpu::BindGroupEntry {
-                        binding: 2,
-                        resource: wgpu::BindingResource::TextureView(&normal.view),
-                    },
-                    wgpu::BindGroupEntry {
-                        binding: 3,
-                        resource: wgpu::BindingResource::Sampler(&normal.sampler),
-                    },
-                ],
-            });
+        let bind_group = create_bind_group(renderer, &bind_group_layout, &albedo, &normal, &buffer);
 
         Self {
+            uniform,
+            buffer,
+            albedo,
+            normal,
             bind_group_layout,
             bind_group,
+            transparent: false,
         }
     }
+
+    /// Mark the material as transparent with the given opacity. The albedo
+    /// alpha is multiplied by `opacity` when blending.
+    pub fn with_transparency(mut self, renderer: &Renderer, opacity: f32) -> Self {
+        self.transparent = true;
+        self.uniform.opacity = opacity.clamp(0.0, 1.0);
+        self.update_buffer(renderer);
+        self
+    }
+
+    /// Fragments with an albedo alpha below `alpha_cutoff` are discarded in
+    /// the G-buffer pass, which allows cutout geometry like foliage without
+    /// having to go through the transparent pass.
+    pub fn with_alpha_cutoff(mut self, renderer: &Renderer, alpha_cutoff: f32) -> Self {
+        self.uniform.alpha_cutoff = alpha_cutoff.clamp(0.0, 1.0);
+        self.update_buffer(renderer);
+        self
+    }
+
+    /// Tint the specular highlights with `color`, e.g. gold for gold metal,
+    /// and scale them by `strength`. The default is white at full strength.
+    /// Both are stored in an 8 bit G-buffer target, so they are clamped to
+    /// 0..1.
+    #[allow(dead_code)]
+    pub fn with_specular(mut self, renderer: &Renderer, color: [f32; 3], strength: f32) -> Self {
+        self.uniform.specular_color = color.map(|c| c.clamp(0.0, 1.0));
+        self.uniform.specular_strength = strength.clamp(0.0, 1.0);
+        self.update_buffer(renderer);
+        self
+    }
+
+    /// A bind group for this material with its albedo and/or normal texture
+    /// swapped out, keeping everything else. Used to debug materials without
+    /// having to change them.
+    pub fn bind_group_with_textures(
+        &self,
+        renderer: &Renderer,
+        albedo: Option<&Texture>,
+        normal: Option<&Texture>,
+    ) -> wgpu::BindGroup {
+        create_bind_group(
+            renderer,
+            &self.bind_group_layout,
+            albedo.unwrap_or(&self.albedo),
+            normal.unwrap_or(&self.normal),
+            &self.buffer,
+        )
+    }
+
+    fn update_buffer(&self, renderer: &Renderer) {
+        renderer
+            .queue
+            .write_buffer(&self.buffer, 0, bytemuck::cast_slice(&[self.uniform]));
+    }
+}
+
+fn create_bind_group(
+    renderer: &Renderer,
+    layout: &wgpu::BindGroupLayout,
+    albedo: &Texture,
+    normal: &Texture,
+    buffer: &wgpu::Buffer,
+) -> wgpu::BindGroup {
+    renderer
+        .device
+        .create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("material bind group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&albedo.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&albedo.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(&normal.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::Sampler(&normal.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: buffer.as_entire_binding(),
+                },
+            ],
+        })
 }
@@ -1,9 +1,8 @@
-use obj::TexturedVertex;
 use wgpu::util::DeviceExt;
 
-use crate::Renderer;
+use crate::{allocations::Allocation, Renderer};
 
-#[derive(Clone, Copy, bytemuck::NoUninit)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
 #[repr(C)]
 pub struct Vertex {
     pub position: [f32; 3],
@@ -11,9 +10,61 @@ pub struct Vertex {
     pub tex_coord: [f32; 2],
     pub tangent: [f32; 3],
     pub bitangent: [f32; 3],
+    /// How much of the ambient light reaches the vertex, 1.0 where nothing is
+    /// in the way. See [Mesh::bake_ao].
+    pub occlusion: f32,
 }
 
 impl Vertex {
+    /// The attributes of a vertex, at the shader locations the mesh shaders
+    /// read them from. Offsets come from the struct, so they can't drift.
+    pub const ATTRIBUTES: [wgpu::VertexAttribute; 6] = [
+        wgpu::VertexAttribute {
+            format: wgpu::VertexFormat::Float32x3,
+            offset: std::mem::offset_of!(Vertex, position) as wgpu::BufferAddress,
+            shader_location: 0,
+        },
+        wgpu::VertexAttribute {
+            format: wgpu::VertexFormat::Float32x3,
+            offset: std::mem::offset_of!(Vertex, normal) as wgpu::BufferAddress,
+            shader_location: 1,
+        },
+        wgpu::VertexAttribute {
+            format: wgpu::VertexFormat::Float32x2,
+            offset: std::mem::offset_of!(Vertex, tex_coord) as wgpu::BufferAddress,
+            shader_location: 2,
+        },
+        wgpu::VertexAttribute {
+            format: wgpu::VertexFormat::Float32x3,
+            offset: std::mem::offset_of!(Vertex, tangent) as wgpu::BufferAddress,
+            shader_location: 3,
+        },
+        wgpu::VertexAttribute {
+            format: wgpu::VertexFormat::Float32x3,
+            offset: std::mem::offset_of!(Vertex, bitangent) as wgpu::BufferAddress,
+            shader_location: 4,
+        },
+        wgpu::VertexAttribute {
+            format: wgpu::VertexFormat::Float32,
+            offset: std::mem::offset_of!(Vertex, occlusion) as wgpu::BufferAddress,
+            shader_location: 5,
+        },
+    ];
+
+    /// The layout of a vertex buffer with all of the [Vertex::ATTRIBUTES].
+    pub fn layout() -> wgpu::VertexBufferLayout<'static> {
+        Self::layout_with(&Self::ATTRIBUTES)
+    }
+
+    /// The layout of a vertex buffer of which only `attributes` are read.
+    pub fn layout_with(attributes: &[wgpu::VertexAttribute]) -> wgpu::VertexBufferLayout<'_> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<Vertex>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes,
+        }
+    }
+
     #[allow(clippy::too_many_arguments)]
     pub fn raw(x: f32, y: f32, z: f32, n_x: f32, n_y: f32, n_z: f32, u: f32, v: f32) -> Self {
         Self {
@@ -22,14 +73,54 @@ impl Vertex {
             tex_coord: [u, v],
             tangent: [0.0, 0.0, 0.0],
             bitangent: [0.0, 0.0, 0.0],
+            occlusion: 1.0,
         }
     }
 }
 
-#[derive(Default)]
+/// A named range of a mesh's indices, loaded from an OBJ `o` or `g`
+/// statement, that can be drawn or hidden on its own.
+#[derive(Clone, Debug)]
+pub struct Submesh {
+    pub name: String,
+    pub indices: std::ops::Range<u32>,
+    pub visible: bool,
+}
+
+#[derive(Clone)]
 pub struct Mesh<V> {
     pub vertices: Vec<V>,
     pub indices: Vec<u16>,
+    /// The winding order of the triangles that face outwards.
+    pub front_face: wgpu::FrontFace,
+    /// The objects in the mesh, in the order they appear in the index list.
+    /// If there are none, the mesh is drawn as a whole.
+    pub submeshes: Vec<Submesh>,
+}
+
+impl<V> Default for Mesh<V> {
+    fn default() -> Self {
+        Self {
+            vertices: Vec::new(),
+            indices: Vec::new(),
+            front_face: wgpu::FrontFace::Ccw,
+            submeshes: Vec::new(),
+        }
+    }
+}
+
+impl<V: Copy> Mesh<V> {
+    /// Iterate over the triangles of the mesh, resolving the indices to the
+    /// vertices. Trailing indices that don't form a full triangle are ignored.
+    pub fn triangles(&self) -> impl Iterator<Item = [V; 3]> + '_ {
+        self.indices.chunks_exact(3).map(|triangle| {
+            [
+                self.vertices[triangle[0] as usize],
+                self.vertices[triangle[1] as usize],
+                self.vertices[triangle[2] as usize],
+            ]
+        })
+    }
 }
 
 impl From<epaint::Mesh> for Mesh<epaint::Vertex> {
@@ -37,37 +128,413 @@ impl From<epaint::Mesh> for Mesh<epaint::Vertex> {
         Self {
             vertices: value.vertices,
             indices: value.indices.iter().map(|i| *i as u16).collect(),
+            ..Default::default()
         }
     }
 }
 
 impl Mesh<Vertex> {
-    pub fn from_reader(reader: impl std::io::BufRead) -> Result<Self, ()> {
-        // let r = BufReader::new(std::fs::File::open(path).unwrap());
-        // let obj: obj::Obj<TexturedVertex, u16> = obj::load_obj(r).unwrap();
-        let obj: obj::Obj<TexturedVertex, u16> = obj::load_obj(reader).map_err(|err| {
+    /// Load a triangulated OBJ with positions, texture coordinates and
+    /// normals. Every `o` and `g` statement in the file starts a new
+    /// [Submesh].
+    ///
+    /// The whole file is read into memory first, so the reader doesn't have to
+    /// be buffered or seekable.
+    pub fn from_reader(mut reader: impl std::io::Read) -> Result<Self, ()> {
+        use std::collections::hash_map::{Entry, HashMap};
+
+        let mut source = String::new();
+        reader.read_to_string(&mut source).map_err(|err| {
+            println!("ERROR: {:?}", err);
+        })?;
+
+        // The obj crate only keeps the last object name, so treat objects as
+        // groups to keep their boundaries.
+        let source = source
+            .lines()
+            .map(|line| match line.trim_start().strip_prefix("o ") {
+                Some(name) => format!("g {}", name.trim().replace(' ', "_")),
+                None => line.to_string(),
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        let raw = obj::raw::parse_obj(source.as_bytes()).map_err(|err| {
             println!("ERROR: {:?}", err);
         })?;
 
-        Ok(Self {
-            vertices: obj
-                .vertices
+        let mut groups = raw
+            .groups
+            .into_iter()
+            .filter(|(_, group)| group.polygons.iter().any(|r| r.start < r.end))
+            .collect::<Vec<_>>();
+        groups.sort_by_key(|(_, group)| group.polygons.first().map(|r| r.start));
+
+        let mut mesh = Self::default();
+        let mut cache = HashMap::new();
+        for (name, group) in groups {
+            let start = mesh.indices.len() as u32;
+
+            for range in group.polygons {
+                for polygon in &raw.polygons[range.start..range.end] {
+                    let obj::raw::object::Polygon::PTN(corners) = polygon else {
+                        println!("ERROR: Polygons need positions, texture coordinates and normals");
+                        return Err(());
+                    };
+                    if corners.len() != 3 {
+                        println!("ERROR: Model should be triangulated");
+                        return Err(());
+                    }
+
+                    for &(p, t, n) in corners {
+                        let index = match cache.entry((p, t, n)) {
+                            Entry::Occupied(entry) => *entry.get(),
+                            Entry::Vacant(entry) => {
+                                let Ok(index) = u16::try_from(mesh.vertices.len()) else {
+                                    println!("ERROR: Too many vertices for 16-bit indices");
+                                    return Err(());
+                                };
+                                let (x, y, z, _) = raw.positions[p];
+                                let (u, v, _) = raw.tex_coords[t];
+                                let (n_x, n_y, n_z) = raw.normals[n];
+                                mesh.vertices
+                                    .push(Vertex::raw(x, y, z, n_x, n_y, n_z, u, v));
+                                *entry.insert(index)
+                            }
+                        };
+                        mesh.indices.push(index);
+                    }
+                }
+            }
+
+            mesh.submeshes.push(Submesh {
+                name,
+                indices: start..mesh.indices.len() as u32,
+                visible: true,
+            });
+        }
+
+        mesh.front_face = mesh.detect_winding();
+
+        Ok(mesh)
+    }
+
+    /// Guess the winding order of the front faces by comparing the normal of
+    /// a sample of the triangles with the normals of their vertices. Meshes
+    /// without normals are assumed to be counter-clockwise.
+    pub fn detect_winding(&self) -> wgpu::FrontFace {
+        use cgmath::InnerSpace;
+
+        const SAMPLE_SIZE: usize = 64;
+
+        let triangle_count = self.indices.len() / 3;
+        let step = (triangle_count / SAMPLE_SIZE).max(1);
+
+        let mut votes = 0_i32;
+        for triangle in self.triangles().step_by(step) {
+            let [a, b, c] = triangle.map(|v| cgmath::Vector3::from(v.position));
+            let face_normal = (b - a).cross(c - a);
+            let vertex_normal = triangle
                 .iter()
-                .map(|v| {
-                    Vertex::raw(
-                        v.position[0],
-                        v.position[1],
-                        v.position[2],
-                        v.normal[0],
-                        v.normal[1],
-                        v.normal[2],
-                        v.texture[0],
-                        v.texture[1],
-                    )
+                .map(|v| cgmath::Vector3::from(v.normal))
+                .sum::<cgmath::Vector3<f32>>();
+
+            let alignment = face_normal.dot(vertex_normal);
+            if alignment > 0.0 {
+                votes += 1;
+            } else if alignment < 0.0 {
+                votes -= 1;
+            }
+        }
+
+        if votes < 0 {
+            wgpu::FrontFace::Cw
+        } else {
+            wgpu::FrontFace::Ccw
+        }
+    }
+
+    /// Transform the positions of all the vertices by `transform`, and the
+    /// normals, tangents and bitangents by its inverse transpose, so they
+    /// stay perpendicular to the surface under non-uniform scaling.
+    pub fn apply_transform(&mut self, transform: cgmath::Matrix4<f32>) {
+        use cgmath::{InnerSpace, Matrix, SquareMatrix, Transform};
+
+        let linear = cgmath::Matrix3::from_cols(
+            transform.x.truncate(),
+            transform.y.truncate(),
+            transform.z.truncate(),
+        );
+        let normal_matrix = linear
+            .invert()
+            .expect("mesh transform can be inverted")
+            .transpose();
+
+        let transform_direction = |direction: [f32; 3]| -> [f32; 3] {
+            let direction = normal_matrix * cgmath::Vector3::from(direction);
+            // Leave zero vectors, like missing tangents, as they are.
+            if direction.magnitude2() > 0.0 {
+                direction.normalize().into()
+            } else {
+                direction.into()
+            }
+        };
+
+        for vertex in self.vertices.iter_mut() {
+            vertex.position = transform
+                .transform_point(cgmath::Point3::from(vertex.position))
+                .into();
+            vertex.normal = transform_direction(vertex.normal);
+            vertex.tangent = transform_direction(vertex.tangent);
+            vertex.bitangent = transform_direction(vertex.bitangent);
+        }
+
+        // A mirroring transform reverses the winding of every triangle.
+        if linear.determinant() < 0.0 {
+            self.front_face = match self.front_face {
+                wgpu::FrontFace::Ccw => wgpu::FrontFace::Cw,
+                wgpu::FrontFace::Cw => wgpu::FrontFace::Ccw,
+            };
+        }
+    }
+
+    /// The corners of the axis aligned box bounding all the vertices.
+    pub fn bounds(&self) -> (cgmath::Point3<f32>, cgmath::Point3<f32>) {
+        if self.vertices.is_empty() {
+            return (
+                cgmath::Point3::new(0.0, 0.0, 0.0),
+                cgmath::Point3::new(0.0, 0.0, 0.0),
+            );
+        }
+
+        let mut min = [f32::MAX; 3];
+        let mut max = [f32::MIN; 3];
+        for v in self.vertices.iter() {
+            for i in 0..3 {
+                min[i] = min[i].min(v.position[i]);
+                max[i] = max[i].max(v.position[i]);
+            }
+        }
+
+        (min.into(), max.into())
+    }
+
+    /// The center of the axis aligned box bounding all the vertices.
+    pub fn center(&self) -> cgmath::Point3<f32> {
+        let (min, max) = self.bounds();
+        cgmath::EuclideanSpace::midpoint(min, max)
+    }
+
+    /// A copy of the mesh with about `ratio` (0..1) of its vertices, for a
+    /// lower level of detail. Vertices are snapped to a grid over the bounding
+    /// box and the ones in the same cell, facing roughly the same way, are
+    /// merged. This is quick and keeps hard edges, but doesn't try to keep
+    /// small features. Triangles that collapse are dropped.
+    pub fn simplify(&self, ratio: f32) -> Self {
+        use cgmath::InnerSpace;
+        use std::collections::hash_map::{Entry, HashMap};
+
+        let ratio = ratio.clamp(0.0, 1.0);
+        if ratio >= 1.0 || self.vertices.is_empty() {
+            return self.clone();
+        }
+
+        // A closed mesh has about half as many distinct positions as
+        // triangles, whether or not its vertices are shared. Its surface
+        // crosses about three times the square of the number of cells along
+        // each axis.
+        let (min, max) = self.bounds();
+        let target_positions = (self.indices.len() / 3) as f32 * 0.5 * ratio;
+        let cells = (target_positions / 3.0).max(1.0).sqrt().ceil();
+        let cell_size = (max - min).map(|extent| (extent / cells).max(f32::EPSILON));
+
+        // Each cluster is the sum of the vertices in it, averaged below.
+        let mut clusters: Vec<(Vertex, f32)> = Vec::new();
+        let mut cluster_indices = HashMap::new();
+        let vertex_clusters = self
+            .vertices
+            .iter()
+            .map(|vertex| {
+                let offset = cgmath::Point3::from(vertex.position) - min;
+                let cell = [0, 1, 2].map(|axis| (offset[axis] / cell_size[axis]).floor() as i32);
+                let facing = vertex.normal.map(|n| (n * 2.0).round() as i32);
+
+                match cluster_indices.entry((cell, facing)) {
+                    Entry::Occupied(entry) => {
+                        let index = *entry.get();
+                        let (sum, count): &mut (Vertex, f32) = &mut clusters[index];
+                        for axis in 0..3 {
+                            sum.position[axis] += vertex.position[axis];
+                            sum.normal[axis] += vertex.normal[axis];
+                        }
+                        sum.occlusion += vertex.occlusion;
+                        *count += 1.0;
+                        index
+                    }
+                    Entry::Vacant(entry) => {
+                        clusters.push((*vertex, 1.0));
+                        *entry.insert(clusters.len() - 1)
+                    }
+                }
+            })
+            .collect::<Vec<_>>();
+
+        // Only clusters used by a triangle that didn't collapse are kept.
+        let mut kept = vec![None; clusters.len()];
+        let mut vertices = Vec::new();
+        let mut indices = Vec::new();
+        let mut keep_triangle = |triangle: &[u16], indices: &mut Vec<u16>| {
+            let triangle = [0, 1, 2].map(|i| vertex_clusters[triangle[i] as usize]);
+            if triangle[0] == triangle[1]
+                || triangle[1] == triangle[2]
+                || triangle[0] == triangle[2]
+            {
+                return;
+            }
+            for cluster in triangle {
+                let index = *kept[cluster].get_or_insert_with(|| {
+                    let (sum, count) = clusters[cluster];
+                    let normal = cgmath::Vector3::from(sum.normal);
+                    vertices.push(Vertex {
+                        position: sum.position.map(|p| p / count),
+                        normal: if normal.magnitude2() > 0.0 {
+                            normal.normalize().into()
+                        } else {
+                            sum.normal
+                        },
+                        // The texture coordinates of the first vertex, as
+                        // averaging them would break seams.
+                        tex_coord: sum.tex_coord,
+                        tangent: [0.0; 3],
+                        bitangent: [0.0; 3],
+                        occlusion: sum.occlusion / count,
+                    });
+                    (vertices.len() - 1) as u16
+                });
+                indices.push(index);
+            }
+        };
+
+        let submeshes = if self.submeshes.is_empty() {
+            for triangle in self.indices.chunks_exact(3) {
+                keep_triangle(triangle, &mut indices);
+            }
+            Vec::new()
+        } else {
+            self.submeshes
+                .iter()
+                .map(|submesh| {
+                    let start = indices.len() as u32;
+                    let range = submesh.indices.start as usize..submesh.indices.end as usize;
+                    for triangle in self.indices[range].chunks_exact(3) {
+                        keep_triangle(triangle, &mut indices);
+                    }
+                    Submesh {
+                        indices: start..indices.len() as u32,
+                        ..submesh.clone()
+                    }
                 })
-                .collect(),
-            indices: obj.indices,
-        })
+                .collect()
+        };
+
+        let mut mesh = Self {
+            vertices,
+            indices,
+            front_face: self.front_face,
+            submeshes,
+        };
+        mesh.update_tangents();
+        mesh
+    }
+
+    /// Store in every vertex how much of the ambient light reaches it, by
+    /// casting `samples` rays over the hemisphere around its normal and
+    /// counting the ones that hit a triangle of the mesh. More samples give
+    /// smoother results, at a cost that grows with the samples times the
+    /// vertices times the triangles, so this is meant for static meshes at
+    /// load time. Only occluders within a fifth of the size of the mesh count,
+    /// which keeps the darkening to creases and contact points.
+    ///
+    /// Other meshes are not taken into account, and 0 samples leave every
+    /// vertex unoccluded.
+    pub fn bake_ao(&mut self, samples: u32) {
+        use cgmath::InnerSpace;
+
+        let triangles = self
+            .triangles()
+            .map(|triangle| triangle.map(|vertex| cgmath::Vector3::from(vertex.position)))
+            .collect::<Vec<_>>();
+
+        let (min, max) = self.bounds();
+        let reach = (max - min).magnitude() * AO_REACH;
+        // Start the rays just off the surface, so they don't hit the
+        // triangles around the vertex they start from.
+        let offset = reach * 0.001;
+
+        // The same directions for every vertex, spread evenly over the
+        // hemisphere around +z in a spiral. They are denser towards the pole,
+        // so every hit is weighted by how much light comes from there.
+        let directions = (0..samples)
+            .map(|i| {
+                let radius = ((i as f32 + 0.5) / samples as f32).sqrt();
+                let angle = i as f32 * std::f32::consts::PI * (3.0 - 5f32.sqrt());
+                cgmath::vec3(
+                    radius * angle.cos(),
+                    radius * angle.sin(),
+                    (1.0 - radius * radius).sqrt(),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        for vertex in self.vertices.iter_mut() {
+            vertex.occlusion = 1.0;
+
+            let normal = cgmath::Vector3::from(vertex.normal);
+            if samples == 0 || normal.magnitude2() == 0.0 {
+                continue;
+            }
+            let normal = normal.normalize();
+            let origin = cgmath::Vector3::from(vertex.position) + normal * offset;
+
+            // Turn the directions from around +z to around the normal.
+            let helper = if normal.x.abs() < 0.9 {
+                cgmath::Vector3::unit_x()
+            } else {
+                cgmath::Vector3::unit_y()
+            };
+            let tangent = normal.cross(helper).normalize();
+            let bitangent = normal.cross(tangent);
+
+            // Only triangles within reach and at least partly in front of the
+            // vertex can be hit, which rules out most of them.
+            let nearby = triangles
+                .iter()
+                .filter(|triangle| {
+                    let center = (triangle[0] + triangle[1] + triangle[2]) / 3.0;
+                    let radius = triangle
+                        .iter()
+                        .map(|corner| (corner - center).magnitude())
+                        .fold(0.0, f32::max);
+                    (center - origin).magnitude() <= reach + radius
+                        && triangle
+                            .iter()
+                            .any(|corner| (corner - origin).dot(normal) > 0.0)
+                })
+                .collect::<Vec<_>>();
+
+            let hits = directions
+                .iter()
+                .filter(|direction| {
+                    let direction =
+                        tangent * direction.x + bitangent * direction.y + normal * direction.z;
+                    nearby
+                        .iter()
+                        .any(|triangle| ray_hits_triangle(origin, direction, triangle, reach))
+                })
+                .count();
+
+            vertex.occlusion = 1.0 - hits as f32 / samples as f32;
+        }
     }
 
     pub fn update_tangents(&mut self) {
@@ -102,6 +569,10 @@ impl Mesh<Vertex> {
             // Luckily, the place I found this equation provided
             // the solution!
             let r = 1.0 / (delta_uv1.x * delta_uv2.y - delta_uv1.y * delta_uv2.x);
+            // Triangles without an area in texture space have no tangents.
+            if !r.is_finite() {
+                continue;
+            }
             let tangent = (delta_pos1 * delta_uv2.y - delta_pos2 * delta_uv1.y) * r;
             // We flip the bitangent to enable right-handed normal
             // maps with wgpu texture coordinate system
@@ -129,6 +600,9 @@ impl Mesh<Vertex> {
 
         // Average the tangents/bitangents
         for (i, n) in triangles_included.into_iter().enumerate() {
+            if n == 0 {
+                continue;
+            }
             let denom = 1.0 / n as f32;
             let v = &mut self.vertices[i];
             v.tangent = (cgmath::Vector3::from(v.tangent) * denom).into();
@@ -137,20 +611,158 @@ impl Mesh<Vertex> {
     }
 }
 
+/// How far [Mesh::bake_ao] looks for occluders, as a fraction of the diagonal
+/// of the bounding box of the mesh.
+const AO_REACH: f32 = 0.2;
+
+/// Whether the ray from `origin` along `direction` hits `triangle`, from
+/// either side, within `max_distance`. `direction` has to be normalized.
+fn ray_hits_triangle(
+    origin: cgmath::Vector3<f32>,
+    direction: cgmath::Vector3<f32>,
+    triangle: &[cgmath::Vector3<f32>; 3],
+    max_distance: f32,
+) -> bool {
+    use cgmath::InnerSpace;
+
+    // Möller-Trumbore: solve for the distance along the ray and the
+    // barycentric coordinates of the hit.
+    let edge1 = triangle[1] - triangle[0];
+    let edge2 = triangle[2] - triangle[0];
+    let p = direction.cross(edge2);
+    let determinant = edge1.dot(p);
+    // The ray runs along the plane of the triangle.
+    if determinant.abs() < f32::EPSILON {
+        return false;
+    }
+    let inverse = 1.0 / determinant;
+
+    let s = origin - triangle[0];
+    let u = s.dot(p) * inverse;
+    if !(0.0..=1.0).contains(&u) {
+        return false;
+    }
+    let q = s.cross(edge1);
+    let v = direction.dot(q) * inverse;
+    if v < 0.0 || u + v > 1.0 {
+        return false;
+    }
+
+    let distance = edge2.dot(q) * inverse;
+    distance > 0.0 && distance <= max_distance
+}
+
 pub struct GpuMesh {
     pub vertex_buffer: wgpu::Buffer,
     pub index_buffer: wgpu::Buffer,
     pub index_count: u32,
+    pub front_face: wgpu::FrontFace,
+    pub submeshes: Vec<Submesh>,
+    /// The vertex and index buffers, untracked when the mesh is dropped.
+    _allocations: [Allocation; 2],
+}
+
+impl GpuMesh {
+    /// Bind the buffers and draw all the visible submeshes, or the whole mesh
+    /// if it has none.
+    pub fn draw(&self, render_pass: &mut wgpu::RenderPass) {
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+
+        if self.submeshes.is_empty() {
+            render_pass.draw_indexed(0..self.index_count, 0, 0..1);
+            return;
+        }
+
+        for submesh in self.submeshes.iter().filter(|s| s.visible) {
+            render_pass.insert_debug_marker(&submesh.name);
+            render_pass.draw_indexed(submesh.indices.clone(), 0, 0..1);
+        }
+    }
+
+    /// Copy the buffers back into a CPU mesh, assuming it was uploaded from a
+    /// `Mesh<Vertex>`. Returns `None` if the buffers were not created with
+    /// `COPY_SRC`. This blocks until the GPU has finished all submitted work,
+    /// so it is meant for debugging and tooling.
+    #[allow(dead_code)]
+    pub fn read_back(&self, renderer: &Renderer) -> Option<Mesh<Vertex>> {
+        if !self
+            .vertex_buffer
+            .usage()
+            .contains(wgpu::BufferUsages::COPY_SRC)
+            || !self
+                .index_buffer
+                .usage()
+                .contains(wgpu::BufferUsages::COPY_SRC)
+        {
+            return None;
+        }
+
+        let vertices = read_buffer(renderer, &self.vertex_buffer)
+            .chunks_exact(std::mem::size_of::<Vertex>())
+            .map(bytemuck::pod_read_unaligned)
+            .collect();
+        // The index buffer is padded to a multiple of 4 bytes.
+        let indices = read_buffer(renderer, &self.index_buffer)
+            .chunks_exact(std::mem::size_of::<u16>())
+            .take(self.index_count as usize)
+            .map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]]))
+            .collect();
+
+        Some(Mesh {
+            vertices,
+            indices,
+            front_face: self.front_face,
+            submeshes: self.submeshes.clone(),
+        })
+    }
+}
+
+fn read_buffer(renderer: &Renderer, buffer: &wgpu::Buffer) -> Vec<u8> {
+    let staging = renderer.device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("read back buffer"),
+        size: buffer.size(),
+        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    });
+
+    let mut encoder = renderer
+        .device
+        .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("read back command encoder"),
+        });
+    encoder.copy_buffer_to_buffer(buffer, 0, &staging, 0, buffer.size());
+    renderer.queue.submit(std::iter::once(encoder.finish()));
+
+    let slice = staging.slice(..);
+    slice.map_async(wgpu::MapMode::Read, |result| {
+        result.expect("map read back buffer");
+    });
+    renderer.device.poll(wgpu::Maintain::Wait);
+
+    let data = slice.get_mapped_range().to_vec();
+    staging.unmap();
+    data
 }
 
 impl<V: bytemuck::NoUninit> Mesh<V> {
     pub fn upload_to_gpu(&self, renderer: &Renderer) -> GpuMesh {
+        self.upload_to_gpu_with_usage(renderer, wgpu::BufferUsages::empty())
+    }
+
+    /// Like [Mesh::upload_to_gpu], with `usage` added to both buffers. Add
+    /// `COPY_SRC` to be able to [GpuMesh::read_back] the mesh.
+    pub fn upload_to_gpu_with_usage(
+        &self,
+        renderer: &Renderer,
+        usage: wgpu::BufferUsages,
+    ) -> GpuMesh {
         let vertex_buffer = renderer
             .device
             .create_buffer_init(&wgpu::util::BufferInitDescriptor {
                 label: Some("vertex buffer"),
                 contents: bytemuck::cast_slice(self.vertices.as_ref()),
-                usage: wgpu::BufferUsages::VERTEX,
+                usage: wgpu::BufferUsages::VERTEX | usage,
             });
 
         let index_buffer = renderer
@@ -158,13 +770,41 @@ impl<V: bytemuck::NoUninit> Mesh<V> {
             .create_buffer_init(&wgpu::util::BufferInitDescriptor {
                 label: Some("index buffer"),
                 contents: bytemuck::cast_slice(self.indices.as_ref()),
-                usage: wgpu::BufferUsages::INDEX,
+                usage: wgpu::BufferUsages::INDEX | usage,
             });
 
         GpuMesh {
+            _allocations: [
+                renderer.allocations.track_buffer(&vertex_buffer),
+                renderer.allocations.track_buffer(&index_buffer),
+            ],
             vertex_buffer,
             index_buffer,
             index_count: self.indices.len() as u32,
+            front_face: self.front_face,
+            submeshes: self.submeshes.clone(),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cube_triangles_match_obj_faces() {
+        let bytes = include_bytes!("../res/cube.obj");
+        let mesh = Mesh::<Vertex>::from_reader(std::io::Cursor::new(bytes))
+            .expect("the bundled cube loads");
+
+        // The bundled cube has bevelled edges, so it is made of many more than
+        // the 12 triangles of a plain cube. Every face in the file is one.
+        let faces = std::str::from_utf8(bytes)
+            .unwrap()
+            .lines()
+            .filter(|line| line.starts_with("f "))
+            .count();
+        assert_eq!(faces, 144);
+        assert_eq!(mesh.triangles().count(), faces);
+    }
+}
@@ -1,11 +1,31 @@
 use std::borrow::Cow;
 
-use wgpu::vertex_attr_array;
+use crate::{gbuffer, mesh::Vertex, Renderer};
 
-use crate::{texture::DEPTH_FORMAT, Renderer};
+/// The name of the pipeline constant that selects [Shading::Flat].
+const FLAT_SHADING_CONSTANT: &str = "FLAT_SHADING";
 
+/// Where the normals written to the G-buffer come from.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Shading {
+    /// The vertex normals blended across each triangle, with the normal map
+    /// applied.
+    #[default]
+    Smooth,
+    /// A single normal for each triangle, found from how the position changes
+    /// across the screen, so the triangles show under lighting. The geometry
+    /// stays as it is.
+    Flat,
+}
+
+/// Fills the G-buffer with opaque meshes. There is a pipeline for each
+/// winding order, so meshes are culled according to their own
+/// [crate::mesh::GpuMesh::front_face], and for each [Shading].
 pub struct MeshRenderPipeline {
-    pub pipeline: wgpu::RenderPipeline,
+    ccw_pipeline: wgpu::RenderPipeline,
+    cw_pipeline: wgpu::RenderPipeline,
+    flat_ccw_pipeline: wgpu::RenderPipeline,
+    flat_cw_pipeline: wgpu::RenderPipeline,
 }
 
 impl MeshRenderPipeline {
@@ -13,82 +33,134 @@ impl MeshRenderPipeline {
         renderer: &Renderer,
         uniforms_bind_group_layout: &wgpu::BindGroupLayout,
         material_bind_group_layout: &wgpu::BindGroupLayout,
+        gbuffer_formats: &[Option<wgpu::TextureFormat>],
     ) -> Self {
         let Renderer { device, .. } = renderer;
 
+        if let Err(err) = gbuffer::validate_layout(&device.limits(), gbuffer_formats) {
+            panic!("Invalid G-buffer layout: {err}");
+        }
+
+        let targets = gbuffer_formats
+            .iter()
+            .map(|format| {
+                format.map(|format| wgpu::ColorTargetState {
+                    format,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::ALL,
+                })
+            })
+            .collect::<Vec<_>>();
+
+        // The position output is dropped when there is no target for it.
+        let store_position = gbuffer_formats[1].is_some();
+        let constants = gbuffer::shader_constants(
+            renderer.limits_profile,
+            renderer.normal_space,
+            renderer.depth_convention,
+            store_position,
+        );
+
         let main_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
             label: Some("main bind group layout"),
             bind_group_layouts: &[uniforms_bind_group_layout, material_bind_group_layout],
             push_constant_ranges: &[],
         });
 
-        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-            label: Some("main shader module"),
-            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("shader.wgsl"))),
-        });
+        // Each shading gets its own module, because the GL backend caches
+        // programs by module and entry point, whatever the constants.
+        let create_module = || {
+            device.create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some("main shader module"),
+                source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("shader.wgsl"))),
+            })
+        };
+        let smooth_module = create_module();
+        let flat_module = create_module();
 
-        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("main pipeline"),
-            layout: Some(&main_pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &module,
-                entry_point: "vertex_main",
-                compilation_options: wgpu::PipelineCompilationOptions::default(),
-                buffers: &[wgpu::VertexBufferLayout {
-                    array_stride: std::mem::size_of::<crate::mesh::Vertex>() as wgpu::BufferAddress,
-                    step_mode: wgpu::VertexStepMode::Vertex,
-                    attributes: &vertex_attr_array![
-                        0 => Float32x3,
-                        1 => Float32x3,
-                        2 => Float32x2,
-                        3 => Float32x3,
-                        4 => Float32x3,
-                    ],
-                }],
-            },
-            primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleList,
-                strip_index_format: None,
-                front_face: wgpu::FrontFace::Cw,
-                cull_mode: Some(wgpu::Face::Front),
-                unclipped_depth: false,
-                polygon_mode: wgpu::PolygonMode::Fill,
-                conservative: false,
-            },
-            depth_stencil: Some(wgpu::DepthStencilState {
-                format: DEPTH_FORMAT,
-                depth_write_enabled: true,
-                depth_compare: wgpu::CompareFunction::Less,
-                stencil: wgpu::StencilState::default(),
-                bias: wgpu::DepthBiasState::default(),
-            }),
-            multisample: wgpu::MultisampleState::default(),
-            fragment: Some(wgpu::FragmentState {
-                module: &module,
-                entry_point: "fragment_main",
-                compilation_options: wgpu::PipelineCompilationOptions::default(),
-                targets: &[
-                    Some(wgpu::ColorTargetState {
-                        format: wgpu::TextureFormat::Rgba8UnormSrgb,
-                        blend: None,
-                        write_mask: wgpu::ColorWrites::ALL,
-                    }),
-                    Some(wgpu::ColorTargetState {
-                        format: wgpu::TextureFormat::Rgba16Float,
-                        blend: None,
-                        write_mask: wgpu::ColorWrites::ALL,
-                    }),
-                    Some(wgpu::ColorTargetState {
-                        format: wgpu::TextureFormat::Rgba16Float,
-                        blend: None,
-                        write_mask: wgpu::ColorWrites::ALL,
-                    }),
-                ],
-            }),
-            multiview: None,
-            cache: None,
-        });
+        let create_pipeline = |label, front_face, shading| {
+            let module = match shading {
+                Shading::Smooth => &smooth_module,
+                Shading::Flat => &flat_module,
+            };
+            let mut constants = constants.clone();
+            constants.insert(
+                FLAT_SHADING_CONSTANT.to_string(),
+                if shading == Shading::Flat { 1.0 } else { 0.0 },
+            );
+
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(label),
+                layout: Some(&main_pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module,
+                    entry_point: "vertex_main",
+                    compilation_options: wgpu::PipelineCompilationOptions::default(),
+                    buffers: &[Vertex::layout()],
+                },
+                primitive: wgpu::PrimitiveState {
+                    topology: wgpu::PrimitiveTopology::TriangleList,
+                    strip_index_format: None,
+                    front_face,
+                    cull_mode: Some(wgpu::Face::Back),
+                    unclipped_depth: false,
+                    polygon_mode: wgpu::PolygonMode::Fill,
+                    conservative: false,
+                },
+                depth_stencil: Some(wgpu::DepthStencilState {
+                    format: renderer.depth_format,
+                    depth_write_enabled: true,
+                    depth_compare: renderer.depth_convention.closer(),
+                    stencil: wgpu::StencilState::default(),
+                    bias: wgpu::DepthBiasState::default(),
+                }),
+                multisample: wgpu::MultisampleState::default(),
+                fragment: Some(wgpu::FragmentState {
+                    module,
+                    entry_point: "fragment_main",
+                    compilation_options: wgpu::PipelineCompilationOptions {
+                        constants: &constants,
+                        ..Default::default()
+                    },
+                    targets: &targets,
+                }),
+                multiview: None,
+                cache: None,
+            })
+        };
+
+        Self {
+            ccw_pipeline: create_pipeline(
+                "main pipeline (ccw)",
+                wgpu::FrontFace::Ccw,
+                Shading::Smooth,
+            ),
+            cw_pipeline: create_pipeline(
+                "main pipeline (cw)",
+                wgpu::FrontFace::Cw,
+                Shading::Smooth,
+            ),
+            flat_ccw_pipeline: create_pipeline(
+                "main pipeline (flat, ccw)",
+                wgpu::FrontFace::Ccw,
+                Shading::Flat,
+            ),
+            flat_cw_pipeline: create_pipeline(
+                "main pipeline (flat, cw)",
+                wgpu::FrontFace::Cw,
+                Shading::Flat,
+            ),
+        }
+    }
 
-        Self { pipeline }
+    /// The pipeline that culls the back faces of meshes wound `front_face`
+    /// and writes normals for `shading`.
+    pub fn pipeline(&self, front_face: wgpu::FrontFace, shading: Shading) -> &wgpu::RenderPipeline {
+        match (shading, front_face) {
+            (Shading::Smooth, wgpu::FrontFace::Ccw) => &self.ccw_pipeline,
+            (Shading::Smooth, wgpu::FrontFace::Cw) => &self.cw_pipeline,
+            (Shading::Flat, wgpu::FrontFace::Ccw) => &self.flat_ccw_pipeline,
+            (Shading::Flat, wgpu::FrontFace::Cw) => &self.flat_cw_pipeline,
+        }
     }
 }
@@ -0,0 +1,208 @@
+use crate::{
+    camera::Camera,
+    pass_builder::PassBuilder,
+    texture::{create_fullscreen_texture, Texture},
+    Renderer,
+};
+
+#[derive(Clone, Copy, bytemuck::NoUninit)]
+#[repr(C)]
+struct GpuSettings {
+    strength: f32,
+    _padding: [f32; 3],
+}
+
+/// Smears the scene along the direction each pixel moved on screen since the
+/// previous frame. The movement comes from reprojecting the position under
+/// each pixel with the camera of the previous frame, so only camera motion
+/// is blurred.
+pub struct MotionBlur {
+    pipeline: wgpu::RenderPipeline,
+    bind_group_layout: wgpu::BindGroupLayout,
+    settings_buffer: wgpu::Buffer,
+    /// A copy of the scene to sample while the blurred result is written
+    /// over it. Created to match the size of the scene.
+    copy: Option<Texture>,
+
+    /// Scales the length of the blur. 1.0 blurs over the full distance moved
+    /// in a frame.
+    pub strength: f32,
+}
+
+impl MotionBlur {
+    pub fn new(renderer: &Renderer, camera: &Camera, format: wgpu::TextureFormat) -> Self {
+        let Renderer { device, .. } = renderer;
+
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("motion blur shader module"),
+            source: wgpu::ShaderSource::Wgsl(std::borrow::Cow::Borrowed(include_str!(
+                "motion_blur.wgsl"
+            ))),
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("motion blur bind group layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("motion blur pipeline layout"),
+            bind_group_layouts: &[&bind_group_layout, &camera.bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("motion blur render pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &module,
+                entry_point: "vertex_main",
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+                buffers: &[],
+            },
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            fragment: Some(wgpu::FragmentState {
+                module: &module,
+                entry_point: "fragment_main",
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            multiview: None,
+            cache: None,
+        });
+
+        let settings_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("motion blur settings buffer"),
+            size: std::mem::size_of::<GpuSettings>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        Self {
+            pipeline,
+            bind_group_layout,
+            settings_buffer,
+            copy: None,
+            strength: 0.5,
+        }
+    }
+
+    /// Blur `scene` in place, using `depth` to find where each pixel was in
+    /// the previous frame.
+    pub fn render(
+        &mut self,
+        renderer: &Renderer,
+        encoder: &mut wgpu::CommandEncoder,
+        scene: &Texture,
+        depth: &Texture,
+        camera: &Camera,
+    ) {
+        let size = scene.texture.size();
+        let copy = match self.copy.take() {
+            Some(texture) if texture.texture.size() == size => texture,
+            _ => create_fullscreen_texture(
+                renderer,
+                size.width,
+                size.height,
+                scene.texture.format(),
+                wgpu::FilterMode::Linear,
+                "motion blur texture",
+            ),
+        };
+
+        encoder.copy_texture_to_texture(
+            scene.texture.as_image_copy(),
+            copy.texture.as_image_copy(),
+            size,
+        );
+
+        renderer.queue.write_buffer(
+            &self.settings_buffer,
+            0,
+            bytemuck::cast_slice(&[GpuSettings {
+                strength: self.strength,
+                _padding: [0.0; 3],
+            }]),
+        );
+
+        let bind_group = renderer
+            .device
+            .create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("motion blur bind group"),
+                layout: &self.bind_group_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(&copy.view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::Sampler(&copy.sampler),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: wgpu::BindingResource::TextureView(&depth.view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 3,
+                        resource: self.settings_buffer.as_entire_binding(),
+                    },
+                ],
+            });
+
+        {
+            let mut render_pass =
+                PassBuilder::composite("motion blur render pass", &scene.view).begin(encoder);
+
+            render_pass.set_pipeline(&self.pipeline);
+            render_pass.set_bind_group(0, &bind_group, &[]);
+            render_pass.set_bind_group(1, &camera.bind_group, &[]);
+            render_pass.draw(0..3, 0..1);
+        }
+
+        self.copy = Some(copy);
+    }
+}
@@ -0,0 +1,88 @@
+@group(0) @binding(0) var t_color: texture_2d<f32>;
+@group(0) @binding(1) var s_color: sampler;
+// The depth target as an unfilterable float texture, with the depth in `.r`.
+@group(0) @binding(2) var t_depth: texture_2d<f32>;
+
+struct Settings {
+    // Scales the distance pixels moved since the previous frame.
+    strength: f32,
+}
+@group(0) @binding(3) var<uniform> settings: Settings;
+
+struct Camera {
+    projection_matrix: mat4x4<f32>,
+    view_matrix: mat4x4<f32>,
+    position: vec3<f32>,
+    inverse_view_projection: mat4x4<f32>,
+    previous_view_projection: mat4x4<f32>,
+}
+@group(1) @binding(0) var<uniform> camera: Camera;
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) tex_coord: vec2<f32>,
+};
+
+@vertex
+fn vertex_main(
+    @builtin(vertex_index) vertex_index: u32
+) -> VertexOutput {
+    // Create a fullscreen texture.
+    let tex_coord = vec2<f32>(
+        f32(vertex_index >> 1u),
+        f32(vertex_index & 1u)
+    ) * 2.0;
+    let position = vec4<f32>(
+        tex_coord * vec2<f32>(2.0, -2.0) + vec2<f32>(-1.0, 1.0),
+        0.0,
+        1.0
+    );
+
+    return VertexOutput(position, tex_coord);
+}
+
+// The number of samples taken along the velocity.
+const SAMPLES: i32 = 12;
+// The longest blur, as a fraction of the screen, so fast turns don't smear
+// the whole frame.
+const MAX_LENGTH: f32 = 0.05;
+
+// How far the surface at `tex_coord` moved on screen since the previous
+// frame, in texture coordinates.
+fn velocity(tex_coord: vec2<f32>) -> vec2<f32> {
+    let size = vec2<f32>(textureDimensions(t_depth));
+    let coord = clamp(vec2<i32>(tex_coord * size), vec2<i32>(0), vec2<i32>(size) - 1);
+    let depth = textureLoad(t_depth, coord, 0).r;
+
+    // Reproject the world position with the previous frame's camera.
+    let ndc = vec4(tex_coord.x * 2.0 - 1.0, 1.0 - tex_coord.y * 2.0, depth, 1.0);
+    let world = camera.inverse_view_projection * ndc;
+    let previous = camera.previous_view_projection * vec4(world.xyz / world.w, 1.0);
+    if previous.w <= 0.0 {
+        return vec2(0.0);
+    }
+    let previous_ndc = previous.xy / previous.w;
+    let previous_tex_coord = vec2(previous_ndc.x * 0.5 + 0.5, 0.5 - previous_ndc.y * 0.5);
+
+    return tex_coord - previous_tex_coord;
+}
+
+@fragment
+fn fragment_main(vertex_output: VertexOutput) -> @location(0) vec4<f32> {
+    var velocity = velocity(vertex_output.tex_coord) * settings.strength;
+    let speed = length(velocity);
+    if speed > MAX_LENGTH {
+        velocity *= MAX_LENGTH / speed;
+    }
+
+    // Average the samples along the path the pixel took, centered on it.
+    let center = textureSampleLevel(t_color, s_color, vertex_output.tex_coord, 0.0);
+    var color = vec3(0.0);
+    for (var i = 0; i < SAMPLES; i += 1) {
+        let t = f32(i) / f32(SAMPLES - 1) - 0.5;
+        let tex_coord = vertex_output.tex_coord - velocity * t;
+        color += textureSampleLevel(t_color, s_color, tex_coord, 0.0).rgb;
+    }
+
+    return vec4(color / f32(SAMPLES), center.a);
+}
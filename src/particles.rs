@@ -0,0 +1,230 @@
+use crate::{camera::Camera, pass_builder::PassBuilder, upload_buffer::UploadBuffer, Renderer};
+
+/// A single particle, simulated on the CPU.
+#[derive(Clone, Copy, Debug)]
+pub struct Particle {
+    pub position: cgmath::Vector3<f32>,
+    /// World units per second.
+    pub velocity: cgmath::Vector3<f32>,
+    /// The width of the billboard in world units.
+    pub size: f32,
+    pub color: [f32; 4],
+    /// Seconds left before the particle dies.
+    pub life: f32,
+    /// The life the particle started with. The particle fades out as its
+    /// life runs down.
+    pub max_life: f32,
+}
+
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+#[repr(C)]
+struct GpuParticle {
+    position: [f32; 3],
+    size: f32,
+    color: [f32; 4],
+}
+
+/// A pool of particles drawn as camera facing billboards with additive
+/// blending. They are tested against the scene depth but don't write to it,
+/// so they can be drawn in any order after the lighting composite.
+pub struct Particles {
+    pipeline: wgpu::RenderPipeline,
+    particles: Vec<Particle>,
+    /// New particles are dropped once the pool holds this many.
+    capacity: usize,
+    /// Pulls particles down, in world units per second squared.
+    pub gravity: cgmath::Vector3<f32>,
+    instances: UploadBuffer,
+    /// State of the random number generator used by the emitters.
+    seed: u32,
+}
+
+impl Particles {
+    pub fn new(renderer: &Renderer, camera: &Camera, capacity: usize) -> Self {
+        let module = renderer
+            .device
+            .create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some("particles module"),
+                source: wgpu::ShaderSource::Wgsl(std::borrow::Cow::Borrowed(include_str!(
+                    "particles.wgsl"
+                ))),
+            });
+
+        let pipeline_layout =
+            renderer
+                .device
+                .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                    label: Some("particles pipeline layout"),
+                    bind_group_layouts: &[&camera.bind_group_layout],
+                    push_constant_ranges: &[],
+                });
+
+        let pipeline = renderer
+            .device
+            .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some("particles render pipeline"),
+                layout: Some(&pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &module,
+                    entry_point: "vertex_main",
+                    compilation_options: wgpu::PipelineCompilationOptions::default(),
+                    buffers: &[wgpu::VertexBufferLayout {
+                        array_stride: std::mem::size_of::<GpuParticle>() as wgpu::BufferAddress,
+                        step_mode: wgpu::VertexStepMode::Instance,
+                        attributes: &wgpu::vertex_attr_array![
+                            0 => Float32x3,
+                            1 => Float32,
+                            2 => Float32x4,
+                        ],
+                    }],
+                },
+                primitive: wgpu::PrimitiveState {
+                    topology: wgpu::PrimitiveTopology::TriangleStrip,
+                    ..Default::default()
+                },
+                depth_stencil: Some(wgpu::DepthStencilState {
+                    format: renderer.depth_format,
+                    depth_write_enabled: false,
+                    depth_compare: renderer.depth_convention.closer(),
+                    stencil: wgpu::StencilState::default(),
+                    bias: wgpu::DepthBiasState::default(),
+                }),
+                multisample: wgpu::MultisampleState::default(),
+                fragment: Some(wgpu::FragmentState {
+                    module: &module,
+                    entry_point: "fragment_main",
+                    compilation_options: wgpu::PipelineCompilationOptions::default(),
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format: renderer.surface_config.format,
+                        blend: Some(wgpu::BlendState {
+                            color: wgpu::BlendComponent {
+                                src_factor: wgpu::BlendFactor::One,
+                                dst_factor: wgpu::BlendFactor::One,
+                                operation: wgpu::BlendOperation::Add,
+                            },
+                            alpha: wgpu::BlendComponent {
+                                src_factor: wgpu::BlendFactor::Zero,
+                                dst_factor: wgpu::BlendFactor::One,
+                                operation: wgpu::BlendOperation::Add,
+                            },
+                        }),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                }),
+                multiview: None,
+                cache: None,
+            });
+
+        Self {
+            pipeline,
+            particles: Vec::with_capacity(capacity),
+            capacity,
+            gravity: cgmath::vec3(0.0, -2.0, 0.0),
+            instances: UploadBuffer::new(
+                renderer,
+                "particles instances",
+                wgpu::BufferUsages::VERTEX,
+            ),
+            seed: 0x2545_f491,
+        }
+    }
+
+    /// The number of live particles.
+    #[allow(dead_code)]
+    pub fn len(&self) -> usize {
+        self.particles.len()
+    }
+
+    #[allow(dead_code)]
+    pub fn is_empty(&self) -> bool {
+        self.particles.is_empty()
+    }
+
+    /// Add a particle to the pool. Does nothing if the pool is full.
+    pub fn emit(&mut self, particle: Particle) {
+        if self.particles.len() < self.capacity {
+            self.particles.push(particle);
+        }
+    }
+
+    /// Emit `count` sparks flying out of `origin` in random directions.
+    pub fn emit_sparks(&mut self, origin: cgmath::Vector3<f32>, count: usize) {
+        for _ in 0..count {
+            let direction = cgmath::vec3(
+                self.random() * 2.0 - 1.0,
+                self.random(),
+                self.random() * 2.0 - 1.0,
+            );
+            let life = 0.5 + self.random();
+            let size = 0.05 + self.random() * 0.05;
+            let green = 0.6 + self.random() * 0.3;
+            self.emit(Particle {
+                position: origin,
+                velocity: direction * 2.0,
+                size,
+                color: [1.0, green, 0.2, 1.0],
+                life,
+                max_life: life,
+            });
+        }
+    }
+
+    /// Move the particles `time_delta` seconds forward and remove the ones
+    /// that died.
+    pub fn update(&mut self, time_delta: f32) {
+        let gravity = self.gravity;
+        self.particles.retain_mut(|particle| {
+            particle.life -= time_delta;
+            particle.velocity += gravity * time_delta;
+            particle.position += particle.velocity * time_delta;
+            particle.life > 0.0
+        });
+    }
+
+    pub fn render(
+        &mut self,
+        renderer: &Renderer,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+        depth_view: &wgpu::TextureView,
+        camera: &Camera,
+    ) {
+        if self.particles.is_empty() {
+            return;
+        }
+
+        let instances = self
+            .particles
+            .iter()
+            .map(|particle| {
+                let [r, g, b, a] = particle.color;
+                let fade = (particle.life / particle.max_life).clamp(0.0, 1.0);
+                GpuParticle {
+                    position: particle.position.into(),
+                    size: particle.size,
+                    color: [r, g, b, a * fade],
+                }
+            })
+            .collect::<Vec<_>>();
+
+        self.instances.clear();
+        let range = self.instances.push(&instances);
+        self.instances.upload(renderer);
+
+        let mut render_pass =
+            PassBuilder::overlay("particles render pass", view, depth_view).begin(encoder);
+
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &camera.bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.instances.slice(range));
+        render_pass.draw(0..4, 0..instances.len() as u32);
+    }
+
+    /// A pseudo random number in 0..1, from a xorshift generator.
+    fn random(&mut self) -> f32 {
+        self.seed ^= self.seed << 13;
+        self.seed ^= self.seed >> 17;
+        self.seed ^= self.seed << 5;
+        (self.seed >> 8) as f32 / (1 << 24) as f32
+    }
+}
@@ -0,0 +1,45 @@
+struct Camera {
+    projection_matrix: mat4x4<f32>,
+    view_matrix: mat4x4<f32>,
+}
+
+@group(0) @binding(0) var<uniform> camera: Camera;
+
+struct VertexInput {
+    @builtin(vertex_index) vertex_index: u32,
+    @location(0) position: vec3<f32>,
+    @location(1) size: f32,
+    @location(2) color: vec4<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) color: vec4<f32>,
+    /// -1..1 across the quad.
+    @location(1) corner: vec2<f32>,
+}
+
+@vertex fn vertex_main(vertex: VertexInput) -> VertexOutput {
+    // A triangle strip of 4 vertices makes the quad.
+    let corner = vec2<f32>(
+        f32(vertex.vertex_index & 1u) * 2.0 - 1.0,
+        f32(vertex.vertex_index >> 1u) * 2.0 - 1.0,
+    );
+
+    // The quad is expanded in view space, so it always faces the camera.
+    let view_position = camera.view_matrix * vec4<f32>(vertex.position, 1.0)
+        + vec4<f32>(corner * vertex.size * 0.5, 0.0, 0.0);
+
+    var output: VertexOutput;
+    output.clip_position = camera.projection_matrix * view_position;
+    output.color = vertex.color;
+    output.corner = corner;
+    return output;
+}
+
+@fragment fn fragment_main(vertex: VertexOutput) -> @location(0) vec4<f32> {
+    // A soft round dot, fading out towards the edge of the quad.
+    let falloff = 1.0 - smoothstep(0.0, 1.0, length(vertex.corner));
+    let strength = vertex.color.a * falloff;
+    return vec4<f32>(vertex.color.rgb * strength, strength);
+}
@@ -0,0 +1,127 @@
+use crate::gbuffer::{GBuffer, GBufferClearValues};
+
+/// Collects the attachments of a render pass, so the common kinds of passes
+/// don't each spell out a [wgpu::RenderPassDescriptor]. Every attachment is
+/// stored at the end of the pass; only what happens at the start differs.
+///
+/// The pass returned by [PassBuilder::begin] ends when it is dropped.
+pub struct PassBuilder<'a> {
+    label: &'a str,
+    color_attachments: Vec<Option<wgpu::RenderPassColorAttachment<'a>>>,
+    depth_stencil_attachment: Option<wgpu::RenderPassDepthStencilAttachment<'a>>,
+}
+
+impl<'a> PassBuilder<'a> {
+    /// A pass without any attachments yet.
+    pub fn new(label: &'a str) -> Self {
+        Self {
+            label,
+            color_attachments: Vec::new(),
+            depth_stencil_attachment: None,
+        }
+    }
+
+    /// The geometry pass, rendering to all the targets of `gbuffer` after
+    /// clearing them to `clear_values`. The position target keeps its slot
+    /// when positions are reconstructed from depth, so the slots match the
+    /// outputs of the geometry shader.
+    pub fn gbuffer(
+        label: &'a str,
+        gbuffer: &GBuffer<'a>,
+        clear_values: &GBufferClearValues,
+    ) -> Self {
+        let mut builder = Self::new(label).with_color(
+            &gbuffer.albedo.view,
+            wgpu::LoadOp::Clear(clear_values.albedo),
+        );
+        builder
+            .color_attachments
+            .push(gbuffer.position.and_then(|position| {
+                color_attachment(&position.view, wgpu::LoadOp::Clear(clear_values.position))
+            }));
+        builder
+            .with_color(
+                &gbuffer.normal.view,
+                wgpu::LoadOp::Clear(clear_values.normal),
+            )
+            .with_color(
+                &gbuffer.specular.view,
+                wgpu::LoadOp::Clear(clear_values.specular),
+            )
+            .with_depth(&gbuffer.depth.view, clear_values.depth_load_op())
+    }
+
+    /// Draws over what is already in `target`, like a fullscreen effect that
+    /// reads a copy of the target, or the UI.
+    pub fn composite(label: &'a str, target: &'a wgpu::TextureView) -> Self {
+        Self::new(label).with_loaded_color(target)
+    }
+
+    /// Draws over what is already in `target`, hidden behind whatever is in
+    /// `depth_view`, like transparent objects and gizmos.
+    pub fn overlay(
+        label: &'a str,
+        target: &'a wgpu::TextureView,
+        depth_view: &'a wgpu::TextureView,
+    ) -> Self {
+        Self::new(label)
+            .with_loaded_color(target)
+            .with_loaded_depth(depth_view)
+    }
+
+    /// Add a color attachment in the next slot.
+    pub fn with_color(
+        mut self,
+        view: &'a wgpu::TextureView,
+        load: wgpu::LoadOp<wgpu::Color>,
+    ) -> Self {
+        self.color_attachments.push(color_attachment(view, load));
+        self
+    }
+
+    /// Add a color attachment in the next slot, keeping what is in it.
+    pub fn with_loaded_color(self, view: &'a wgpu::TextureView) -> Self {
+        self.with_color(view, wgpu::LoadOp::Load)
+    }
+
+    pub fn with_depth(mut self, view: &'a wgpu::TextureView, load: wgpu::LoadOp<f32>) -> Self {
+        self.depth_stencil_attachment = Some(wgpu::RenderPassDepthStencilAttachment {
+            view,
+            depth_ops: Some(wgpu::Operations {
+                load,
+                store: wgpu::StoreOp::Store,
+            }),
+            stencil_ops: None,
+        });
+        self
+    }
+
+    /// Test against the depth in `view`, and keep writing to it.
+    pub fn with_loaded_depth(self, view: &'a wgpu::TextureView) -> Self {
+        self.with_depth(view, wgpu::LoadOp::Load)
+    }
+
+    pub fn begin(self, encoder: &mut wgpu::CommandEncoder) -> wgpu::RenderPass<'_> {
+        encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some(self.label),
+            color_attachments: &self.color_attachments,
+            depth_stencil_attachment: self.depth_stencil_attachment,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        })
+    }
+}
+
+fn color_attachment(
+    view: &wgpu::TextureView,
+    load: wgpu::LoadOp<wgpu::Color>,
+) -> Option<wgpu::RenderPassColorAttachment<'_>> {
+    Some(wgpu::RenderPassColorAttachment {
+        view,
+        resolve_target: None,
+        ops: wgpu::Operations {
+            load,
+            store: wgpu::StoreOp::Store,
+        },
+    })
+}
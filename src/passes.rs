@@ -0,0 +1,121 @@
+/// A section of the frame. The app renders the enabled passes in the order of
+/// its [PassList].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Pass {
+    Shadows,
+    GBuffer,
+    Decals,
+    Lighting,
+    Reflections,
+    Transparent,
+    Particles,
+    DepthOfField,
+    MotionBlur,
+    PostEffects,
+    Gizmos,
+    Upscale,
+    Ui,
+}
+
+impl Pass {
+    /// Used for the debug group of the pass, so it is easy to find in GPU
+    /// captures.
+    pub fn name(self) -> &'static str {
+        match self {
+            Pass::Shadows => "shadows",
+            Pass::GBuffer => "gbuffer",
+            Pass::Decals => "decals",
+            Pass::Lighting => "lighting",
+            Pass::Reflections => "reflections",
+            Pass::Transparent => "transparent",
+            Pass::Particles => "particles",
+            Pass::DepthOfField => "depth of field",
+            Pass::MotionBlur => "motion blur",
+            Pass::PostEffects => "post effects",
+            Pass::Gizmos => "gizmos",
+            Pass::Upscale => "upscale",
+            Pass::Ui => "ui",
+        }
+    }
+}
+
+/// An ordered list of passes, each of which can be turned off. It doesn't
+/// resolve dependencies between passes, so reordering them is up to the
+/// caller.
+pub struct PassList {
+    passes: Vec<(Pass, bool)>,
+}
+
+impl Default for PassList {
+    /// Every pass, enabled, in the order the frame depends on.
+    fn default() -> Self {
+        Self {
+            passes: [
+                Pass::Shadows,
+                Pass::GBuffer,
+                Pass::Decals,
+                Pass::Lighting,
+                Pass::Reflections,
+                Pass::Transparent,
+                Pass::Particles,
+                Pass::DepthOfField,
+                Pass::MotionBlur,
+                Pass::PostEffects,
+                Pass::Gizmos,
+                Pass::Upscale,
+                Pass::Ui,
+            ]
+            .into_iter()
+            .map(|pass| (pass, true))
+            .collect(),
+        }
+    }
+}
+
+impl PassList {
+    /// The enabled passes, in order.
+    pub fn enabled(&self) -> impl Iterator<Item = Pass> + '_ {
+        self.passes
+            .iter()
+            .filter(|(_, enabled)| *enabled)
+            .map(|(pass, _)| *pass)
+    }
+
+    pub fn is_enabled(&self, pass: Pass) -> bool {
+        self.passes
+            .iter()
+            .any(|(other, enabled)| *other == pass && *enabled)
+    }
+
+    pub fn set_enabled(&mut self, pass: Pass, enabled: bool) {
+        for (other, other_enabled) in self.passes.iter_mut() {
+            if *other == pass {
+                *other_enabled = enabled;
+            }
+        }
+    }
+
+    pub fn toggle(&mut self, pass: Pass) {
+        self.set_enabled(pass, !self.is_enabled(pass));
+    }
+
+    /// Move `pass` so it runs right before `before`. Does nothing if either
+    /// of them is not in the list.
+    #[allow(dead_code)]
+    pub fn move_before(&mut self, pass: Pass, before: Pass) {
+        let Some(from) = self.passes.iter().position(|(other, _)| *other == pass) else {
+            return;
+        };
+        if !self.passes.iter().any(|(other, _)| *other == before) {
+            return;
+        }
+
+        let entry = self.passes.remove(from);
+        let to = self
+            .passes
+            .iter()
+            .position(|(other, _)| *other == before)
+            .expect("checked above");
+        self.passes.insert(to, entry);
+    }
+}
@@ -0,0 +1,348 @@
+use crate::{
+    camera::Camera,
+    deferred_renderer::DeferredRenderer,
+    gbuffer::{self, GBuffer, GBufferClearValues},
+    lights::Lights,
+    mesh::{GpuMesh, Vertex},
+    mesh_render_pipeline::{MeshRenderPipeline, Shading},
+    pass_builder::PassBuilder,
+    texture::{create_depth_texture, create_fullscreen_texture, Texture},
+    Renderer,
+};
+
+#[derive(Clone, Copy, bytemuck::NoUninit)]
+#[repr(C)]
+struct GpuSettings {
+    target_size: [f32; 2],
+    reflectivity: f32,
+    _padding: f32,
+}
+
+/// The G-buffer and lit output of the mirrored view.
+struct Targets {
+    depth: Texture,
+    albedo: Texture,
+    position: Option<Texture>,
+    normal: Texture,
+    specular: Texture,
+    color: Texture,
+}
+
+/// Reflects the scene in a horizontal plane, like a wet floor. The scene is
+/// rendered a second time through the deferred pipeline from a camera
+/// mirrored below the plane, and the result is blended over the plane with a
+/// Fresnel term.
+///
+/// Geometry below the plane is not clipped, so it should only be used for a
+/// plane that everything stands on.
+pub struct PlanarReflection {
+    /// Drawn into by the mirrored view, with its own uniform buffer so it
+    /// doesn't overwrite the main camera.
+    camera: Camera,
+    pipeline: wgpu::RenderPipeline,
+    bind_group_layout: wgpu::BindGroupLayout,
+    settings_buffer: wgpu::Buffer,
+    /// Whether the G-buffer of the mirrored view has a position target, which
+    /// has to match the pipeline it is filled with.
+    store_position: bool,
+    /// Created to match the size of the scene and [PlanarReflection::resolution_scale].
+    targets: Option<Targets>,
+
+    /// The height of the reflecting plane.
+    pub height: f32,
+    /// How much of the reflection is blended over the plane, before the
+    /// Fresnel term.
+    pub reflectivity: f32,
+    /// The size of the reflection relative to the scene. The reflection is
+    /// usually blurry enough to get away with less than the full resolution.
+    pub resolution_scale: f32,
+}
+
+impl PlanarReflection {
+    pub fn new(
+        renderer: &Renderer,
+        camera: &Camera,
+        format: wgpu::TextureFormat,
+        store_position: bool,
+    ) -> Self {
+        let Renderer { device, .. } = renderer;
+
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("planar reflection shader module"),
+            source: wgpu::ShaderSource::Wgsl(std::borrow::Cow::Borrowed(include_str!(
+                "planar_reflection.wgsl"
+            ))),
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("planar reflection bind group layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("planar reflection pipeline layout"),
+            bind_group_layouts: &[&bind_group_layout, &camera.bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("planar reflection render pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &module,
+                entry_point: "vertex_main",
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+                buffers: &[Vertex::layout_with(&Vertex::ATTRIBUTES[..1])],
+            },
+            primitive: wgpu::PrimitiveState::default(),
+            // The plane is already in the depth buffer, so only the pixels
+            // where it is visible are blended.
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: renderer.depth_format,
+                depth_write_enabled: false,
+                depth_compare: renderer.depth_convention.closer_or_equal(),
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            fragment: Some(wgpu::FragmentState {
+                module: &module,
+                entry_point: "fragment_main",
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::COLOR,
+                })],
+            }),
+            multiview: None,
+            cache: None,
+        });
+
+        let settings_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("planar reflection settings buffer"),
+            size: std::mem::size_of::<GpuSettings>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        Self {
+            camera: Camera::new(renderer),
+            pipeline,
+            bind_group_layout,
+            settings_buffer,
+            store_position,
+            targets: None,
+            height: 0.0,
+            reflectivity: 0.5,
+            resolution_scale: 0.5,
+        }
+    }
+
+    /// Render `objects` mirrored in the plane, as seen by `camera` from
+    /// `camera_position`, shaded like the scene with `shading`. `size` is the
+    /// size of the scene the reflection is blended into.
+    #[allow(clippy::too_many_arguments)]
+    pub fn render<'a>(
+        &mut self,
+        renderer: &Renderer,
+        encoder: &mut wgpu::CommandEncoder,
+        camera: &Camera,
+        camera_position: cgmath::Point3<f32>,
+        objects: impl Iterator<Item = (&'a GpuMesh, &'a wgpu::BindGroup)>,
+        mesh_render_pipeline: &MeshRenderPipeline,
+        shading: Shading,
+        deferred_renderer: &DeferredRenderer,
+        lights: &Lights,
+        size: (u32, u32),
+    ) {
+        // Reflect across the plane: move it to the origin, flip Y and move it
+        // back.
+        let mirror = cgmath::Matrix4::from_translation(cgmath::vec3(0.0, self.height, 0.0))
+            * cgmath::Matrix4::from_nonuniform_scale(1.0, -1.0, 1.0)
+            * cgmath::Matrix4::from_translation(cgmath::vec3(0.0, -self.height, 0.0));
+        let mirrored_position = cgmath::Point3::new(
+            camera_position.x,
+            2.0 * self.height - camera_position.y,
+            camera_position.z,
+        );
+        self.camera.set_matrices(
+            renderer,
+            camera.projection_matrix,
+            camera.view_matrix * mirror,
+            mirrored_position,
+        );
+
+        self.update_targets(renderer, size);
+        let targets = self.targets.as_ref().expect("created above");
+        let gbuffer = GBuffer {
+            depth: &targets.depth,
+            albedo: &targets.albedo,
+            position: targets.position.as_ref(),
+            normal: &targets.normal,
+            specular: &targets.specular,
+        };
+
+        {
+            let clear_values = GBufferClearValues::new(renderer.depth_convention);
+            let mut render_pass = PassBuilder::gbuffer(
+                "planar reflection gbuffer render pass",
+                &gbuffer,
+                &clear_values,
+            )
+            .begin(encoder);
+
+            render_pass.set_bind_group(0, &self.camera.bind_group, &[]);
+            for (mesh, material) in objects {
+                // Mirroring flips the winding of every triangle.
+                let front_face = match mesh.front_face {
+                    wgpu::FrontFace::Ccw => wgpu::FrontFace::Cw,
+                    wgpu::FrontFace::Cw => wgpu::FrontFace::Ccw,
+                };
+                render_pass.set_pipeline(mesh_render_pipeline.pipeline(front_face, shading));
+                render_pass.set_bind_group(1, material, &[]);
+                mesh.draw(&mut render_pass);
+            }
+        }
+
+        deferred_renderer.light_pass(
+            renderer,
+            encoder,
+            &gbuffer,
+            lights,
+            &self.camera,
+            &targets.color.view,
+        );
+    }
+
+    /// Blend the reflection over `plane`, where it is visible in `depth`.
+    pub fn composite(
+        &self,
+        renderer: &Renderer,
+        encoder: &mut wgpu::CommandEncoder,
+        target: &Texture,
+        depth: &Texture,
+        camera: &Camera,
+        plane: &GpuMesh,
+    ) {
+        let Some(targets) = self.targets.as_ref() else {
+            return;
+        };
+
+        renderer.queue.write_buffer(
+            &self.settings_buffer,
+            0,
+            bytemuck::cast_slice(&[GpuSettings {
+                target_size: [
+                    target.texture.width() as f32,
+                    target.texture.height() as f32,
+                ],
+                reflectivity: self.reflectivity,
+                _padding: 0.0,
+            }]),
+        );
+
+        let bind_group = renderer
+            .device
+            .create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("planar reflection bind group"),
+                layout: &self.bind_group_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(&targets.color.view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::Sampler(&targets.color.sampler),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: self.settings_buffer.as_entire_binding(),
+                    },
+                ],
+            });
+
+        let mut render_pass =
+            PassBuilder::overlay("planar reflection render pass", &target.view, &depth.view)
+                .begin(encoder);
+
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &bind_group, &[]);
+        render_pass.set_bind_group(1, &camera.bind_group, &[]);
+        plane.draw(&mut render_pass);
+    }
+
+    /// Recreate the targets if the size of the scene or the resolution scale
+    /// changed.
+    fn update_targets(&mut self, renderer: &Renderer, size: (u32, u32)) {
+        let width = ((size.0 as f32 * self.resolution_scale).round() as u32).max(1);
+        let height = ((size.1 as f32 * self.resolution_scale).round() as u32).max(1);
+
+        let up_to_date = self.targets.as_ref().is_some_and(|targets| {
+            targets.color.texture.width() == width && targets.color.texture.height() == height
+        });
+        if !up_to_date {
+            let target = |format, filter, label| {
+                create_fullscreen_texture(renderer, width, height, format, filter, label)
+            };
+            self.targets = Some(Targets {
+                depth: create_depth_texture(renderer, width, height, renderer.depth_format),
+                albedo: target(
+                    gbuffer::ALBEDO_FORMAT,
+                    wgpu::FilterMode::Linear,
+                    "reflection albedo texture",
+                ),
+                position: self.store_position.then(|| {
+                    target(
+                        gbuffer::POSITION_FORMAT,
+                        wgpu::FilterMode::Nearest,
+                        "reflection position texture",
+                    )
+                }),
+                normal: target(
+                    gbuffer::normal_format(renderer.limits_profile),
+                    wgpu::FilterMode::Nearest,
+                    "reflection normal texture",
+                ),
+                specular: target(
+                    gbuffer::SPECULAR_FORMAT,
+                    wgpu::FilterMode::Linear,
+                    "reflection specular texture",
+                ),
+                color: target(
+                    renderer.surface_config.format,
+                    wgpu::FilterMode::Linear,
+                    "reflection texture",
+                ),
+            });
+        }
+    }
+}
@@ -0,0 +1,50 @@
+@group(0) @binding(0) var t_reflection: texture_2d<f32>;
+@group(0) @binding(1) var s_reflection: sampler;
+
+struct Settings {
+    // The size of the target the plane is drawn into, to find where the
+    // reflection of each pixel is.
+    target_size: vec2<f32>,
+    reflectivity: f32,
+}
+@group(0) @binding(2) var<uniform> settings: Settings;
+
+struct Camera {
+    projection_matrix: mat4x4<f32>,
+    view_matrix: mat4x4<f32>,
+    position: vec3<f32>,
+}
+@group(1) @binding(0) var<uniform> camera: Camera;
+
+// How much a wet surface reflects when looked at head on. It goes up towards
+// 1.0 at grazing angles.
+const BASE_REFLECTANCE: f32 = 0.25;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) world_position: vec3<f32>,
+}
+
+@vertex
+fn vertex_main(@location(0) position: vec3<f32>) -> VertexOutput {
+    // The same transform as the geometry pass, so the depth matches the plane
+    // in the depth buffer exactly.
+    let world_position = vec4(position, 1.0);
+    let clip_position = camera.projection_matrix * camera.view_matrix * world_position;
+    return VertexOutput(clip_position, world_position.xyz);
+}
+
+@fragment
+fn fragment_main(vertex: VertexOutput) -> @location(0) vec4<f32> {
+    // The mirrored camera sees the reflection of each pixel at the same place
+    // on screen.
+    let tex_coord = vertex.clip_position.xy / settings.target_size;
+    let reflection = textureSample(t_reflection, s_reflection, tex_coord).rgb;
+
+    // Schlick's approximation of the Fresnel term, for a horizontal plane.
+    let view_direction = normalize(camera.position - vertex.world_position);
+    let cos_theta = clamp(view_direction.y, 0.0, 1.0);
+    let fresnel = BASE_REFLECTANCE + (1.0 - BASE_REFLECTANCE) * pow(1.0 - cos_theta, 5.0);
+
+    return vec4(reflection, settings.reflectivity * fresnel);
+}
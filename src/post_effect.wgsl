@@ -0,0 +1,61 @@
+// Prepended to the source of every custom post effect. The effect defines
+// `fragment_main`, which gets a `VertexOutput` and returns the new color of
+// the pixel.
+
+// The scene as it was before the effect.
+@group(0) @binding(0) var t_color: texture_2d<f32>;
+@group(0) @binding(1) var s_color: sampler;
+// The G-buffer, for effects that need more than the color. The depth is in
+// the red channel, in 0..1 with `FAR_DEPTH` at the far plane, and can be read
+// with `textureLoad(t_depth, coord, 0).r`, or with
+// `textureSample(t_depth, s_depth, tex_coord).r`.
+@group(0) @binding(2) var t_depth: texture_2d<f32>;
+// The depth where nothing was drawn: 1.0, or 0.0 with reversed depth.
+override FAR_DEPTH: f32 = 1.0;
+// The alpha of the albedo is the baked ambient occlusion of the vertices.
+@group(0) @binding(3) var t_albedo: texture_2d<f32>;
+// Normals are in world space, or in view space if the renderer was built with
+// `NormalSpace::View`, and packed into 0..1 on the compatibility profile.
+@group(0) @binding(4) var t_normal: texture_2d<f32>;
+
+struct PostEffectInput {
+    // The size of the scene in pixels.
+    resolution: vec2<f32>,
+    // Seconds since the app started.
+    time: f32,
+}
+@group(0) @binding(5) var<uniform> input: PostEffectInput;
+
+// Reads depth values as they are, without filtering.
+@group(0) @binding(6) var s_depth: sampler;
+
+struct Camera {
+    projection_matrix: mat4x4<f32>,
+    view_matrix: mat4x4<f32>,
+    position: vec3<f32>,
+    inverse_view_projection: mat4x4<f32>,
+}
+@group(1) @binding(0) var<uniform> camera: Camera;
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) tex_coord: vec2<f32>,
+};
+
+@vertex
+fn vertex_main(
+    @builtin(vertex_index) vertex_index: u32
+) -> VertexOutput {
+    // Create a fullscreen texture.
+    let tex_coord = vec2<f32>(
+        f32(vertex_index >> 1u),
+        f32(vertex_index & 1u)
+    ) * 2.0;
+    let position = vec4<f32>(
+        tex_coord * vec2<f32>(2.0, -2.0) + vec2<f32>(-1.0, 1.0),
+        0.0,
+        1.0
+    );
+
+    return VertexOutput(position, tex_coord);
+}
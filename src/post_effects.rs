@@ -0,0 +1,297 @@
+use crate::{
+    camera::Camera,
+    gbuffer::GBuffer,
+    pass_builder::PassBuilder,
+    texture::{create_depth_sampler, create_fullscreen_texture, Texture},
+    Renderer,
+};
+
+slotmap::new_key_type! {
+    /// A custom effect added with [PostEffects::add].
+    pub struct PostEffectId;
+}
+
+#[derive(Clone, Copy, bytemuck::NoUninit)]
+#[repr(C)]
+struct GpuPostEffectInput {
+    resolution: [f32; 2],
+    time: f32,
+    _padding: f32,
+}
+
+struct PostEffect {
+    pipeline: wgpu::RenderPipeline,
+    enabled: bool,
+}
+
+/// Fullscreen effects written in WGSL by users of the renderer, run one
+/// after the other over the lit scene.
+///
+/// The source of an effect only has to define `fragment_main`. The bindings
+/// and the vertex shader are prepended from `post_effect.wgsl`, which
+/// documents what is available: the scene color, the depth, albedo and
+/// normal targets of the G-buffer, the resolution, the time and the camera.
+pub struct PostEffects {
+    effects: slotmap::SlotMap<PostEffectId, PostEffect>,
+    /// Effects run in the order they were added.
+    order: Vec<PostEffectId>,
+    bind_group_layout: wgpu::BindGroupLayout,
+    pipeline_layout: wgpu::PipelineLayout,
+    format: wgpu::TextureFormat,
+    input_buffer: wgpu::Buffer,
+    depth_sampler: wgpu::Sampler,
+    /// A copy of the scene for each effect to read while it writes over the
+    /// scene. Created to match the size of the scene.
+    copy: Option<Texture>,
+    /// Time passed to the effects, advanced by [PostEffects::advance].
+    time: std::time::Duration,
+}
+
+impl PostEffects {
+    pub fn new(renderer: &Renderer, camera: &Camera, format: wgpu::TextureFormat) -> Self {
+        let Renderer { device, .. } = renderer;
+
+        let texture_entry = |binding, sample_type| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type,
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        };
+        let filterable = wgpu::TextureSampleType::Float { filterable: true };
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("post effect bind group layout"),
+            entries: &[
+                texture_entry(0, filterable),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                texture_entry(2, wgpu::TextureSampleType::Float { filterable: false }),
+                texture_entry(3, filterable),
+                texture_entry(4, filterable),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 5,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 6,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::NonFiltering),
+                    count: None,
+                },
+            ],
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("post effect pipeline layout"),
+            bind_group_layouts: &[&bind_group_layout, &camera.bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let input_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("post effect input buffer"),
+            size: std::mem::size_of::<GpuPostEffectInput>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        Self {
+            effects: slotmap::SlotMap::with_key(),
+            order: Vec::new(),
+            bind_group_layout,
+            pipeline_layout,
+            format,
+            input_buffer,
+            depth_sampler: create_depth_sampler(renderer),
+            copy: None,
+            time: std::time::Duration::ZERO,
+        }
+    }
+
+    /// Compile `source` and add it after the other effects, enabled. Returns
+    /// `None` if the shader doesn't compile; the error is logged.
+    pub fn add(&mut self, renderer: &Renderer, source: &str) -> Option<PostEffectId> {
+        let Renderer { device, .. } = renderer;
+
+        renderer.push_error_scope();
+
+        let source = format!("{}\n{}", include_str!("post_effect.wgsl"), source);
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("post effect shader module"),
+            source: wgpu::ShaderSource::Wgsl(source.into()),
+        });
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("post effect render pipeline"),
+            layout: Some(&self.pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &module,
+                entry_point: "vertex_main",
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+                buffers: &[],
+            },
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            fragment: Some(wgpu::FragmentState {
+                module: &module,
+                entry_point: "fragment_main",
+                compilation_options: wgpu::PipelineCompilationOptions {
+                    constants: &renderer.depth_convention.shader_constants(),
+                    ..Default::default()
+                },
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: self.format,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            multiview: None,
+            cache: None,
+        });
+
+        if !renderer.pop_error_scope("post effect") {
+            return None;
+        }
+
+        let id = self.effects.insert(PostEffect {
+            pipeline,
+            enabled: true,
+        });
+        self.order.push(id);
+        Some(id)
+    }
+
+    /// Remove the effect. Does nothing if it was already removed.
+    pub fn remove(&mut self, id: PostEffectId) {
+        self.effects.remove(id);
+        self.order.retain(|other| *other != id);
+    }
+
+    pub fn is_enabled(&self, id: PostEffectId) -> bool {
+        self.effects.get(id).is_some_and(|effect| effect.enabled)
+    }
+
+    pub fn set_enabled(&mut self, id: PostEffectId, enabled: bool) {
+        if let Some(effect) = self.effects.get_mut(id) {
+            effect.enabled = enabled;
+        }
+    }
+
+    /// Move the time the effects see forward by `time_delta`.
+    pub fn advance(&mut self, time_delta: std::time::Duration) {
+        self.time += time_delta;
+    }
+
+    /// Run the enabled effects over `scene`, in the order they were added.
+    pub fn render(
+        &mut self,
+        renderer: &Renderer,
+        encoder: &mut wgpu::CommandEncoder,
+        scene: &Texture,
+        gbuffer: &GBuffer,
+        camera: &Camera,
+    ) {
+        let enabled = self
+            .order
+            .iter()
+            .filter_map(|id| self.effects.get(*id))
+            .filter(|effect| effect.enabled)
+            .collect::<Vec<_>>();
+        if enabled.is_empty() {
+            return;
+        }
+
+        let size = scene.texture.size();
+        let copy = match self.copy.take() {
+            Some(texture) if texture.texture.size() == size => texture,
+            _ => create_fullscreen_texture(
+                renderer,
+                size.width,
+                size.height,
+                scene.texture.format(),
+                wgpu::FilterMode::Linear,
+                "post effect texture",
+            ),
+        };
+
+        renderer.queue.write_buffer(
+            &self.input_buffer,
+            0,
+            bytemuck::cast_slice(&[GpuPostEffectInput {
+                resolution: [size.width as f32, size.height as f32],
+                time: self.time.as_secs_f32(),
+                _padding: 0.0,
+            }]),
+        );
+
+        let bind_group = renderer
+            .device
+            .create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("post effect bind group"),
+                layout: &self.bind_group_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(&copy.view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::Sampler(&copy.sampler),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: wgpu::BindingResource::TextureView(&gbuffer.depth.view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 3,
+                        resource: wgpu::BindingResource::TextureView(&gbuffer.albedo.view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 4,
+                        resource: wgpu::BindingResource::TextureView(&gbuffer.normal.view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 5,
+                        resource: self.input_buffer.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 6,
+                        resource: wgpu::BindingResource::Sampler(&self.depth_sampler),
+                    },
+                ],
+            });
+
+        // Each effect reads the result of the one before it.
+        for effect in enabled {
+            encoder.copy_texture_to_texture(
+                scene.texture.as_image_copy(),
+                copy.texture.as_image_copy(),
+                size,
+            );
+
+            let mut render_pass =
+                PassBuilder::composite("post effect render pass", &scene.view).begin(encoder);
+
+            render_pass.set_pipeline(&effect.pipeline);
+            render_pass.set_bind_group(0, &bind_group, &[]);
+            render_pass.set_bind_group(1, &camera.bind_group, &[]);
+            render_pass.draw(0..3, 0..1);
+        }
+
+        self.copy = Some(copy);
+    }
+}
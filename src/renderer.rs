@@ -0,0 +1,611 @@
+use std::sync::Arc;
+
+use winit::dpi::PhysicalSize;
+
+use crate::{
+    allocations::AllocationTracker,
+    gbuffer::NormalSpace,
+    texture::{create_fullscreen_texture, DepthConvention, Texture, DEFAULT_DEPTH_FORMAT},
+};
+
+/// The set of device limits the renderer asks for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LimitsProfile {
+    /// Use [`LimitsProfile::Compatibility`] on adapters that are not fully
+    /// WebGPU compliant, like GLES and WebGL, otherwise [`LimitsProfile::Full`].
+    Auto,
+    /// The default WebGPU limits.
+    Full,
+    /// Limits that fit WebGL2 and GLES 3 devices. The G-buffer is packed into
+    /// smaller formats to stay within them.
+    Compatibility,
+}
+
+/// Picks one of the adapters listed by [`enumerate_adapters`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AdapterSelector {
+    /// The adapter at this position in the list.
+    Index(usize),
+    /// The first adapter with a name containing this, ignoring case.
+    Name(String),
+}
+
+impl AdapterSelector {
+    fn matches(&self, index: usize, info: &wgpu::AdapterInfo) -> bool {
+        match self {
+            AdapterSelector::Index(i) => *i == index,
+            AdapterSelector::Name(name) => info.name.to_lowercase().contains(&name.to_lowercase()),
+        }
+    }
+}
+
+impl std::str::FromStr for AdapterSelector {
+    type Err = std::convert::Infallible;
+
+    /// A number selects by index, anything else by name.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s.parse() {
+            Ok(index) => AdapterSelector::Index(index),
+            Err(_) => AdapterSelector::Name(s.to_string()),
+        })
+    }
+}
+
+/// Describe every adapter available on `backends`, in the order that
+/// [`AdapterSelector::Index`] refers to them.
+pub fn enumerate_adapters(backends: wgpu::Backends) -> Vec<wgpu::AdapterInfo> {
+    wgpu::Instance::new(wgpu::InstanceDescriptor {
+        backends,
+        ..Default::default()
+    })
+    .enumerate_adapters(backends)
+    .iter()
+    .map(wgpu::Adapter::get_info)
+    .collect()
+}
+
+/// A window's surface, on a device that can be shared with the renderers of
+/// other windows, see [Renderer::for_window].
+pub struct Renderer {
+    pub device: Arc<wgpu::Device>,
+    pub queue: Arc<wgpu::Queue>,
+    /// `None` for headless renderers, which only render offscreen.
+    pub surface: Option<wgpu::Surface<'static>>,
+    /// Describes the frames rendered by the app, even without a surface.
+    pub surface_config: wgpu::SurfaceConfiguration,
+    /// The profile the device was created with, never [`LimitsProfile::Auto`].
+    pub limits_profile: LimitsProfile,
+    /// The format of the scene depth buffer, supported by the adapter.
+    pub depth_format: wgpu::TextureFormat,
+    /// How the scene depth buffer is cleared and tested.
+    pub depth_convention: DepthConvention,
+    /// The space of the normals in the G-buffer.
+    pub normal_space: NormalSpace,
+    /// What the adapter supports beyond the WebGPU baseline, e.g. whether
+    /// depth textures can be copied to buffers.
+    pub downlevel_flags: wgpu::DownlevelFlags,
+    /// Counts the memory of the meshes, textures and upload buffers created
+    /// through the crate's helpers.
+    pub allocations: AllocationTracker,
+    /// Kept to create the surface again when the app resumes, and the surfaces
+    /// of other windows.
+    instance: Arc<wgpu::Instance>,
+    /// Kept to check what the surfaces of other windows support.
+    adapter: Arc<wgpu::Adapter>,
+    /// What headless renderers render frames into, the size of
+    /// `surface_config`.
+    headless_target: Option<Texture>,
+}
+
+impl Renderer {
+    /// The bytes of GPU memory held by meshes, textures and upload buffers
+    /// that are still alive. An estimate, see [AllocationTracker].
+    pub fn allocated_bytes(&self) -> u64 {
+        self.allocations.allocated_bytes()
+    }
+
+    /// Drop the surface when the app is suspended. On Android the window loses
+    /// its native window until the app resumes, so the surface can't be used.
+    /// Everything else stays on the device, see [Renderer::resume].
+    pub fn suspend(&mut self) {
+        self.surface = None;
+    }
+
+    /// Create the surface for `window` again after [Renderer::suspend], with
+    /// the same configuration at the current size of the window.
+    pub fn resume(&mut self, window: Arc<winit::window::Window>) {
+        let PhysicalSize { width, height } = window.inner_size();
+        let surface = self
+            .instance
+            .create_surface(window)
+            .expect("create surface");
+
+        self.surface = Some(surface);
+        self.resize(width, height);
+    }
+
+    /// A renderer for another window, on the same device. Everything created
+    /// on the device, like meshes, textures and pipelines, can be used by
+    /// both. The surface uses the same format if it supports it.
+    pub fn for_window(&self, window: Arc<winit::window::Window>) -> Renderer {
+        let PhysicalSize { width, height } = window.inner_size();
+        let surface = self
+            .instance
+            .create_surface(window)
+            .expect("create surface");
+
+        let formats = surface.get_capabilities(&self.adapter).formats;
+        let mut surface_config = self.surface_config.clone();
+        if !formats.contains(&surface_config.format) {
+            surface_config.format = formats[0];
+        }
+        surface_config.width = width;
+        surface_config.height = height;
+        surface.configure(&self.device, &surface_config);
+
+        Renderer {
+            device: Arc::clone(&self.device),
+            queue: Arc::clone(&self.queue),
+            surface: Some(surface),
+            surface_config,
+            limits_profile: self.limits_profile,
+            depth_format: self.depth_format,
+            depth_convention: self.depth_convention,
+            normal_space: self.normal_space,
+            downlevel_flags: self.downlevel_flags,
+            allocations: self.allocations.clone(),
+            instance: Arc::clone(&self.instance),
+            adapter: Arc::clone(&self.adapter),
+            headless_target: None,
+        }
+    }
+
+    /// Change the size of the frames, after the window is resized. A
+    /// minimized window has no size, the surface is left as it is until it
+    /// gets one again.
+    pub fn resize(&mut self, width: u32, height: u32) {
+        if width == 0 || height == 0 {
+            return;
+        }
+
+        self.surface_config.width = width;
+        self.surface_config.height = height;
+        if let Some(surface) = &self.surface {
+            surface.configure(&self.device, &self.surface_config);
+        }
+        if self.headless_target.is_some() {
+            self.headless_target = Some(self.create_headless_target());
+        }
+    }
+
+    fn create_headless_target(&self) -> Texture {
+        create_fullscreen_texture(
+            self,
+            self.surface_config.width,
+            self.surface_config.height,
+            self.surface_config.format,
+            wgpu::FilterMode::Linear,
+            "headless frame texture",
+        )
+    }
+
+    /// Start capturing validation errors. Must be paired with a call to
+    /// [`Renderer::pop_error_scope`].
+    pub fn push_error_scope(&self) {
+        self.device.push_error_scope(wgpu::ErrorFilter::Validation);
+    }
+
+    /// Stop capturing validation errors and log the error, if any, tagged with
+    /// the subsystem that caused it. Returns `true` if there was no error.
+    pub fn pop_error_scope(&self, subsystem: &str) -> bool {
+        match pollster::block_on(self.device.pop_error_scope()) {
+            Some(err) => {
+                eprintln!("wgpu validation error in {subsystem}: {err}");
+                false
+            }
+            None => true,
+        }
+    }
+
+    /// Get the texture to render the next frame into. Headless renderers get
+    /// an offscreen texture, so they run the same passes.
+    ///
+    /// Returns `None` if the frame should be skipped: while suspended, when
+    /// the surface timed out, or when it was lost or outdated, e.g. after a
+    /// resize or while minimized. The surface is configured again in the
+    /// last case, so the next frame can use it.
+    pub fn begin_frame(&self) -> Option<Frame> {
+        let surface = match (&self.surface, &self.headless_target) {
+            (Some(surface), _) => surface,
+            (None, Some(target)) => {
+                return Some(Frame {
+                    surface_texture: None,
+                    view: target
+                        .texture
+                        .create_view(&wgpu::TextureViewDescriptor::default()),
+                });
+            }
+            (None, None) => return None,
+        };
+
+        let surface_texture = match surface.get_current_texture() {
+            Ok(surface_texture) => surface_texture,
+            Err(wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated) => {
+                surface.configure(&self.device, &self.surface_config);
+                return None;
+            }
+            Err(wgpu::SurfaceError::Timeout) => return None,
+            Err(err @ wgpu::SurfaceError::OutOfMemory) => panic!("get current texture: {err}"),
+        };
+        let view = surface_texture
+            .texture
+            .create_view(&wgpu::TextureViewDescriptor::default());
+        Some(Frame {
+            surface_texture: Some(surface_texture),
+            view,
+        })
+    }
+}
+
+/// A frame being rendered, from [Renderer::begin_frame]. The surface texture
+/// is presented when the frame is dropped, so returning early can't leave it
+/// acquired without being presented. Use [Frame::discard] to drop a frame
+/// without showing it.
+pub struct Frame {
+    /// `None` for headless renderers, or once presented or discarded.
+    surface_texture: Option<wgpu::SurfaceTexture>,
+    pub view: wgpu::TextureView,
+}
+
+impl Frame {
+    /// Show the frame on the surface.
+    pub fn present(mut self) {
+        if let Some(surface_texture) = self.surface_texture.take() {
+            surface_texture.present();
+        }
+    }
+
+    /// Drop the frame without showing it.
+    #[allow(dead_code)]
+    pub fn discard(mut self) {
+        // Dropping a surface texture without presenting it discards it.
+        self.surface_texture.take();
+    }
+}
+
+impl Drop for Frame {
+    fn drop(&mut self) {
+        if let Some(surface_texture) = self.surface_texture.take() {
+            surface_texture.present();
+        }
+    }
+}
+
+/// Options used to create the [`Renderer`] for a window.
+pub struct RendererBuilder {
+    backends: wgpu::Backends,
+    power_preference: wgpu::PowerPreference,
+    present_mode: wgpu::PresentMode,
+    required_features: wgpu::Features,
+    required_limits: wgpu::Limits,
+    /// Replaces `required_limits` when it resolves to
+    /// [`LimitsProfile::Compatibility`].
+    limits_profile: LimitsProfile,
+    /// The surface format to use if the surface supports it, otherwise the
+    /// first sRGB format is used.
+    preferred_format: Option<wgpu::TextureFormat>,
+    /// Ask for a software adapter, so the renderer runs without a GPU.
+    force_fallback_adapter: bool,
+    /// Use this adapter instead of letting wgpu pick one from the power
+    /// preference.
+    adapter: Option<AdapterSelector>,
+    /// The depth format to use if the adapter supports it, otherwise
+    /// [`DEFAULT_DEPTH_FORMAT`] is used.
+    depth_format: wgpu::TextureFormat,
+    normal_space: NormalSpace,
+}
+
+impl Default for RendererBuilder {
+    fn default() -> Self {
+        Self {
+            backends: wgpu::Backends::PRIMARY,
+            power_preference: wgpu::PowerPreference::default(),
+            present_mode: wgpu::PresentMode::AutoVsync,
+            required_features: wgpu::Features::empty(),
+            required_limits: wgpu::Limits::default(),
+            limits_profile: LimitsProfile::Auto,
+            preferred_format: None,
+            force_fallback_adapter: false,
+            adapter: None,
+            depth_format: DEFAULT_DEPTH_FORMAT,
+            normal_space: NormalSpace::World,
+        }
+    }
+}
+
+impl RendererBuilder {
+    pub fn with_backends(mut self, backends: wgpu::Backends) -> Self {
+        self.backends = backends;
+        self
+    }
+
+    pub fn with_power_preference(mut self, power_preference: wgpu::PowerPreference) -> Self {
+        self.power_preference = power_preference;
+        self
+    }
+
+    pub fn with_present_mode(mut self, present_mode: wgpu::PresentMode) -> Self {
+        self.present_mode = present_mode;
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn with_required_features(mut self, required_features: wgpu::Features) -> Self {
+        self.required_features = required_features;
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn with_required_limits(mut self, required_limits: wgpu::Limits) -> Self {
+        self.required_limits = required_limits;
+        self
+    }
+
+    pub fn with_limits_profile(mut self, limits_profile: LimitsProfile) -> Self {
+        self.limits_profile = limits_profile;
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn with_preferred_format(mut self, format: wgpu::TextureFormat) -> Self {
+        self.preferred_format = Some(format);
+        self
+    }
+
+    /// Use a software adapter, so tests can render without a GPU.
+    #[cfg(test)]
+    pub fn with_force_fallback_adapter(mut self, force_fallback_adapter: bool) -> Self {
+        self.force_fallback_adapter = force_fallback_adapter;
+        self
+    }
+
+    /// Use the adapter picked by `adapter`. Falls back to the power preference
+    /// if no adapter matches, or the one that matches can't present to the
+    /// window.
+    pub fn with_adapter(mut self, adapter: Option<AdapterSelector>) -> Self {
+        self.adapter = adapter;
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn with_depth_format(mut self, depth_format: wgpu::TextureFormat) -> Self {
+        self.depth_format = depth_format;
+        self
+    }
+
+    /// Store view space normals in the G-buffer instead of world space ones.
+    pub fn with_normal_space(mut self, normal_space: NormalSpace) -> Self {
+        self.normal_space = normal_space;
+        self
+    }
+
+    pub fn build(self, window: Arc<winit::window::Window>) -> Renderer {
+        let PhysicalSize { width, height } = window.inner_size();
+
+        let instance = self.create_instance();
+
+        let surface = instance.create_surface(window).expect("create surface");
+
+        let (adapter, device, queue, limits_profile) = self
+            .request_device(&instance, Some(&surface))
+            .expect("request adapter and device");
+
+        let surface_caps = surface.get_capabilities(&adapter);
+
+        // Use the preferred format if it is supported, otherwise find a sRGB
+        // surface format or use the first.
+        let format = self
+            .preferred_format
+            .filter(|format| surface_caps.formats.contains(format))
+            .or_else(|| {
+                surface_caps
+                    .formats
+                    .iter()
+                    .find(|cap| cap.is_srgb())
+                    .copied()
+            })
+            .unwrap_or(surface_caps.formats[0]);
+
+        let mut surface_config = surface
+            .get_default_config(&adapter, width, height)
+            .expect("surface get default configuration");
+        surface_config.format = format;
+        surface_config.present_mode = self.present_mode;
+
+        surface.configure(&device, &surface_config);
+
+        let depth_format = self.resolve_depth_format(&adapter);
+
+        Renderer {
+            device: Arc::new(device),
+            queue: Arc::new(queue),
+            surface: Some(surface),
+            surface_config,
+            limits_profile,
+            depth_format,
+            depth_convention: DepthConvention::default(),
+            normal_space: self.normal_space,
+            downlevel_flags: adapter.get_downlevel_capabilities().flags,
+            allocations: AllocationTracker::default(),
+            instance: Arc::new(instance),
+            adapter: Arc::new(adapter),
+            headless_target: None,
+        }
+    }
+
+    /// Create a renderer without a window that renders frames of `width` by
+    /// `height` offscreen. Returns `None` if there is no suitable adapter.
+    #[allow(dead_code)]
+    pub fn build_headless(self, width: u32, height: u32) -> Option<Renderer> {
+        let instance = self.create_instance();
+
+        let (adapter, device, queue, limits_profile) = self.request_device(&instance, None)?;
+
+        let surface_config = wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            format: self
+                .preferred_format
+                .unwrap_or(wgpu::TextureFormat::Rgba8UnormSrgb),
+            width,
+            height,
+            present_mode: self.present_mode,
+            desired_maximum_frame_latency: 2,
+            alpha_mode: wgpu::CompositeAlphaMode::Auto,
+            view_formats: vec![],
+        };
+
+        let mut renderer = Renderer {
+            device: Arc::new(device),
+            queue: Arc::new(queue),
+            surface: None,
+            surface_config,
+            limits_profile,
+            depth_format: self.resolve_depth_format(&adapter),
+            depth_convention: DepthConvention::default(),
+            normal_space: self.normal_space,
+            downlevel_flags: adapter.get_downlevel_capabilities().flags,
+            allocations: AllocationTracker::default(),
+            instance: Arc::new(instance),
+            adapter: Arc::new(adapter),
+            headless_target: None,
+        };
+        renderer.headless_target = Some(renderer.create_headless_target());
+        Some(renderer)
+    }
+
+    fn create_instance(&self) -> wgpu::Instance {
+        wgpu::Instance::new(wgpu::InstanceDescriptor {
+            backends: self.backends,
+            ..Default::default()
+        })
+    }
+
+    /// The requested depth format if the device can render to it and sample
+    /// it, otherwise [`DEFAULT_DEPTH_FORMAT`].
+    fn resolve_depth_format(&self, adapter: &wgpu::Adapter) -> wgpu::TextureFormat {
+        let format = self.depth_format;
+        let usages = wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING;
+
+        let supported = format.is_depth_stencil_format()
+            && self.required_features.contains(format.required_features())
+            && adapter
+                .get_texture_format_features(format)
+                .allowed_usages
+                .contains(usages);
+
+        if supported {
+            format
+        } else {
+            eprintln!("Depth format {format:?} is not supported, using {DEFAULT_DEPTH_FORMAT:?}");
+            DEFAULT_DEPTH_FORMAT
+        }
+    }
+
+    /// The adapter picked by [`RendererBuilder::with_adapter`], if it was set
+    /// and matches a usable adapter.
+    fn select_adapter(
+        &self,
+        instance: &wgpu::Instance,
+        surface: Option<&wgpu::Surface>,
+    ) -> Option<wgpu::Adapter> {
+        let selector = self.adapter.as_ref()?;
+
+        let adapter = instance
+            .enumerate_adapters(self.backends)
+            .into_iter()
+            .enumerate()
+            .find(|(index, adapter)| selector.matches(*index, &adapter.get_info()))
+            .map(|(_, adapter)| adapter);
+
+        match adapter {
+            Some(adapter)
+                if surface.is_none_or(|surface| adapter.is_surface_supported(surface)) =>
+            {
+                Some(adapter)
+            }
+            Some(adapter) => {
+                eprintln!(
+                    "Adapter {} can't present to the window, using the default",
+                    adapter.get_info().name
+                );
+                None
+            }
+            None => {
+                eprintln!("No adapter matches {selector:?}, using the default");
+                None
+            }
+        }
+    }
+
+    /// Find an adapter, compatible with `surface` if there is one, and create
+    /// the device. Also returns the resolved limits profile.
+    fn request_device(
+        &self,
+        instance: &wgpu::Instance,
+        surface: Option<&wgpu::Surface>,
+    ) -> Option<(wgpu::Adapter, wgpu::Device, wgpu::Queue, LimitsProfile)> {
+        let adapter = match self.select_adapter(instance, surface) {
+            Some(adapter) => adapter,
+            None => pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference: self.power_preference,
+                compatible_surface: surface,
+                force_fallback_adapter: self.force_fallback_adapter,
+            }))?,
+        };
+
+        let adapter_info = adapter.get_info();
+        println!(
+            "Using adapter: {} ({:?}, {:?})",
+            adapter_info.name, adapter_info.device_type, adapter_info.backend
+        );
+
+        let limits_profile = match self.limits_profile {
+            LimitsProfile::Auto => {
+                if adapter.get_downlevel_capabilities().is_webgpu_compliant() {
+                    LimitsProfile::Full
+                } else {
+                    LimitsProfile::Compatibility
+                }
+            }
+            limits_profile => limits_profile,
+        };
+        println!("Using {limits_profile:?} limits profile");
+
+        let required_limits = match limits_profile {
+            LimitsProfile::Compatibility => {
+                wgpu::Limits::downlevel_webgl2_defaults().using_resolution(adapter.limits())
+            }
+            _ => self.required_limits.clone(),
+        };
+
+        // Compressed textures can be loaded whenever the adapter supports
+        // them, without every app having to ask.
+        let required_features = self.required_features
+            | (adapter.features()
+                & (wgpu::Features::TEXTURE_COMPRESSION_BC
+                    | wgpu::Features::TEXTURE_COMPRESSION_ETC2));
+
+        let (device, queue) = pollster::block_on(adapter.request_device(
+            &wgpu::DeviceDescriptor {
+                required_features,
+                required_limits,
+                ..Default::default()
+            },
+            None,
+        ))
+        .map_err(|err| eprintln!("Could not create device: {err}"))
+        .ok()?;
+
+        Some((adapter, device, queue, limits_profile))
+    }
+}
@@ -0,0 +1,200 @@
+use crate::{
+    lights::PointLight,
+    mesh::{Mesh, Vertex},
+};
+
+/// The scenes built into the app, to show off the renderer without loading
+/// any files.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Scene {
+    /// A metal cube next to two glass spheres, with a decal on the cube.
+    #[default]
+    Showcase,
+    /// A single metal cube.
+    Cube,
+    /// A sphere resting on the ground plane.
+    SphereAndPlane,
+    /// A few objects lit by several colored lights.
+    MultiLight,
+}
+
+/// How an object in a scene is shaded.
+#[derive(Clone, Copy, Debug)]
+pub enum SceneMaterial {
+    /// The bundled metal textures.
+    Metal,
+    /// A light blue, see through material, drawn in the transparent pass.
+    Glass,
+    /// A single sRGB color.
+    Matte([u8; 3]),
+}
+
+pub struct SceneObject {
+    pub mesh: Mesh<Vertex>,
+    pub material: SceneMaterial,
+    /// The simplification ratios of the lower levels of detail. Empty for
+    /// meshes that are too simple to need them.
+    pub lod_ratios: Vec<f32>,
+}
+
+/// Everything needed to set up a [Scene].
+pub struct SceneDescription {
+    pub objects: Vec<SceneObject>,
+    /// The first light is the one controlled by the light sliders.
+    pub lights: Vec<PointLight>,
+    /// Where the ring decal is projected, as the transforms of unit boxes.
+    pub decals: Vec<cgmath::Matrix4<f32>>,
+    pub yaw: cgmath::Deg<f32>,
+    pub pitch: cgmath::Deg<f32>,
+    pub distance: f32,
+}
+
+impl Scene {
+    /// The scene after this one, wrapping around after the last.
+    pub fn next(self) -> Self {
+        match self {
+            Scene::Showcase => Scene::Cube,
+            Scene::Cube => Scene::SphereAndPlane,
+            Scene::SphereAndPlane => Scene::MultiLight,
+            Scene::MultiLight => Scene::Showcase,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Scene::Showcase => "showcase",
+            Scene::Cube => "cube",
+            Scene::SphereAndPlane => "sphere and plane",
+            Scene::MultiLight => "multiple lights",
+        }
+    }
+
+    pub fn describe(self) -> SceneDescription {
+        match self {
+            Scene::Showcase => SceneDescription {
+                objects: std::iter::once(metal_cube())
+                    .chain(
+                        [[2.5, 0.0, 0.0], [0.0, 0.0, 2.5]].map(|offset| SceneObject {
+                            mesh: sphere(offset.into(), 1.0),
+                            material: SceneMaterial::Glass,
+                            lod_ratios: vec![0.25, 0.05],
+                        }),
+                    )
+                    .collect(),
+                lights: vec![key_light([0.5, 0.5, 0.5], 0.5)],
+                // A ring projected onto the top of the cube.
+                decals: vec![
+                    cgmath::Matrix4::from_translation(cgmath::vec3(0.0, 1.0, 0.0))
+                        * cgmath::Matrix4::from_nonuniform_scale(1.5, 0.5, 1.5),
+                ],
+                yaw: cgmath::Deg(90.0),
+                pitch: cgmath::Deg(0.0),
+                distance: 10.0,
+            },
+
+            Scene::Cube => SceneDescription {
+                objects: vec![metal_cube()],
+                lights: vec![key_light([2.0, 3.0, 2.0], 2.0)],
+                decals: vec![],
+                yaw: cgmath::Deg(45.0),
+                pitch: cgmath::Deg(20.0),
+                distance: 6.0,
+            },
+
+            Scene::SphereAndPlane => SceneDescription {
+                // The ground plane is put under the lowest object, so it
+                // touches the bottom of the sphere.
+                objects: vec![SceneObject {
+                    mesh: sphere(cgmath::vec3(0.0, 0.0, 0.0), 2.0),
+                    material: SceneMaterial::Matte([200, 80, 40]),
+                    lod_ratios: vec![0.25, 0.05],
+                }],
+                lights: vec![key_light([-2.0, 3.0, 1.0], 2.0)],
+                decals: vec![],
+                yaw: cgmath::Deg(60.0),
+                pitch: cgmath::Deg(25.0),
+                distance: 7.0,
+            },
+
+            Scene::MultiLight => SceneDescription {
+                objects: vec![
+                    metal_cube(),
+                    SceneObject {
+                        mesh: sphere(cgmath::vec3(-2.5, -0.5, 0.0), 1.0),
+                        material: SceneMaterial::Matte([230, 230, 230]),
+                        lod_ratios: vec![0.25, 0.05],
+                    },
+                    SceneObject {
+                        mesh: sphere(cgmath::vec3(2.5, -0.5, 0.0), 1.0),
+                        material: SceneMaterial::Matte([230, 230, 230]),
+                        lod_ratios: vec![0.25, 0.05],
+                    },
+                ],
+                lights: vec![
+                    key_light([0.0, 3.0, 0.0], 0.5),
+                    PointLight::default()
+                        .with_position([-3.0, 1.0, 2.0])
+                        .with_intensity(2.0)
+                        .with_color([1.0, 0.2, 0.2]),
+                    PointLight::default()
+                        .with_position([3.0, 1.0, 2.0])
+                        .with_intensity(2.0)
+                        .with_color([0.2, 1.0, 0.2]),
+                    PointLight::default()
+                        .with_position([0.0, 1.0, -3.0])
+                        .with_intensity(2.0)
+                        .with_color([0.2, 0.4, 1.0]),
+                ],
+                decals: vec![],
+                yaw: cgmath::Deg(90.0),
+                pitch: cgmath::Deg(30.0),
+                distance: 9.0,
+            },
+        }
+    }
+}
+
+/// A white, shadow casting light.
+fn key_light(position: [f32; 3], intensity: f32) -> PointLight {
+    PointLight::default()
+        .with_position(position)
+        .with_intensity(intensity)
+        .with_color([1.0, 1.0, 1.0])
+        .with_shininess(10.0)
+        .with_casts_shadows(true)
+}
+
+/// The bundled cube, 2 units wide, in the middle of the scene.
+fn metal_cube() -> SceneObject {
+    let mut mesh = load_obj(include_bytes!("../res/cube.obj"));
+    // Put the cube in the middle of the scene, wherever it was modeled.
+    mesh.apply_transform(cgmath::Matrix4::from_translation(
+        cgmath::Point3::new(0.0, 0.0, 0.0) - mesh.center(),
+    ));
+    mesh.update_tangents();
+
+    SceneObject {
+        mesh,
+        material: SceneMaterial::Metal,
+        lod_ratios: vec![],
+    }
+}
+
+/// The bundled icosphere, `diameter` wide, around `center`.
+fn sphere(center: cgmath::Vector3<f32>, diameter: f32) -> Mesh<Vertex> {
+    let mut mesh = load_obj(include_bytes!("../res/icosphere.obj"));
+    // The icosphere is modeled 1 unit wide around the origin.
+    mesh.apply_transform(
+        cgmath::Matrix4::from_translation(center) * cgmath::Matrix4::from_scale(diameter),
+    );
+    mesh.update_tangents();
+    mesh
+}
+
+fn load_obj(bytes: &[u8]) -> Mesh<Vertex> {
+    let reader = std::io::BufReader::new(std::io::Cursor::new(bytes));
+    match Mesh::<Vertex>::from_reader(reader) {
+        Ok(mesh) => mesh,
+        Err(err) => panic!("Error: {:?}", err),
+    }
+}
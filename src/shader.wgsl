@@ -12,12 +12,31 @@ struct Camera {
 @group(1) @binding(2) var t_normal: texture_2d<f32>;
 @group(1) @binding(3) var s_normal: sampler;
 
+struct Material {
+    opacity: f32,
+    alpha_cutoff: f32,
+    specular_color: vec3<f32>,
+    specular_strength: f32,
+}
+@group(1) @binding(4) var<uniform> material: Material;
+
+// Set when the normal target can't store negative values, so normals are
+// packed into the 0..1 range.
+override PACK_NORMALS: bool = false;
+// Set to store normals in view space instead of world space.
+override VIEW_SPACE_NORMALS: bool = false;
+// Set to give every triangle a single normal, ignoring the vertex normals and
+// the normal map.
+override FLAT_SHADING: bool = false;
+
 struct VertexInput {
     @location(0) position: vec3<f32>,
     @location(1) normal: vec3<f32>,
     @location(2) tex_coord: vec2<f32>,
     @location(3) tangent: vec3<f32>,
     @location(4) bitangent: vec3<f32>,
+    // The baked ambient occlusion, 1.0 where nothing is in the way.
+    @location(5) occlusion: f32,
 }
 
 struct VertexOutput {
@@ -25,6 +44,9 @@ struct VertexOutput {
     @location(0) tex_coord: vec2<f32>,
     @location(1) world_normal: vec3<f32>,
     @location(2) world_position: vec3<f32>,
+    @location(3) world_tangent: vec3<f32>,
+    @location(4) world_bitangent: vec3<f32>,
+    @location(5) occlusion: f32,
 }
 
 @vertex
@@ -34,24 +56,83 @@ fn vertex_main(
     let world_position = vec4(vertex.position, 1.0);
     let clip_position = camera.projection_matrix * camera.view_matrix * world_position;
 
-    return VertexOutput(clip_position, vertex.tex_coord, vertex.normal, world_position.xyz);
+    // Meshes are already in world space.
+    return VertexOutput(
+        clip_position,
+        vertex.tex_coord,
+        vertex.normal,
+        world_position.xyz,
+        vertex.tangent,
+        vertex.bitangent,
+        vertex.occlusion,
+    );
 }
 
 struct FragmentOutput {
+    // The alpha is the ambient occlusion, applied in the lighting pass.
     @location(0) albedo: vec4<f32>,
     @location(1) position: vec4<f32>,
+    // The alpha is the coverage, 1.0 wherever geometry was drawn. The target
+    // is cleared to 0.0.
     @location(2) normal: vec4<f32>,
+    @location(3) specular: vec4<f32>,
+}
+
+// The world space normal with the normal map applied. Vertices without
+// tangents, like meshes that were never passed through `update_tangents`,
+// keep their interpolated normal.
+fn perturbed_normal(vertex: VertexOutput) -> vec3<f32> {
+    // Sampled before branching, because sampling needs uniform control flow.
+    let tangent_normal = textureSample(t_normal, s_normal, vertex.tex_coord).xyz * 2.0 - 1.0;
+
+    let n = normalize(vertex.world_normal);
+    if dot(vertex.world_tangent, vertex.world_tangent) < 0.000001
+        || dot(vertex.world_bitangent, vertex.world_bitangent) < 0.000001 {
+        return n;
+    }
+
+    let t = normalize(vertex.world_tangent);
+    let b = normalize(vertex.world_bitangent);
+    let tbn = mat3x3<f32>(t, b, n);
+    return normalize(tbn * tangent_normal);
+}
+
+// The world space normal of the triangle, from the change in position to the
+// neighbouring pixels. It is turned to the same side as the vertex normals, so
+// it doesn't depend on the winding or on a mirrored view.
+fn face_normal(vertex: VertexOutput) -> vec3<f32> {
+    let n = normalize(cross(dpdx(vertex.world_position), dpdy(vertex.world_position)));
+    if dot(n, vertex.world_normal) < 0.0 {
+        return -n;
+    }
+    return n;
 }
 
 @fragment
 fn fragment_main(vertex: VertexOutput) -> FragmentOutput {
-    // let albedo: vec4<f32> = textureSample(t_albedo, s_albedo, vertex.tex_coord);
-    let albedo = vec4(0.1, 0.2, 0.3, 1.0);  // A solid color.
+    // Alpha test for cutout materials.
+    let coverage = textureSample(t_albedo, s_albedo, vertex.tex_coord).a;
+    if coverage < material.alpha_cutoff {
+        discard;
+    }
+
+    // let albedo = vec4(textureSample(t_albedo, s_albedo, vertex.tex_coord).rgb, vertex.occlusion);
+    let albedo = vec4(0.1, 0.2, 0.3, vertex.occlusion);  // A solid color.
 
     let position = vec4(vertex.world_position, 1.0);
 
-    // let normal = textureSample(t_normal, s_normal, vertex.tex_coord);
-    let normal = vec4(vertex.world_normal, 1.0);  // Flat normals.
+    var normal = vec4(perturbed_normal(vertex), 1.0);
+    if FLAT_SHADING {
+        normal = vec4(face_normal(vertex), 1.0);
+    }
+    if VIEW_SPACE_NORMALS {
+        normal = vec4(normalize((camera.view_matrix * vec4(normal.xyz, 0.0)).xyz), 1.0);
+    }
+    if PACK_NORMALS {
+        normal = vec4(normal.xyz * 0.5 + 0.5, 1.0);
+    }
+
+    let specular = vec4(material.specular_color, material.specular_strength);
 
-    return FragmentOutput(albedo, position, normal);
+    return FragmentOutput(albedo, position, normal, specular);
 }
@@ -0,0 +1,6 @@
+@group(0) @binding(0) var<uniform> view_projection: mat4x4<f32>;
+
+@vertex
+fn vertex_main(@location(0) position: vec3<f32>) -> @builtin(position) vec4<f32> {
+    return view_projection * vec4<f32>(position, 1.0);
+}
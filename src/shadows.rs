@@ -0,0 +1,186 @@
+use wgpu::util::DeviceExt;
+
+use crate::{
+    mesh::{GpuMesh, Vertex},
+    pass_builder::PassBuilder,
+    texture::{create_depth_texture, Texture},
+    Renderer,
+};
+
+/// Width and height of the shadow atlas in pixels.
+pub const SHADOW_ATLAS_SIZE: u32 = 2048;
+/// The atlas is split into a grid of this many tiles on each side, one tile
+/// per shadow casting light.
+const TILES_PER_SIDE: u32 = 2;
+const TILE_SIZE: u32 = SHADOW_ATLAS_SIZE / TILES_PER_SIDE;
+pub const MAX_SHADOW_CASTERS: usize = (TILES_PER_SIDE * TILES_PER_SIDE) as usize;
+/// The atlas keeps full precision, whatever the depth format of the renderer.
+const SHADOW_ATLAS_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+
+/// cgmath builds projections for OpenGL's -1..1 depth range, wgpu uses 0..1.
+#[rustfmt::skip]
+pub const OPENGL_TO_WGPU_MATRIX: cgmath::Matrix4<f32> = cgmath::Matrix4::new(
+    1.0, 0.0, 0.0, 0.0,
+    0.0, 1.0, 0.0, 0.0,
+    0.0, 0.0, 0.5, 0.0,
+    0.0, 0.0, 0.5, 1.0,
+);
+
+/// The region of the atlas used by the shadow caster at `index`, as
+/// `[x, y, width, height]` in texture coordinates.
+pub fn tile_rect(index: usize) -> Option<[f32; 4]> {
+    if index >= MAX_SHADOW_CASTERS {
+        return None;
+    }
+
+    let size = 1.0 / TILES_PER_SIDE as f32;
+    let x = (index as u32 % TILES_PER_SIDE) as f32 * size;
+    let y = (index as u32 / TILES_PER_SIDE) as f32 * size;
+    Some([x, y, size, size])
+}
+
+/// A single depth texture holding the shadow maps of all shadow casting
+/// lights, each in its own tile.
+pub struct ShadowAtlas {
+    pub texture: Texture,
+    pipeline: wgpu::RenderPipeline,
+    tiles: Vec<(wgpu::Buffer, wgpu::BindGroup)>,
+}
+
+impl ShadowAtlas {
+    pub fn new(renderer: &Renderer) -> Self {
+        let Renderer { device, .. } = renderer;
+
+        let texture = create_depth_texture(
+            renderer,
+            SHADOW_ATLAS_SIZE,
+            SHADOW_ATLAS_SIZE,
+            SHADOW_ATLAS_FORMAT,
+        );
+
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("shadow shader module"),
+            source: wgpu::ShaderSource::Wgsl(std::borrow::Cow::Borrowed(include_str!(
+                "shadow.wgsl"
+            ))),
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("shadow bind group layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+
+        let tiles = (0..MAX_SHADOW_CASTERS)
+            .map(|_| {
+                let view_projection: [[f32; 4]; 4] =
+                    <cgmath::Matrix4<f32> as cgmath::SquareMatrix>::identity().into();
+                let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some("shadow view projection buffer"),
+                    contents: bytemuck::cast_slice(&[view_projection]),
+                    usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                });
+                let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: Some("shadow bind group"),
+                    layout: &bind_group_layout,
+                    entries: &[wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: buffer.as_entire_binding(),
+                    }],
+                });
+                (buffer, bind_group)
+            })
+            .collect();
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("shadow pipeline layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("shadow render pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &module,
+                entry_point: "vertex_main",
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+                // Only the positions are needed for depth.
+                buffers: &[Vertex::layout_with(&Vertex::ATTRIBUTES[..1])],
+            },
+            // Equivalent to culling the back faces of counter-clockwise meshes.
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                front_face: wgpu::FrontFace::Cw,
+                cull_mode: Some(wgpu::Face::Front),
+                ..wgpu::PrimitiveState::default()
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: SHADOW_ATLAS_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: wgpu::CompareFunction::Less,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            fragment: None,
+            multiview: None,
+            cache: None,
+        });
+
+        Self {
+            texture,
+            pipeline,
+            tiles,
+        }
+    }
+
+    /// Render `meshes` into a tile for each of the `view_projections`, in the
+    /// same order as [tile_rect].
+    pub fn render(
+        &self,
+        renderer: &Renderer,
+        encoder: &mut wgpu::CommandEncoder,
+        view_projections: &[cgmath::Matrix4<f32>],
+        meshes: &[&GpuMesh],
+    ) {
+        let mut render_pass = PassBuilder::new("shadow render pass")
+            .with_depth(&self.texture.view, wgpu::LoadOp::Clear(1.0))
+            .begin(encoder);
+
+        render_pass.set_pipeline(&self.pipeline);
+
+        for (index, (view_projection, (buffer, bind_group))) in
+            view_projections.iter().zip(self.tiles.iter()).enumerate()
+        {
+            let view_projection: [[f32; 4]; 4] = (*view_projection).into();
+            renderer
+                .queue
+                .write_buffer(buffer, 0, bytemuck::cast_slice(&[view_projection]));
+
+            let x = index as u32 % TILES_PER_SIDE * TILE_SIZE;
+            let y = index as u32 / TILES_PER_SIDE * TILE_SIZE;
+            render_pass.set_viewport(
+                x as f32,
+                y as f32,
+                TILE_SIZE as f32,
+                TILE_SIZE as f32,
+                0.0,
+                1.0,
+            );
+            render_pass.set_bind_group(0, bind_group, &[]);
+
+            for mesh in meshes {
+                mesh.draw(&mut render_pass);
+            }
+        }
+    }
+}
@@ -1,23 +1,170 @@
-use crate::Renderer;
+use crate::{allocations::Allocation, Renderer};
 
 pub struct Texture {
     pub texture: wgpu::Texture,
     pub view: wgpu::TextureView,
     pub sampler: wgpu::Sampler,
+    /// Untracked when the texture is dropped.
+    _allocation: Allocation,
 }
 
 impl Texture {
+    /// Load a PNG as an RGBA8 texture. Set `srgb` for color maps like albedo,
+    /// which are authored in sRGB and decoded to linear when sampled, and
+    /// leave it off for data like normal maps.
+    ///
+    /// The reader doesn't have to be buffered or seekable, e.g. an entry in a
+    /// zip archive; it is read to the end before decoding.
+    #[allow(dead_code)]
     pub fn from_reader(
         renderer: &Renderer,
-        reader: impl std::io::BufRead + std::io::Seek,
-        format: wgpu::TextureFormat,
+        mut reader: impl std::io::Read,
+        srgb: bool,
     ) -> Result<Self, ()> {
-        let img = image::load(reader, image::ImageFormat::Png)
+        let mut bytes = Vec::new();
+        reader
+            .read_to_end(&mut bytes)
             .map_err(|err| println!("error: {err:?}"))?;
 
+        Self::from_bytes(renderer, &bytes, srgb)
+    }
+
+    /// Like [Texture::from_reader], for a PNG that is already in memory.
+    pub fn from_bytes(renderer: &Renderer, bytes: &[u8], srgb: bool) -> Result<Self, ()> {
+        let data = decode_png(bytes)?;
+        Ok(Self::from_image(renderer, &data, srgb))
+    }
+
+    /// Like [Texture::from_bytes], but the PNG is decoded on a new thread so
+    /// large images don't block the caller. Poll the returned
+    /// [TexturePending] to upload the texture once it is decoded.
+    #[allow(dead_code)]
+    pub fn from_bytes_in_background(bytes: Vec<u8>, srgb: bool) -> TexturePending {
+        let decoder = std::thread::Builder::new()
+            .name("texture decode".to_string())
+            .spawn(move || decode_png(&bytes))
+            .expect("spawn texture decode thread");
+
+        TexturePending {
+            decoder: Some(decoder),
+            srgb,
+        }
+    }
+
+    fn from_image(renderer: &Renderer, data: &image::RgbaImage, srgb: bool) -> Self {
+        let format = if srgb {
+            wgpu::TextureFormat::Rgba8UnormSrgb
+        } else {
+            wgpu::TextureFormat::Rgba8Unorm
+        };
+
+        Self::from_rgba8(renderer, data.width(), data.height(), data.as_ref(), format)
+    }
+
+    /// Create a 1x1 texture filled with a single color. Useful as a stand-in
+    /// for material maps that are not loaded from an image.
+    pub fn from_color(renderer: &Renderer, color: [u8; 4], format: wgpu::TextureFormat) -> Self {
+        Self::from_rgba8(renderer, 1, 1, &color, format)
+    }
+
+    /// Copy the first mip level of the texture back to the CPU, with tightly
+    /// packed rows. This blocks until the GPU has finished all submitted work,
+    /// so it is meant for debugging and captures, not for every frame.
+    pub fn read_pixels(&self, renderer: &Renderer) -> Vec<u8> {
+        self.read_region(renderer, 0, 0, self.texture.width(), self.texture.height())
+    }
+
+    /// Like [Texture::read_pixels], but only copies the `width` by `height`
+    /// rectangle starting at `x`, `y`.
+    pub fn read_region(
+        &self,
+        renderer: &Renderer,
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+    ) -> Vec<u8> {
+        let format = self.texture.format();
+        let aspect = if format.has_depth_aspect() {
+            wgpu::TextureAspect::DepthOnly
+        } else {
+            wgpu::TextureAspect::All
+        };
+        let bytes_per_pixel = format
+            .block_copy_size(Some(aspect))
+            .expect("texture format can be copied");
+
+        // Rows in the buffer has to be aligned.
+        let unpadded_bytes_per_row = width * bytes_per_pixel;
+        let padded_bytes_per_row = unpadded_bytes_per_row
+            .div_ceil(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT)
+            * wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+
+        let buffer = renderer.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("read pixels buffer"),
+            size: (padded_bytes_per_row * height) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = renderer
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("read pixels command encoder"),
+            });
+
+        encoder.copy_texture_to_buffer(
+            wgpu::ImageCopyTexture {
+                texture: &self.texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d { x, y, z: 0 },
+                aspect,
+            },
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded_bytes_per_row),
+                    rows_per_image: Some(height),
+                },
+            },
+            wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+        );
+
+        renderer.queue.submit(std::iter::once(encoder.finish()));
+
+        let slice = buffer.slice(..);
+        slice.map_async(wgpu::MapMode::Read, |result| {
+            result.expect("map read pixels buffer");
+        });
+        renderer.device.poll(wgpu::Maintain::Wait);
+
+        let data = slice.get_mapped_range();
+        let mut pixels = Vec::with_capacity((unpadded_bytes_per_row * height) as usize);
+        for row in data.chunks(padded_bytes_per_row as usize) {
+            pixels.extend_from_slice(&row[..unpadded_bytes_per_row as usize]);
+        }
+        drop(data);
+        buffer.unmap();
+
+        pixels
+    }
+
+    /// Create a texture from tightly packed RGBA8 pixels.
+    pub fn from_rgba8(
+        renderer: &Renderer,
+        width: u32,
+        height: u32,
+        data: &[u8],
+        format: wgpu::TextureFormat,
+    ) -> Self {
         let size = wgpu::Extent3d {
-            width: img.width(),
-            height: img.height(),
+            width,
+            height,
             depth_or_array_layers: 1,
         };
 
@@ -47,8 +194,6 @@ impl Texture {
             ..Default::default()
         });
 
-        let data = img.into_rgba8();
-
         renderer.queue.write_texture(
             wgpu::ImageCopyTexture {
                 texture: &texture,
@@ -57,7 +202,7 @@ impl Texture {
                 aspect: wgpu::TextureAspect::All,
             },
             // The actual pixel data
-            data.as_ref(),
+            data,
             // The layout of the texture
             wgpu::ImageDataLayout {
                 offset: 0,
@@ -67,7 +212,114 @@ impl Texture {
             size,
         );
 
+        Texture {
+            _allocation: renderer.allocations.track_texture(&texture),
+            texture,
+            view,
+            sampler,
+        }
+    }
+
+    /// Load a 2D texture from a KTX2 file, with all of its mip levels. The
+    /// data is uploaded as is, so block compressed formats (BC1, BC3, BC7 and
+    /// ETC2) stay compressed on the GPU. Fails if the device doesn't support
+    /// the format, or if the file is supercompressed, like Basis Universal
+    /// files, because those have to be transcoded first.
+    #[allow(dead_code)]
+    pub fn from_ktx2(renderer: &Renderer, bytes: &[u8]) -> Result<Self, ()> {
+        let reader = ktx2::Reader::new(bytes).map_err(|err| println!("error: {err}"))?;
+        let header = reader.header();
+
+        if let Some(scheme) = header.supercompression_scheme {
+            println!("error: supercompressed KTX2 files ({scheme:?}) are not supported");
+            return Err(());
+        }
+        if header.pixel_depth > 1 || header.layer_count > 1 || header.face_count > 1 {
+            println!("error: only 2D KTX2 textures are supported");
+            return Err(());
+        }
+
+        let Some(format) = header.format.and_then(ktx2_format) else {
+            println!("error: KTX2 format {:?} is not supported", header.format);
+            return Err(());
+        };
+        let required_features = format.required_features();
+        if !renderer.device.features().contains(required_features) {
+            println!(
+                "error: {format:?} needs {required_features:?}, which the device doesn't have"
+            );
+            return Err(());
+        }
+
+        let size = wgpu::Extent3d {
+            width: header.pixel_width,
+            height: header.pixel_height.max(1),
+            depth_or_array_layers: 1,
+        };
+        let mip_level_count = header.level_count.max(1);
+
+        let texture = renderer.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("ktx2 texture"),
+            size,
+            mip_level_count,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+
+        let (block_width, block_height) = format.block_dimensions();
+        let block_size = format
+            .block_copy_size(None)
+            .expect("color formats have a block size");
+        for (mip_level, level) in (0..mip_level_count).zip(reader.levels()) {
+            // Compressed mip levels are stored in whole blocks, even when the
+            // level is smaller than a block.
+            let mip_size = size
+                .mip_level_size(mip_level, wgpu::TextureDimension::D2)
+                .physical_size(format);
+            let bytes_per_row = mip_size.width / block_width * block_size;
+            let rows = mip_size.height / block_height;
+            if level.data.len() < (bytes_per_row * rows) as usize {
+                println!("error: KTX2 mip level {mip_level} is too short");
+                return Err(());
+            }
+
+            renderer.queue.write_texture(
+                wgpu::ImageCopyTexture {
+                    texture: &texture,
+                    mip_level,
+                    origin: wgpu::Origin3d::ZERO,
+                    aspect: wgpu::TextureAspect::All,
+                },
+                level.data,
+                wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(bytes_per_row),
+                    rows_per_image: Some(rows),
+                },
+                mip_size,
+            );
+        }
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let sampler = renderer.device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::Repeat,
+            address_mode_v: wgpu::AddressMode::Repeat,
+            address_mode_w: wgpu::AddressMode::Repeat,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Linear,
+            lod_min_clamp: 0.0,
+            lod_max_clamp: 100.0,
+            compare: None,
+            ..Default::default()
+        });
+
         Ok(Texture {
+            _allocation: renderer.allocations.track_texture(&texture),
             texture,
             view,
             sampler,
@@ -75,29 +327,169 @@ impl Texture {
     }
 }
 
-pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+/// The texture format for a KTX2 (Vulkan) format, if it is one that can be
+/// loaded.
+fn ktx2_format(format: ktx2::Format) -> Option<wgpu::TextureFormat> {
+    use wgpu::TextureFormat;
+
+    Some(match format {
+        ktx2::Format::R8G8B8A8_UNORM => TextureFormat::Rgba8Unorm,
+        ktx2::Format::R8G8B8A8_SRGB => TextureFormat::Rgba8UnormSrgb,
+        ktx2::Format::BC1_RGBA_UNORM_BLOCK => TextureFormat::Bc1RgbaUnorm,
+        ktx2::Format::BC1_RGBA_SRGB_BLOCK => TextureFormat::Bc1RgbaUnormSrgb,
+        ktx2::Format::BC3_UNORM_BLOCK => TextureFormat::Bc3RgbaUnorm,
+        ktx2::Format::BC3_SRGB_BLOCK => TextureFormat::Bc3RgbaUnormSrgb,
+        ktx2::Format::BC7_UNORM_BLOCK => TextureFormat::Bc7RgbaUnorm,
+        ktx2::Format::BC7_SRGB_BLOCK => TextureFormat::Bc7RgbaUnormSrgb,
+        ktx2::Format::ETC2_R8G8B8_UNORM_BLOCK => TextureFormat::Etc2Rgb8Unorm,
+        ktx2::Format::ETC2_R8G8B8_SRGB_BLOCK => TextureFormat::Etc2Rgb8UnormSrgb,
+        ktx2::Format::ETC2_R8G8B8A8_UNORM_BLOCK => TextureFormat::Etc2Rgba8Unorm,
+        ktx2::Format::ETC2_R8G8B8A8_SRGB_BLOCK => TextureFormat::Etc2Rgba8UnormSrgb,
+        _ => return None,
+    })
+}
+
+/// The depth format used when the renderer isn't asked for another one.
+pub const DEFAULT_DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+
+/// Name of the shader constant with the depth of the far plane, see
+/// [DepthConvention::shader_constants].
+pub const FAR_DEPTH_CONSTANT: &str = "FAR_DEPTH";
+
+/// Which end of the depth range is near in the scene depth buffer. Passes
+/// that clear, test against or read the scene depth get their values from
+/// here, so they can't disagree about it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DepthConvention {
+    /// Near is 0.0 and far is 1.0.
+    #[default]
+    Standard,
+    /// Near is 1.0 and far is 0.0, which spreads float precision more evenly
+    /// over the distance. Needs a projection that maps depth the same way.
+    #[allow(dead_code)]
+    Reversed,
+}
+
+impl DepthConvention {
+    /// The depth of the far plane, which the depth buffer is cleared to.
+    pub fn far(self) -> f32 {
+        match self {
+            DepthConvention::Standard => 1.0,
+            DepthConvention::Reversed => 0.0,
+        }
+    }
+
+    /// The depth test that passes fragments closer than the depth buffer.
+    pub fn closer(self) -> wgpu::CompareFunction {
+        match self {
+            DepthConvention::Standard => wgpu::CompareFunction::Less,
+            DepthConvention::Reversed => wgpu::CompareFunction::Greater,
+        }
+    }
+
+    /// Like [DepthConvention::closer], but also passes fragments at the same
+    /// depth, to draw over surfaces that are already in the depth buffer.
+    pub fn closer_or_equal(self) -> wgpu::CompareFunction {
+        match self {
+            DepthConvention::Standard => wgpu::CompareFunction::LessEqual,
+            DepthConvention::Reversed => wgpu::CompareFunction::GreaterEqual,
+        }
+    }
+
+    /// Whether depth `a` is closer to the camera than depth `b`, the test of
+    /// [DepthConvention::closer] on the CPU.
+    pub fn is_closer(self, a: f32, b: f32) -> bool {
+        match self {
+            DepthConvention::Standard => a < b,
+            DepthConvention::Reversed => a > b,
+        }
+    }
+
+    /// Whether `depth` is at the far plane, where nothing was drawn.
+    pub fn is_far(self, depth: f32) -> bool {
+        !self.is_closer(depth, self.far())
+    }
+
+    /// Pipeline constants for shaders that read the scene depth. They compare
+    /// against `FAR_DEPTH` to tell the background from surfaces.
+    pub fn shader_constants(self) -> std::collections::HashMap<String, f64> {
+        std::collections::HashMap::from([(FAR_DEPTH_CONSTANT.to_string(), self.far() as f64)])
+    }
+}
+
+/// A texture that is being decoded on another thread, created by
+/// [Texture::from_bytes_in_background].
+pub struct TexturePending {
+    /// Taken once the texture has been handed out.
+    decoder: Option<std::thread::JoinHandle<Result<image::RgbaImage, ()>>>,
+    srgb: bool,
+}
+
+impl TexturePending {
+    /// Upload the texture if it has been decoded. Returns `None` while it is
+    /// still decoding and after the result has been returned once.
+    #[allow(dead_code)]
+    pub fn poll(&mut self, renderer: &Renderer) -> Option<Result<Texture, ()>> {
+        if !self.decoder.as_ref()?.is_finished() {
+            return None;
+        }
+        self.wait(renderer)
+    }
+
+    /// Block until the texture is decoded, then upload it. Returns `None`
+    /// if the result has already been returned.
+    #[allow(dead_code)]
+    pub fn wait(&mut self, renderer: &Renderer) -> Option<Result<Texture, ()>> {
+        let decoder = self.decoder.take()?;
+        let data = decoder.join().unwrap_or_else(|_| {
+            println!("error: texture decode thread panicked");
+            Err(())
+        });
+        Some(data.map(|data| Texture::from_image(renderer, &data, self.srgb)))
+    }
+}
+
+fn decode_png(bytes: &[u8]) -> Result<image::RgbaImage, ()> {
+    let img = image::load_from_memory_with_format(bytes, image::ImageFormat::Png)
+        .map_err(|err| println!("error: {err:?}"))?;
+    Ok(img.into_rgba8())
+}
 
-pub fn create_depth_texture(device: &wgpu::Device, width: u32, height: u32) -> Texture {
+/// The view only covers the depth aspect, so it can be sampled even if
+/// `format` has a stencil aspect. The sampler is a comparison sampler for
+/// shadow lookups. To read the depth values themselves, bind the view as an
+/// unfilterable float texture and use `textureLoad` or [create_depth_sampler].
+pub fn create_depth_texture(
+    renderer: &Renderer,
+    width: u32,
+    height: u32,
+    format: wgpu::TextureFormat,
+) -> Texture {
     let size = wgpu::Extent3d {
         width,
         height,
         depth_or_array_layers: 1,
     };
 
-    let texture = device.create_texture(&wgpu::TextureDescriptor {
+    let texture = renderer.device.create_texture(&wgpu::TextureDescriptor {
         label: Some("depth texture"),
         size,
         mip_level_count: 1,
         sample_count: 1,
         dimension: wgpu::TextureDimension::D2,
-        format: DEPTH_FORMAT,
-        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+        format,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+            | wgpu::TextureUsages::TEXTURE_BINDING
+            | wgpu::TextureUsages::COPY_SRC,
         view_formats: &[],
     });
 
-    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+    let view = texture.create_view(&wgpu::TextureViewDescriptor {
+        aspect: wgpu::TextureAspect::DepthOnly,
+        ..Default::default()
+    });
 
-    let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+    let sampler = renderer.device.create_sampler(&wgpu::SamplerDescriptor {
         address_mode_u: wgpu::AddressMode::ClampToEdge,
         address_mode_v: wgpu::AddressMode::ClampToEdge,
         address_mode_w: wgpu::AddressMode::ClampToEdge,
@@ -111,43 +503,70 @@ pub fn create_depth_texture(device: &wgpu::Device, width: u32, height: u32) -> T
     });
 
     Texture {
+        _allocation: renderer.allocations.track_texture(&texture),
         texture,
         view,
         sampler,
     }
 }
 
+/// A sampler for reading depth values as they are, for textures made by
+/// [create_depth_texture]. Their own sampler compares against a reference
+/// depth, which is what shadow lookups need but not what effects that want the
+/// depth itself need. Depth can't be filtered, so this uses nearest filtering.
+pub fn create_depth_sampler(renderer: &Renderer) -> wgpu::Sampler {
+    renderer.device.create_sampler(&wgpu::SamplerDescriptor {
+        label: Some("depth sampler"),
+        address_mode_u: wgpu::AddressMode::ClampToEdge,
+        address_mode_v: wgpu::AddressMode::ClampToEdge,
+        address_mode_w: wgpu::AddressMode::ClampToEdge,
+        mag_filter: wgpu::FilterMode::Nearest,
+        min_filter: wgpu::FilterMode::Nearest,
+        mipmap_filter: wgpu::FilterMode::Nearest,
+        compare: None,
+        ..Default::default()
+    })
+}
+
+/// `filter` is used by the sampler for both magnification and minification.
+/// Targets that hold data, rather than colors, should use
+/// [wgpu::FilterMode::Nearest] so neighbouring texels aren't blended.
 pub fn create_fullscreen_texture(
-    device: &wgpu::Device,
-    surface_config: &wgpu::SurfaceConfiguration,
+    renderer: &Renderer,
+    width: u32,
+    height: u32,
     format: wgpu::TextureFormat,
+    filter: wgpu::FilterMode,
     label: &str,
 ) -> Texture {
     let size = wgpu::Extent3d {
-        width: surface_config.width,
-        height: surface_config.height,
+        width,
+        height,
         depth_or_array_layers: 1,
     };
 
-    let texture = device.create_texture(&wgpu::TextureDescriptor {
+    let texture = renderer.device.create_texture(&wgpu::TextureDescriptor {
         label: Some(label),
         size,
         mip_level_count: 1,
         sample_count: 1,
         dimension: wgpu::TextureDimension::D2,
         format,
-        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+            | wgpu::TextureUsages::TEXTURE_BINDING
+            | wgpu::TextureUsages::COPY_SRC
+            | wgpu::TextureUsages::COPY_DST,
         view_formats: &[],
     });
 
     let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
 
-    let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+    let sampler = renderer.device.create_sampler(&wgpu::SamplerDescriptor {
         address_mode_u: wgpu::AddressMode::ClampToEdge,
         address_mode_v: wgpu::AddressMode::ClampToEdge,
         address_mode_w: wgpu::AddressMode::ClampToEdge,
-        mag_filter: wgpu::FilterMode::Linear,
-        min_filter: wgpu::FilterMode::Linear,
+        mag_filter: filter,
+        min_filter: filter,
         mipmap_filter: wgpu::FilterMode::Nearest,
         lod_min_clamp: 0.0,
         lod_max_clamp: 100.0,
@@ -156,6 +575,7 @@ pub fn create_fullscreen_texture(
     });
 
     Texture {
+        _allocation: renderer.allocations.track_texture(&texture),
         texture,
         view,
         sampler,
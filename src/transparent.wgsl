@@ -0,0 +1,166 @@
+struct Camera {
+    projection_matrix: mat4x4<f32>,
+    view_matrix: mat4x4<f32>,
+    position: vec3<f32>,
+}
+
+@group(0) @binding(0) var<uniform> camera: Camera;
+
+struct Material {
+    opacity: f32,
+    alpha_cutoff: f32,
+    specular_color: vec3<f32>,
+    specular_strength: f32,
+}
+
+@group(1) @binding(0) var t_albedo: texture_2d<f32>;
+@group(1) @binding(1) var s_albedo: sampler;
+@group(1) @binding(2) var t_normal: texture_2d<f32>;
+@group(1) @binding(3) var s_normal: sampler;
+@group(1) @binding(4) var<uniform> material: Material;
+
+struct Shadow {
+    view_projection: mat4x4<f32>,
+    // Empty for lights that don't cast shadows.
+    atlas_rect: vec4<f32>,
+    // Near and far planes, and 1.0 for perspective projections.
+    depth_range: vec4<f32>,
+}
+
+struct PointLight {
+    position: vec3<f32>,
+    intensity: f32,
+    color: vec3<f32>,
+    shininess: f32,
+}
+
+struct DirectionalLight {
+    direction: vec3<f32>,
+    intensity: f32,
+    color: vec3<f32>,
+    shadow: Shadow,
+}
+
+struct ShadowSettings {
+    filter_mode: u32,
+    softness: f32,
+    constant_bias: f32,
+    slope_bias: f32,
+}
+
+const MAX_POINT_LIGHTS: u32 = 320u;
+const MAX_SHADOWED_POINT_LIGHTS: u32 = 4u;
+
+struct Lights {
+    point_lights: array<PointLight, MAX_POINT_LIGHTS>,
+    // The shadows of the first point lights, the others don't cast any.
+    point_light_shadows: array<Shadow, MAX_SHADOWED_POINT_LIGHTS>,
+    directional_light: DirectionalLight,
+    sky_color: vec4<f32>,
+    ambient_color: vec4<f32>,
+    shadow_settings: ShadowSettings,
+    // Uniform buffers need members after a struct to start on 16 bytes.
+    @align(16) point_light_count: u32,
+}
+@group(2) @binding(0) var<uniform> lights: Lights;
+
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+    @location(1) normal: vec3<f32>,
+    @location(2) tex_coord: vec2<f32>,
+    @location(3) tangent: vec3<f32>,
+    @location(4) bitangent: vec3<f32>,
+    // The baked ambient occlusion, 1.0 where nothing is in the way.
+    @location(5) occlusion: f32,
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) tex_coord: vec2<f32>,
+    @location(1) world_normal: vec3<f32>,
+    @location(2) world_position: vec3<f32>,
+    @location(3) occlusion: f32,
+}
+
+@vertex
+fn vertex_main(
+    vertex: VertexInput,
+) -> VertexOutput {
+    let world_position = vec4(vertex.position, 1.0);
+    let clip_position = camera.projection_matrix * camera.view_matrix * world_position;
+
+    return VertexOutput(
+        clip_position,
+        vertex.tex_coord,
+        vertex.normal,
+        world_position.xyz,
+        vertex.occlusion,
+    );
+}
+
+fn diffuse(
+    intensity: f32,
+    color: vec3<f32>,
+    direction_to_light: vec3<f32>,
+    surface_normal: vec3<f32>,
+) -> vec3<f32> {
+    let radiance = dot(direction_to_light, surface_normal);
+    return color * intensity * max(radiance, 0.0);
+}
+
+fn specular(
+    intensity: f32,
+    color: vec3<f32>,
+    direction_to_camera: vec3<f32>,
+    direction_to_light_reflected: vec3<f32>,
+    shininess: f32,
+) -> vec3<f32> {
+    let x = dot(direction_to_light_reflected, direction_to_camera);
+    let radiance = pow(max(x, 0.0), shininess);
+    return color * intensity * radiance;
+}
+
+@fragment
+fn fragment_main(vertex: VertexOutput) -> @location(0) vec4<f32> {
+    let albedo = textureSample(t_albedo, s_albedo, vertex.tex_coord);
+
+    let world_normal = normalize(vertex.world_normal);
+    let direction_to_camera = normalize(camera.position - vertex.world_position);
+
+    // Without point lights the loop doesn't run, leaving the ambient and
+    // directional light.
+    var lit = vec3<f32>(0.0);
+    for (var i = 0u; i < min(lights.point_light_count, MAX_POINT_LIGHTS); i += 1u) {
+        let point_light = lights.point_lights[i];
+        let direction_to_light = normalize(point_light.position - vertex.world_position);
+
+        let diffuse = diffuse(
+            point_light.intensity,
+            albedo.rgb,
+            direction_to_light,
+            world_normal,
+        );
+
+        let r = reflect(-direction_to_light, world_normal);
+        let specular = specular(
+            point_light.intensity,
+            point_light.color * material.specular_color * material.specular_strength,
+            direction_to_camera,
+            r,
+            point_light.shininess,
+        );
+
+        lit += diffuse + specular;
+    }
+
+    let ambient = albedo.rgb * lights.ambient_color.rgb * vertex.occlusion;
+
+    let sun = diffuse(
+        lights.directional_light.intensity,
+        albedo.rgb * lights.directional_light.color,
+        -normalize(lights.directional_light.direction),
+        world_normal,
+    );
+
+    return vec4(lit + ambient + sun, albedo.a * material.opacity);
+}
@@ -0,0 +1,83 @@
+use std::borrow::Cow;
+
+use crate::{mesh::Vertex, Renderer};
+
+/// Forward pipeline for alpha blended geometry. It is drawn after the deferred
+/// lighting composite, tested against (but not writing to) the G-buffer depth.
+pub struct TransparentRenderPipeline {
+    pub pipeline: wgpu::RenderPipeline,
+}
+
+impl TransparentRenderPipeline {
+    pub fn new(
+        renderer: &Renderer,
+        uniforms_bind_group_layout: &wgpu::BindGroupLayout,
+        material_bind_group_layout: &wgpu::BindGroupLayout,
+        lights_bind_group_layout: &wgpu::BindGroupLayout,
+    ) -> Self {
+        let Renderer {
+            device,
+            surface_config,
+            ..
+        } = renderer;
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("transparent pipeline layout"),
+            bind_group_layouts: &[
+                uniforms_bind_group_layout,
+                material_bind_group_layout,
+                lights_bind_group_layout,
+            ],
+            push_constant_ranges: &[],
+        });
+
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("transparent shader module"),
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("transparent.wgsl"))),
+        });
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("transparent pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &module,
+                entry_point: "vertex_main",
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+                buffers: &[Vertex::layout()],
+            },
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Cw,
+                cull_mode: Some(wgpu::Face::Front),
+                unclipped_depth: false,
+                polygon_mode: wgpu::PolygonMode::Fill,
+                conservative: false,
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: renderer.depth_format,
+                // Transparent surfaces should not occlude each other, the draw
+                // order takes care of that.
+                depth_write_enabled: false,
+                depth_compare: renderer.depth_convention.closer(),
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            fragment: Some(wgpu::FragmentState {
+                module: &module,
+                entry_point: "fragment_main",
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: surface_config.format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            multiview: None,
+            cache: None,
+        });
+
+        Self { pipeline }
+    }
+}
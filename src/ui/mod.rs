@@ -2,7 +2,7 @@ use std::sync::Arc;
 
 use wgpu::util::DeviceExt;
 
-use crate::Renderer;
+use crate::{pass_builder::PassBuilder, upload_buffer::UploadBuffer, Renderer};
 
 mod widget;
 pub use widget::*;
@@ -22,7 +22,23 @@ pub struct UserInterface {
 
     pipeline: wgpu::RenderPipeline,
 
+    /// The vertices and indices of the meshes drawn this frame.
+    vertices: UploadBuffer,
+    indices: UploadBuffer,
+
     shapes: Vec<epaint::ClippedShape>,
+
+    /// Fade the edges of shapes out over `feathering_size` pixels, which is
+    /// what antialiases them. Turning it off gives hard edges, but fewer
+    /// vertices.
+    pub feathering: bool,
+    pub feathering_size: f32,
+    /// How far, in pixels, the line segments of tessellated curves may stray
+    /// from the curve. Lower is smoother and takes more segments.
+    pub bezier_tolerance: f32,
+    /// How small a change along a curve is treated as none, when splitting it
+    /// into segments. Lower gives more segments on large curves.
+    pub curve_epsilon: f32,
 }
 
 impl UserInterface {
@@ -155,7 +171,7 @@ impl UserInterface {
                     entry_point: "fragment_main",
                     compilation_options: wgpu::PipelineCompilationOptions::default(),
                     targets: &[Some(wgpu::ColorTargetState {
-                        format: wgpu::TextureFormat::Bgra8UnormSrgb,
+                        format: renderer.surface_config.format,
                         blend: Some(wgpu::BlendState::ALPHA_BLENDING),
                         write_mask: wgpu::ColorWrites::ALL,
                     })],
@@ -177,7 +193,15 @@ impl UserInterface {
 
             pipeline,
 
+            vertices: UploadBuffer::new(renderer, "epaint vertices", wgpu::BufferUsages::VERTEX),
+            indices: UploadBuffer::new(renderer, "epaint indices", wgpu::BufferUsages::INDEX),
+
             shapes: vec![],
+
+            feathering: true,
+            feathering_size: 1.0,
+            bezier_tolerance: 0.1,
+            curve_epsilon: 1.0e-5,
         }
     }
 
@@ -189,6 +213,11 @@ impl UserInterface {
         self.shapes.append(&mut shapes);
     }
 
+    /// Drop the shapes pushed since the last render, without drawing them.
+    pub fn clear_shapes(&mut self) {
+        self.shapes.clear();
+    }
+
     pub fn resize(&mut self, renderer: &Renderer, size: [f32; 2]) {
         renderer
             .queue
@@ -354,7 +383,13 @@ impl UserInterface {
                 .set(epaint::TextureId::default(), font_image_delta);
         }
 
-        let tessellation_options = epaint::TessellationOptions::default();
+        let tessellation_options = epaint::TessellationOptions {
+            feathering: self.feathering,
+            feathering_size_in_pixels: self.feathering_size,
+            bezier_tolerance: self.bezier_tolerance,
+            epsilon: self.curve_epsilon,
+            ..Default::default()
+        };
         let texture_atlas = self.fonts.texture_atlas();
         let (font_tex_size, prepared_discs) = {
             let atlas = texture_atlas.lock();
@@ -367,50 +402,78 @@ impl UserInterface {
         let shapes = std::mem::take(&mut self.shapes);
         let primitives = tessellator.tessellate_shapes(shapes);
 
+        // Gather all the meshes and upload them with a single write.
+        self.vertices.clear();
+        self.indices.clear();
+        let meshes = primitives
+            .into_iter()
+            .filter_map(|primitive| match primitive.primitive {
+                // Empty buffer slices are not allowed.
+                epaint::Primitive::Mesh(mesh) if mesh.is_empty() => None,
+                epaint::Primitive::Mesh(mesh) => Some((
+                    primitive.clip_rect,
+                    mesh.texture_id,
+                    self.vertices.push(&mesh.vertices),
+                    self.indices.push(&mesh.indices),
+                    mesh.indices.len() as u32,
+                )),
+                epaint::Primitive::Callback(..) => todo!(),
+            })
+            .collect::<Vec<_>>();
+        self.vertices.upload(renderer);
+        self.indices.upload(renderer);
+
         let texture_deltas = self.texture_manager.take_delta();
 
         for (texture_id, image_delta) in texture_deltas.set {
             self.update_texture(renderer, texture_id, image_delta);
         }
 
-        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-            label: Some("epaint render pass"),
-            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                view,
-                resolve_target: None,
-                ops: wgpu::Operations {
-                    load: wgpu::LoadOp::Load,
-                    store: wgpu::StoreOp::Store,
-                },
-            })],
-            depth_stencil_attachment: None,
-            timestamp_writes: None,
-            occlusion_query_set: None,
-        });
-
-        for primitive in primitives.into_iter() {
-            match primitive.primitive {
-                epaint::Primitive::Mesh(mesh) => {
-                    let (_, texture_bind_group) = self
-                        .textures
-                        .get(&mesh.texture_id)
-                        .expect("texture not uploaded");
-
-                    let buffers = crate::mesh::Mesh::from(mesh).upload_to_gpu(renderer);
-
-                    render_pass.set_pipeline(&self.pipeline);
-                    render_pass.set_vertex_buffer(0, buffers.vertex_buffer.slice(..));
-                    render_pass.set_index_buffer(
-                        buffers.index_buffer.slice(..),
-                        wgpu::IndexFormat::Uint16,
-                    );
-                    render_pass.set_bind_group(0, &self.screen_size_bind_group, &[]);
-                    render_pass.set_bind_group(1, texture_bind_group, &[]);
-                    render_pass.draw_indexed(0..buffers.index_count, 0, 0..1);
-                }
+        let mut render_pass = PassBuilder::composite("epaint render pass", view).begin(encoder);
 
-                epaint::Primitive::Callback(..) => todo!(),
-            }
+        let target_size = epaint::vec2(
+            renderer.surface_config.width as f32,
+            renderer.surface_config.height as f32,
+        );
+
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.screen_size_bind_group, &[]);
+        for (clip_rect, texture_id, vertices, indices, index_count) in meshes {
+            // Only draw inside the clip rect of the shape, so widgets can be
+            // kept inside the panel they belong to.
+            let Some([x, y, width, height]) = scissor_rect(clip_rect, target_size) else {
+                continue;
+            };
+            render_pass.set_scissor_rect(x, y, width, height);
+
+            let (_, texture_bind_group) = self
+                .textures
+                .get(&texture_id)
+                .expect("texture not uploaded");
+
+            render_pass.set_vertex_buffer(0, self.vertices.slice(vertices));
+            render_pass.set_index_buffer(self.indices.slice(indices), wgpu::IndexFormat::Uint32);
+            render_pass.set_bind_group(1, texture_bind_group, &[]);
+            render_pass.draw_indexed(0..index_count, 0, 0..1);
         }
     }
 }
+
+/// The pixels of a target of `target_size` covered by `clip_rect`, as
+/// `[x, y, width, height]`. `None` if nothing is covered.
+fn scissor_rect(clip_rect: epaint::Rect, target_size: epaint::Vec2) -> Option<[u32; 4]> {
+    let clip_rect =
+        clip_rect.intersect(epaint::Rect::from_min_size(epaint::Pos2::ZERO, target_size));
+    let min = clip_rect.min.round();
+    let max = clip_rect.max.round();
+    if max.x <= min.x || max.y <= min.y {
+        return None;
+    }
+
+    Some([
+        min.x as u32,
+        min.y as u32,
+        (max.x - min.x) as u32,
+        (max.y - min.y) as u32,
+    ])
+}
@@ -14,6 +14,38 @@ pub trait Widget {
     fn shapes(&self) -> Vec<epaint::ClippedShape>;
 }
 
+/// A filled rectangle with its corners rounded by `rounding`. The rounding is
+/// limited to half the shortest side, so a large value gives a pill shape.
+pub fn rounded_rect(
+    rect: epaint::Rect,
+    rounding: impl Into<epaint::Rounding>,
+    fill: epaint::Color32,
+) -> epaint::ClippedShape {
+    epaint::ClippedShape {
+        clip_rect: epaint::Rect::EVERYTHING,
+        shape: epaint::Shape::rect_filled(rect, rounding, fill),
+    }
+}
+
+/// A filled circle with an outline. Use [`epaint::Stroke::NONE`] for no
+/// outline.
+pub fn circle(
+    center: epaint::Pos2,
+    radius: f32,
+    fill: epaint::Color32,
+    stroke: impl Into<epaint::Stroke>,
+) -> epaint::ClippedShape {
+    epaint::ClippedShape {
+        clip_rect: epaint::Rect::EVERYTHING,
+        shape: epaint::Shape::Circle(epaint::CircleShape {
+            center,
+            radius,
+            fill,
+            stroke: stroke.into(),
+        }),
+    }
+}
+
 #[derive(Clone, Copy, Debug, Default, PartialEq)]
 struct NodeId(usize);
 
@@ -101,16 +133,103 @@ impl Widget for Label {
     }
 }
 
+/// A filled background behind a group of widgets. Widgets can use
+/// [`Panel::clip_rect`] to be cut off at the edge of the panel, and an opaque
+/// fill stops the scene from showing through translucent widgets.
+pub struct Panel {
+    pub bounds: epaint::Rect,
+    fill: epaint::Color32,
+    rounding: epaint::Rounding,
+    /// Space between the bounds of the widgets and the edge of the panel.
+    padding: f32,
+}
+
+impl Panel {
+    /// Hides the scene behind the panel completely.
+    pub const SOLID: epaint::Color32 = epaint::Color32::from_gray(24);
+    /// Darkens the scene behind the panel, but lets it show through.
+    pub const TRANSLUCENT: epaint::Color32 = epaint::Color32::from_black_alpha(160);
+
+    pub fn new(fill: epaint::Color32) -> Self {
+        Self {
+            bounds: epaint::Rect::NOTHING,
+            fill,
+            rounding: epaint::Rounding::ZERO,
+            padding: 0.0,
+        }
+    }
+
+    pub fn with_rounding(mut self, rounding: impl Into<epaint::Rounding>) -> Self {
+        self.rounding = rounding.into();
+        self
+    }
+
+    pub fn with_padding(mut self, padding: f32) -> Self {
+        self.padding = padding;
+        self
+    }
+
+    pub fn set_fill(&mut self, fill: epaint::Color32) {
+        self.fill = fill;
+    }
+
+    /// Size the panel to hold widgets with the given bounds, plus padding.
+    pub fn fit(&mut self, widget_bounds: impl IntoIterator<Item = epaint::Rect>) {
+        self.bounds = widget_bounds
+            .into_iter()
+            .fold(epaint::Rect::NOTHING, |bounds, rect| bounds.union(rect))
+            .expand(self.padding);
+    }
+
+    /// The area widgets in the panel are clipped to.
+    pub fn clip_rect(&self) -> epaint::Rect {
+        self.bounds
+    }
+}
+
+impl Widget for Panel {
+    fn layout(&mut self, bounds: epaint::Rect) {
+        self.bounds = bounds;
+    }
+
+    fn bounds(&self) -> epaint::Rect {
+        self.bounds
+    }
+
+    fn shapes(&self) -> Vec<epaint::ClippedShape> {
+        if !self.bounds.is_positive() || self.fill == epaint::Color32::TRANSPARENT {
+            return vec![];
+        }
+
+        vec![rounded_rect(self.bounds, self.rounding, self.fill)]
+    }
+}
+
 slotmap::new_key_type! {
     pub struct SliderId;
 }
 
+/// The direction a [`Slider`] moves in. Values increase to the right, or up.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Orientation {
+    #[default]
+    Horizontal,
+    Vertical,
+}
+
 pub struct Slider {
     fonts: Arc<epaint::Fonts>,
     pub bounds: epaint::Rect,
     label_galley: Arc<epaint::Galley>,
     color: epaint::Color32,
+    orientation: Orientation,
+    /// How round the corners of the slide are.
+    rounding: epaint::Rounding,
+    /// Map positions on the slide to values on a logarithmic curve.
+    log_scale: bool,
     value: f32,
+    /// The value the slider is reset to on a double-click.
+    default_value: f32,
     min: f32,
     max: f32,
 
@@ -130,22 +249,58 @@ impl Slider {
             bounds: epaint::Rect::ZERO,
             label_galley,
             color: epaint::Color32::LIGHT_GREEN,
+            orientation: Orientation::Horizontal,
+            rounding: epaint::Rounding::same(4.0),
+            log_scale: false,
             value: 0.5,
+            default_value: 0.5,
             min: 0.0,
             max: 1.0,
             slide_bounds: epaint::Rect::ZERO,
         }
     }
 
+    pub fn with_orientation(mut self, orientation: Orientation) -> Self {
+        self.orientation = orientation;
+        self
+    }
+
+    pub fn orientation(&self) -> Orientation {
+        self.orientation
+    }
+
+    pub fn with_rounding(mut self, rounding: impl Into<epaint::Rounding>) -> Self {
+        self.rounding = rounding.into();
+        self
+    }
+
+    /// Use a logarithmic scale, which gives finer control at the low end of
+    /// ranges that span orders of magnitude. Only applies when the range is
+    /// positive.
+    pub fn with_log_scale(mut self, log_scale: bool) -> Self {
+        self.log_scale = log_scale;
+        self
+    }
+
+    /// Set the range of the slider. The bounds are swapped if `min` is larger
+    /// than `max`, and the range is left as it was if either is NaN.
     pub fn with_min_max(mut self, min: f32, max: f32) -> Self {
+        if min.is_nan() || max.is_nan() {
+            return self;
+        }
+
+        let (min, max) = if min <= max { (min, max) } else { (max, min) };
         self.min = min;
         self.max = max;
         self.value = self.value.clamp(min, max);
+        self.default_value = self.default_value.clamp(min, max);
         self
     }
 
+    /// Set the value of the slider, which is also used as its default value.
     pub fn with_value(mut self, value: f32) -> Self {
         self.set_value(value);
+        self.default_value = self.value;
         self
     }
 
@@ -153,12 +308,16 @@ impl Slider {
         self.value
     }
 
+    /// Set the value, clamped to the range of the slider. NaN is ignored.
     pub fn set_value(&mut self, value: f32) {
-        self.value = value.clamp(self.min, self.max);
+        if !value.is_nan() {
+            self.value = value.clamp(self.min, self.max);
+        }
     }
 
     pub fn shapes(&mut self) -> Vec<epaint::ClippedShape> {
         const PADDING: f32 = 10.0;
+        const KNOB_RADIUS: f32 = 10.0;
         // const SLIDE_THICKNESS: f32 = 20.0;
 
         // let background_shape = epaint::ClippedShape {
@@ -170,10 +329,40 @@ impl Slider {
         //     ),
         // };
 
-        let v_center = self.bounds.height() / 2.0;
+        let label_size = self.label_galley.rect.size();
+        let label_position = match self.orientation {
+            Orientation::Horizontal => {
+                let v_center = self.bounds.height() / 2.0;
+
+                self.slide_bounds = epaint::Rect {
+                    min: epaint::pos2(
+                        self.bounds.min.x + PADDING * 2.0 + label_size.x,
+                        self.bounds.min.y + v_center - label_size.y / 2.0,
+                    ),
+                    max: epaint::pos2(
+                        self.bounds.max.x - PADDING,
+                        self.bounds.min.y + v_center + label_size.y / 2.0,
+                    ),
+                };
+
+                self.bounds.min + epaint::vec2(PADDING, v_center - label_size.y / 2.0)
+            }
+            Orientation::Vertical => {
+                // The label sits on top of the slide.
+                let h_center = self.bounds.width() / 2.0;
+
+                self.slide_bounds = epaint::Rect {
+                    min: epaint::pos2(
+                        self.bounds.min.x + PADDING,
+                        self.bounds.min.y + PADDING * 2.0 + label_size.y,
+                    ),
+                    max: epaint::pos2(self.bounds.max.x - PADDING, self.bounds.max.y - PADDING),
+                };
+
+                self.bounds.min + epaint::vec2(h_center - label_size.x / 2.0, PADDING)
+            }
+        };
 
-        let label_position = self.bounds.min
-            + epaint::vec2(PADDING, v_center - self.label_galley.rect.height() / 2.0);
         let label_shape = epaint::ClippedShape {
             clip_rect: epaint::Rect::EVERYTHING,
             shape: epaint::Shape::galley(
@@ -183,46 +372,54 @@ impl Slider {
             ),
         };
 
-        self.slide_bounds = epaint::Rect {
-            min: epaint::pos2(
-                self.bounds.min.x + PADDING * 2.0 + self.label_galley.rect.width(),
-                self.bounds.min.y + v_center - self.label_galley.rect.height() / 2.0,
-            ),
-            max: epaint::pos2(
-                self.bounds.max.x - PADDING,
-                self.bounds.min.y + v_center + self.label_galley.rect.height() / 2.0,
-            ),
+        let slide = rounded_rect(self.slide_bounds, self.rounding, epaint::Color32::DARK_GRAY);
+
+        let t = self.value_to_t(self.value);
+        let progress_bounds = match self.orientation {
+            Orientation::Horizontal => epaint::Rect {
+                min: self.slide_bounds.min,
+                max: epaint::pos2(
+                    self.slide_bounds.min.x + (self.slide_bounds.width() * t),
+                    self.slide_bounds.max.y,
+                ),
+            },
+            Orientation::Vertical => epaint::Rect {
+                min: epaint::pos2(
+                    self.slide_bounds.min.x,
+                    self.slide_bounds.max.y - (self.slide_bounds.height() * t),
+                ),
+                max: self.slide_bounds.max,
+            },
         };
 
-        let slide = epaint::ClippedShape {
-            clip_rect: epaint::Rect::EVERYTHING,
-            shape: epaint::Shape::rect_filled(
-                self.slide_bounds,
-                epaint::Rounding::ZERO,
-                epaint::Color32::DARK_GRAY,
-            ),
-        };
+        let progress = rounded_rect(progress_bounds, self.rounding, epaint::Color32::LIGHT_GREEN);
 
-        let t = (self.value - self.min) / (self.max - self.min);
-        let progress_bounds = epaint::Rect {
-            min: self.slide_bounds.min,
-            max: epaint::pos2(
-                self.slide_bounds.min.x + (self.slide_bounds.width() * t),
-                self.slide_bounds.max.y,
+        // The knob sits on the end of the progress bar, and sticks out of the
+        // slide a little.
+        let (knob_center, knob_radius) = match self.orientation {
+            Orientation::Horizontal => (
+                epaint::pos2(progress_bounds.max.x, self.slide_bounds.center().y),
+                self.slide_bounds.height() / 2.0 + 2.0,
             ),
-        };
-
-        let progress = epaint::ClippedShape {
-            clip_rect: epaint::Rect::EVERYTHING,
-            shape: epaint::Shape::rect_filled(
-                progress_bounds,
-                epaint::Rounding::ZERO,
-                epaint::Color32::LIGHT_GREEN,
+            Orientation::Vertical => (
+                epaint::pos2(self.slide_bounds.center().x, progress_bounds.min.y),
+                KNOB_RADIUS,
             ),
         };
+        let knob = circle(
+            knob_center,
+            knob_radius.min(KNOB_RADIUS),
+            epaint::Color32::WHITE,
+            epaint::Stroke::new(1.0, epaint::Color32::DARK_GREEN),
+        );
 
+        let value_text = match self.orientation {
+            Orientation::Horizontal => format!("{}", self.value),
+            // Vertical slides are narrow, so keep the value short.
+            Orientation::Vertical => format!("{:.3}", self.value),
+        };
         let progress_label = self.fonts.layout_no_wrap(
-            format!("{}", self.value),
+            value_text,
             epaint::FontId::monospace(14.0),
             epaint::Color32::BLACK,
         );
@@ -241,13 +438,14 @@ impl Slider {
             label_shape,
             slide,
             progress,
+            knob,
             progress_label,
         ]
     }
 
     pub fn on_mouse_down(&mut self, x: f32, y: f32) -> bool {
         if self.slide_bounds.contains(epaint::pos2(x, y)) {
-            self.update_value(x);
+            self.update_value(x, y);
             return true;
         }
 
@@ -256,24 +454,129 @@ impl Slider {
 
     pub fn on_mouse_up(&mut self) {}
 
-    pub fn on_mouse_dragged(&mut self, x: f32, _y: f32) {
-        if x < self.slide_bounds.min.x {
-            self.value = self.min;
-            return;
+    /// Activate the slider from the keyboard, which resets it to its default
+    /// value.
+    pub fn on_activate(&mut self) {
+        self.value = self.default_value;
+    }
+
+    /// Reset the slider to its default value when double-clicking the slide.
+    pub fn on_double_click(&mut self, x: f32, y: f32) -> bool {
+        if self.slide_bounds.contains(epaint::pos2(x, y)) {
+            self.value = self.default_value;
+            return true;
         }
 
-        if x > self.slide_bounds.max.x {
-            self.value = self.max;
+        false
+    }
+
+    pub fn on_mouse_dragged(&mut self, x: f32, y: f32) {
+        self.update_value(x, y);
+    }
+
+    fn update_value(&mut self, x: f32, y: f32) {
+        let (offset, length) = match self.orientation {
+            Orientation::Horizontal => (x - self.slide_bounds.min.x, self.slide_bounds.width()),
+            Orientation::Vertical => (self.slide_bounds.max.y - y, self.slide_bounds.height()),
+        };
+        // The slide has no size until the slider is laid out.
+        if length <= 0.0 || offset.is_nan() {
             return;
         }
 
-        self.update_value(x);
+        self.set_value(self.t_to_value((offset / length).clamp(0.0, 1.0)));
+    }
+
+    fn uses_log_scale(&self) -> bool {
+        self.log_scale && self.min > 0.0 && self.max > self.min
+    }
+
+    /// Where `value` is on the slide, from 0.0 to 1.0.
+    fn value_to_t(&self, value: f32) -> f32 {
+        if self.max <= self.min {
+            return 0.0;
+        }
+
+        let t = if self.uses_log_scale() {
+            (value / self.min).ln() / (self.max / self.min).ln()
+        } else {
+            (value - self.min) / (self.max - self.min)
+        };
+        t.clamp(0.0, 1.0)
     }
 
-    fn update_value(&mut self, x: f32) {
-        let t = (x - self.slide_bounds.min.x) / self.slide_bounds.width();
-        self.value = self.min + (self.max - self.min) * t;
+    /// The value at `t` along the slide, from 0.0 to 1.0.
+    fn t_to_value(&self, t: f32) -> f32 {
+        if self.uses_log_scale() {
+            self.min * (self.max / self.min).powf(t)
+        } else {
+            self.min + (self.max - self.min) * t
+        }
     }
 
     pub fn on_mouse_moved(&mut self, _x: f32, _y: f32) {}
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn slider() -> Slider {
+        let fonts = epaint::Fonts::new(1.0, 1024, epaint::text::FontDefinitions::default());
+        Slider::new(Arc::new(fonts), "test")
+    }
+
+    #[test]
+    fn inverted_range_is_swapped() {
+        let slider = slider().with_min_max(5.0, -5.0).with_value(10.0);
+        assert_eq!((slider.min, slider.max), (-5.0, 5.0));
+        assert_eq!(slider.value(), 5.0);
+    }
+
+    #[test]
+    fn nan_is_ignored() {
+        let mut slider = slider().with_min_max(f32::NAN, 10.0).with_value(0.25);
+        assert_eq!((slider.min, slider.max), (0.0, 1.0));
+
+        slider.set_value(f32::NAN);
+        assert_eq!(slider.value(), 0.25);
+    }
+
+    #[test]
+    fn dragging_before_layout_keeps_the_value() {
+        let mut slider = slider().with_value(0.25);
+        // The slide has no size yet.
+        slider.on_mouse_dragged(0.0, 0.0);
+        assert_eq!(slider.value(), 0.25);
+
+        let mut slider = slider.with_orientation(Orientation::Vertical);
+        slider.on_mouse_dragged(0.0, 0.0);
+        assert_eq!(slider.value(), 0.25);
+    }
+
+    #[test]
+    fn empty_range_is_at_the_start() {
+        let slider = slider().with_min_max(2.0, 2.0);
+        assert_eq!(slider.value_to_t(2.0), 0.0);
+        assert_eq!(slider.t_to_value(1.0), 2.0);
+    }
+
+    #[test]
+    fn log_scale_round_trips() {
+        let slider = slider().with_log_scale(true).with_min_max(0.1, 100.0);
+        for step in 0..=10 {
+            let t = step as f32 / 10.0;
+            let value = slider.t_to_value(t);
+            assert!((slider.value_to_t(value) - t).abs() < 1e-5, "t = {t}");
+        }
+        // Halfway is the geometric mean of the range.
+        assert!((slider.t_to_value(0.5) - 10.0_f32.sqrt()).abs() < 1e-4);
+    }
+
+    #[test]
+    fn log_scale_needs_a_positive_range() {
+        let slider = slider().with_log_scale(true).with_min_max(-1.0, 1.0);
+        assert_eq!(slider.t_to_value(0.5), 0.0);
+        assert_eq!(slider.value_to_t(0.0), 0.5);
+    }
+}
@@ -0,0 +1,85 @@
+use std::ops::Range;
+
+use crate::{allocations::Allocation, Renderer};
+
+/// A GPU buffer for data that changes every frame, like UI meshes. Data is
+/// gathered on the CPU with [UploadBuffer::push] and copied over with a single
+/// [UploadBuffer::upload], so nothing is allocated per draw. The buffer only
+/// grows, so after the first few frames it isn't reallocated either.
+pub struct UploadBuffer {
+    label: &'static str,
+    usage: wgpu::BufferUsages,
+    buffer: wgpu::Buffer,
+    allocation: Allocation,
+    /// The data pushed since the last [UploadBuffer::clear].
+    data: Vec<u8>,
+}
+
+impl UploadBuffer {
+    /// The size of the buffer before anything is pushed.
+    const INITIAL_SIZE: wgpu::BufferAddress = 64 * 1024;
+
+    pub fn new(renderer: &Renderer, label: &'static str, usage: wgpu::BufferUsages) -> Self {
+        let usage = usage | wgpu::BufferUsages::COPY_DST;
+        let buffer = create_buffer(renderer, label, usage, Self::INITIAL_SIZE);
+        Self {
+            label,
+            usage,
+            allocation: renderer.allocations.track_buffer(&buffer),
+            buffer,
+            data: Vec::with_capacity(Self::INITIAL_SIZE as usize),
+        }
+    }
+
+    /// Drop the data of the previous frame.
+    pub fn clear(&mut self) {
+        self.data.clear();
+    }
+
+    /// Add `items` to the data for this frame and return where they will be
+    /// in the buffer. Each push starts at an offset aligned for vertex and
+    /// index data.
+    pub fn push<T: bytemuck::NoUninit>(&mut self, items: &[T]) -> Range<wgpu::BufferAddress> {
+        let start = self.data.len();
+        self.data.extend_from_slice(bytemuck::cast_slice(items));
+        let end = self.data.len();
+
+        let aligned_len = end.next_multiple_of(wgpu::COPY_BUFFER_ALIGNMENT as usize);
+        self.data.resize(aligned_len, 0);
+
+        start as wgpu::BufferAddress..end as wgpu::BufferAddress
+    }
+
+    /// Copy everything pushed since the last [UploadBuffer::clear] to the GPU,
+    /// growing the buffer if it is too small. Ranges returned by
+    /// [UploadBuffer::push] can be used after this.
+    pub fn upload(&mut self, renderer: &Renderer) {
+        let size = self.data.len() as wgpu::BufferAddress;
+        if size > self.buffer.size() {
+            self.buffer = create_buffer(renderer, self.label, self.usage, size.next_power_of_two());
+            self.allocation = renderer.allocations.track_buffer(&self.buffer);
+        }
+
+        if !self.data.is_empty() {
+            renderer.queue.write_buffer(&self.buffer, 0, &self.data);
+        }
+    }
+
+    pub fn slice(&self, range: Range<wgpu::BufferAddress>) -> wgpu::BufferSlice<'_> {
+        self.buffer.slice(range)
+    }
+}
+
+fn create_buffer(
+    renderer: &Renderer,
+    label: &str,
+    usage: wgpu::BufferUsages,
+    size: wgpu::BufferAddress,
+) -> wgpu::Buffer {
+    renderer.device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some(label),
+        size,
+        usage,
+        mapped_at_creation: false,
+    })
+}
@@ -0,0 +1,198 @@
+use wgpu::util::DeviceExt;
+
+use crate::{
+    allocations::Allocation,
+    camera::Camera,
+    mesh::{Mesh, Vertex},
+    pass_builder::PassBuilder,
+    Renderer,
+};
+
+#[derive(Clone, Copy, bytemuck::NoUninit)]
+#[repr(C)]
+struct GpuSettings {
+    color: [f32; 4],
+    thickness: f32,
+    _padding: [f32; 3],
+}
+
+/// The triangles of a mesh without shared vertices, which lets the wireframe
+/// shader tell the corners of each triangle apart by their vertex index.
+/// Only the positions are kept.
+pub struct WireframeMesh {
+    vertex_buffer: wgpu::Buffer,
+    vertex_count: u32,
+    /// Untracked when the mesh is dropped.
+    _allocation: Allocation,
+}
+
+impl WireframeMesh {
+    pub fn new(renderer: &Renderer, mesh: &Mesh<Vertex>) -> Self {
+        let positions = mesh
+            .triangles()
+            .flat_map(|triangle| triangle.map(|vertex| vertex.position))
+            .collect::<Vec<_>>();
+
+        let vertex_buffer = renderer
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("wireframe vertex buffer"),
+                contents: bytemuck::cast_slice(&positions),
+                usage: wgpu::BufferUsages::VERTEX,
+            });
+
+        Self {
+            _allocation: renderer.allocations.track_buffer(&vertex_buffer),
+            vertex_buffer,
+            vertex_count: positions.len() as u32,
+        }
+    }
+}
+
+/// Draws the edges of the triangles of meshes over an image of the same
+/// meshes, like the lit scene. The edges are found from barycentric
+/// coordinates in the fragment shader, so this needs neither a geometry shader
+/// nor line polygon mode, and the lines are antialiased.
+pub struct WireframeOverlay {
+    pipeline: wgpu::RenderPipeline,
+    settings_buffer: wgpu::Buffer,
+    settings_bind_group: wgpu::BindGroup,
+
+    pub color: [f32; 4],
+    /// The width of the lines in pixels.
+    pub thickness: f32,
+}
+
+impl WireframeOverlay {
+    pub fn new(renderer: &Renderer, camera: &Camera, format: wgpu::TextureFormat) -> Self {
+        let Renderer { device, .. } = renderer;
+
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("wireframe shader module"),
+            source: wgpu::ShaderSource::Wgsl(std::borrow::Cow::Borrowed(include_str!(
+                "wireframe.wgsl"
+            ))),
+        });
+
+        let settings_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("wireframe settings bind group layout"),
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }],
+            });
+
+        let settings_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("wireframe settings buffer"),
+            size: std::mem::size_of::<GpuSettings>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let settings_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("wireframe settings bind group"),
+            layout: &settings_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: settings_buffer.as_entire_binding(),
+            }],
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("wireframe pipeline layout"),
+            bind_group_layouts: &[&camera.bind_group_layout, &settings_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("wireframe render pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &module,
+                entry_point: "vertex_main",
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+                buffers: &[wgpu::VertexBufferLayout {
+                    array_stride: std::mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
+                    step_mode: wgpu::VertexStepMode::Vertex,
+                    attributes: &wgpu::vertex_attr_array![0 => Float32x3],
+                }],
+            },
+            // Back faces are hidden by the depth test, and drawing them keeps
+            // the lines of meshes with either winding.
+            primitive: wgpu::PrimitiveState::default(),
+            // The triangles are at the same depth as in the G-buffer, so they
+            // pass an "equal" test, and there is no need to write depth.
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: renderer.depth_format,
+                depth_write_enabled: false,
+                depth_compare: renderer.depth_convention.closer_or_equal(),
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            fragment: Some(wgpu::FragmentState {
+                module: &module,
+                entry_point: "fragment_main",
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::COLOR,
+                })],
+            }),
+            multiview: None,
+            cache: None,
+        });
+
+        Self {
+            pipeline,
+            settings_buffer,
+            settings_bind_group,
+            color: [0.0, 0.0, 0.0, 1.0],
+            thickness: 1.0,
+        }
+    }
+
+    /// Draw the edges of `meshes` over `target`, hidden where `depth_view`
+    /// has something in front of them.
+    pub fn render<'a>(
+        &self,
+        renderer: &Renderer,
+        encoder: &mut wgpu::CommandEncoder,
+        target: &wgpu::TextureView,
+        depth_view: &wgpu::TextureView,
+        camera: &Camera,
+        meshes: impl Iterator<Item = &'a WireframeMesh>,
+    ) {
+        renderer.queue.write_buffer(
+            &self.settings_buffer,
+            0,
+            bytemuck::cast_slice(&[GpuSettings {
+                color: self.color,
+                thickness: self.thickness.max(0.0),
+                _padding: [0.0; 3],
+            }]),
+        );
+
+        let mut render_pass =
+            PassBuilder::overlay("wireframe render pass", target, depth_view).begin(encoder);
+
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &camera.bind_group, &[]);
+        render_pass.set_bind_group(1, &self.settings_bind_group, &[]);
+        for mesh in meshes {
+            if mesh.vertex_count == 0 {
+                continue;
+            }
+            render_pass.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+            render_pass.draw(0..mesh.vertex_count, 0..1);
+        }
+    }
+}
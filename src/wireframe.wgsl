@@ -0,0 +1,57 @@
+struct Camera {
+    projection_matrix: mat4x4<f32>,
+    view_matrix: mat4x4<f32>,
+    position: vec3<f32>,
+}
+@group(0) @binding(0) var<uniform> camera: Camera;
+
+struct Settings {
+    color: vec4<f32>,
+    // The width of the lines in pixels.
+    thickness: f32,
+}
+@group(1) @binding(0) var<uniform> settings: Settings;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    // 1.0 at one corner of the triangle, fading to 0.0 at the opposite edge,
+    // for each of the corners.
+    @location(0) barycentric: vec3<f32>,
+}
+
+@vertex
+fn vertex_main(
+    @builtin(vertex_index) vertex_index: u32,
+    @location(0) position: vec3<f32>,
+) -> VertexOutput {
+    // Every triangle has its own three vertices, so the index tells which
+    // corner this is.
+    let corner = vertex_index % 3u;
+    let barycentric = vec3<f32>(
+        f32(corner == 0u),
+        f32(corner == 1u),
+        f32(corner == 2u),
+    );
+
+    // The same transform as the G-buffer pass, so the depths match exactly.
+    let world_position = vec4(position, 1.0);
+    let clip_position = camera.projection_matrix * camera.view_matrix * world_position;
+
+    return VertexOutput(clip_position, barycentric);
+}
+
+@fragment
+fn fragment_main(vertex: VertexOutput) -> @location(0) vec4<f32> {
+    // How much the barycentric coordinates change over a pixel, so the lines
+    // are the same width in pixels wherever the triangle is.
+    let pixel = fwidth(vertex.barycentric);
+    let distance = vertex.barycentric / max(pixel, vec3(0.00001));
+    let closest_edge = min(min(distance.x, distance.y), distance.z);
+    let edge = 1.0 - smoothstep(settings.thickness * 0.5, settings.thickness * 0.5 + 1.0, closest_edge);
+
+    if edge <= 0.0 {
+        discard;
+    }
+
+    return vec4(settings.color.rgb, settings.color.a * edge);
+}